@@ -0,0 +1,333 @@
+use std::{
+    net::SocketAddr,
+    pin::Pin,
+    str::FromStr,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+    task::{Context as TaskContext, Poll},
+    time::SystemTime,
+};
+
+use anyhow::{anyhow, Context, Result};
+use bytes::{Buf, BytesMut};
+use clap::ValueEnum;
+use futures::{stream, Stream, StreamExt};
+use tokio::{
+    io::{AsyncRead, ReadBuf},
+    net::{TcpStream, UdpSocket},
+};
+use tokio_util::codec::{Decoder, FramedRead};
+
+use mavlink_codec::{
+    codec::MavlinkCodec, error::DecoderError, pcap::PcapReader, tlog::TlogReader, Packet,
+};
+
+use crate::CodecArgs;
+
+pub type DecodeResult = Result<Packet, DecoderError>;
+/// Records read ahead of the consumer of a pcap input
+const PCAP_CHANNEL_CAPACITY: usize = 64;
+
+/// Decoded packets, with their capture timestamp when the input format has one
+pub type PacketStream =
+    Pin<Box<dyn Stream<Item = std::io::Result<(Option<SystemTime>, DecodeResult)>> + Send>>;
+
+pub struct Source {
+    pub packets: PacketStream,
+    /// Total of bytes read from the input, when it can be accounted for
+    pub bytes_read: Option<Arc<AtomicU64>>,
+    /// Bytes used by the input format around each packet
+    pub record_overhead: usize,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Input {
+    Stdin,
+    File(String),
+    Udp(SocketAddr),
+    Tcp(SocketAddr),
+}
+
+impl FromStr for Input {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s == "-" {
+            return Ok(Self::Stdin);
+        }
+
+        if let Some(address) = s.strip_prefix("udp://") {
+            return Ok(Self::Udp(address.parse().context("Invalid UDP address")?));
+        }
+
+        if let Some(address) = s.strip_prefix("tcp://") {
+            return Ok(Self::Tcp(address.parse().context("Invalid TCP address")?));
+        }
+
+        Ok(Self::File(s.to_string()))
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum InputFormat {
+    /// A raw MAVLink byte stream
+    Raw,
+    /// A telemetry log, with timestamped packets
+    Tlog,
+    /// A pcap or pcapng capture of MAVLink over UDP
+    Pcap,
+}
+
+type DynDecoder =
+    Box<dyn Decoder<Item = Result<Packet, DecoderError>, Error = std::io::Error> + Send>;
+
+/// Monomorphizes [`MavlinkCodec`] for each combination of the runtime options
+macro_rules! select_codec {
+    ([$($selected:tt),*] []) => {
+        Box::new(MavlinkCodec::<$($selected),*>::default())
+    };
+    ([$($selected:tt),*] [$option:expr $(, $options:expr)*]) => {
+        if $option {
+            select_codec!([$($selected,)* true] [$($options),*])
+        } else {
+            select_codec!([$($selected,)* false] [$($options),*])
+        }
+    };
+}
+/// Wraps the codec selected at runtime
+struct DynCodec {
+    options: CodecArgs,
+    decoder: DynDecoder,
+}
+
+impl DynCodec {
+    fn new(options: CodecArgs) -> Self {
+        Self {
+            options,
+            decoder: select_codec!(
+                []
+                [
+                    !options.no_v1,
+                    !options.no_v2,
+                    options.drop_invalid_sysid,
+                    options.drop_invalid_compid,
+                    options.skip_crc_validation,
+                    options.drop_incompatible,
+                    options.validate_payload_length
+                ]
+            ),
+        }
+    }
+
+    fn reset(&mut self) {
+        *self = Self::new(self.options);
+    }
+}
+
+impl Clone for DynCodec {
+    fn clone(&self) -> Self {
+        Self::new(self.options)
+    }
+}
+
+impl Decoder for DynCodec {
+    type Item = Result<Packet, DecoderError>;
+    type Error = std::io::Error;
+
+    fn decode(&mut self, buf: &mut BytesMut) -> Result<Option<Self::Item>, Self::Error> {
+        self.decoder.decode(buf)
+    }
+
+    fn decode_eof(&mut self, buf: &mut BytesMut) -> Result<Option<Self::Item>, Self::Error> {
+        // A truncated packet might be hiding valid ones, so keep resyncing until the buffer is drained
+        loop {
+            match self.decode(buf)? {
+                Some(item) => return Ok(Some(item)),
+                None if buf.is_empty() => return Ok(None),
+                None => {
+                    self.reset();
+                    buf.advance(1);
+                }
+            }
+        }
+    }
+}
+
+pub async fn open(input: &Input, format: InputFormat, options: CodecArgs) -> Result<Source> {
+    let bytes_read = Arc::new(AtomicU64::new(0));
+
+    let packets = match (input, format) {
+        (Input::Stdin, InputFormat::Raw) => raw(tokio::io::stdin(), options, &bytes_read),
+        (Input::File(path), InputFormat::Raw) => {
+            let file = tokio::fs::File::open(path)
+                .await
+                .with_context(|| format!("Failed to open {path:?}"))?;
+            raw(file, options, &bytes_read)
+        }
+        (Input::Tcp(address), InputFormat::Raw) => {
+            let stream = TcpStream::connect(address)
+                .await
+                .with_context(|| format!("Failed to connect to {address}"))?;
+            raw(stream, options, &bytes_read)
+        }
+        (Input::Udp(address), InputFormat::Raw) => {
+            let socket = UdpSocket::bind(address)
+                .await
+                .with_context(|| format!("Failed to bind to {address}"))?;
+            udp(socket, options, &bytes_read)
+        }
+        (Input::File(path), InputFormat::Tlog) => {
+            let file = tokio::fs::File::open(path)
+                .await
+                .with_context(|| format!("Failed to open {path:?}"))?;
+            tlog(file, options, &bytes_read)
+        }
+        (Input::Stdin, InputFormat::Tlog) => tlog(tokio::io::stdin(), options, &bytes_read),
+        (Input::File(path), InputFormat::Pcap) => {
+            return Ok(Source {
+                packets: pcap(path, options).await?,
+                bytes_read: None,
+                record_overhead: 0,
+            });
+        }
+        (input, format) => {
+            return Err(anyhow!(
+                "The {format:?} format is not supported for {input:?}"
+            ))
+        }
+    };
+
+    Ok(Source {
+        packets,
+        bytes_read: Some(bytes_read),
+        record_overhead: match format {
+            InputFormat::Tlog => mavlink_codec::tlog::TIMESTAMP_SIZE,
+            InputFormat::Raw | InputFormat::Pcap => 0,
+        },
+    })
+}
+
+fn raw<R: AsyncRead + Unpin + Send + 'static>(
+    reader: R,
+    options: CodecArgs,
+    bytes_read: &Arc<AtomicU64>,
+) -> PacketStream {
+    let reader = CountingReader::new(reader, bytes_read);
+
+    FramedRead::new(reader, DynCodec::new(options))
+        .map(|result| result.map(|decoded| (None, decoded)))
+        .boxed()
+}
+
+fn tlog<R: AsyncRead + Unpin + Send + 'static>(
+    reader: R,
+    options: CodecArgs,
+    bytes_read: &Arc<AtomicU64>,
+) -> PacketStream {
+    let reader = CountingReader::new(reader, bytes_read);
+
+    TlogReader::with_codec(reader, DynCodec::new(options))
+        .map(|record| record.map(|(timestamp, packet)| (Some(timestamp), Ok(packet))))
+        .boxed()
+}
+
+/// Reads the capture on a blocking thread, decoding the datagrams with the codec options
+async fn pcap(path: &str, options: CodecArgs) -> Result<PacketStream> {
+    let path = path.to_string();
+    let reader = tokio::task::spawn_blocking(move || {
+        let file =
+            std::fs::File::open(&path).with_context(|| format!("Failed to open {path:?}"))?;
+        PcapReader::with_codec(std::io::BufReader::new(file), DynCodec::new(options))
+            .with_context(|| format!("Failed to read {path:?}"))
+    })
+    .await??;
+
+    let (sender, receiver) = tokio::sync::mpsc::channel(PCAP_CHANNEL_CAPACITY);
+    tokio::task::spawn_blocking(move || {
+        for record in reader {
+            let item = record.map(|(timestamp, .., decoded)| (Some(timestamp), decoded));
+            let failed = item.is_err();
+            if sender.blocking_send(item).is_err() || failed {
+                return;
+            }
+        }
+    });
+
+    Ok(stream::unfold(receiver, |mut receiver| async move {
+        receiver.recv().await.map(|item| (item, receiver))
+    })
+    .boxed())
+}
+
+fn udp(socket: UdpSocket, options: CodecArgs, bytes_read: &Arc<AtomicU64>) -> PacketStream {
+    // Each datagram is decoded on its own, so a truncated datagram doesn't corrupt the next ones
+    let state = (
+        socket,
+        DynCodec::new(options),
+        BytesMut::new(),
+        vec![0u8; 65535],
+    );
+    let bytes_read = bytes_read.clone();
+
+    stream::unfold(state, move |(socket, mut codec, mut buf, mut datagram)| {
+        let bytes_read = bytes_read.clone();
+        async move {
+            loop {
+                match codec.decode(&mut buf) {
+                    Ok(Some(item)) => {
+                        return Some((Ok((None, item)), (socket, codec, buf, datagram)))
+                    }
+                    Ok(None) => (),
+                    Err(error) => return Some((Err(error), (socket, codec, buf, datagram))),
+                }
+
+                match socket.recv_from(&mut datagram).await {
+                    Ok((size, _source)) => {
+                        bytes_read.fetch_add(size as u64, Ordering::Relaxed);
+                        codec.reset();
+                        buf.clear();
+                        buf.extend_from_slice(&datagram[..size]);
+                    }
+                    Err(error) => return Some((Err(error), (socket, codec, buf, datagram))),
+                }
+            }
+        }
+    })
+    .boxed()
+}
+
+/// Counts the bytes read from the inner reader
+struct CountingReader<R> {
+    inner: R,
+    count: Arc<AtomicU64>,
+}
+
+impl<R> CountingReader<R> {
+    fn new(inner: R, count: &Arc<AtomicU64>) -> Self {
+        Self {
+            inner,
+            count: count.clone(),
+        }
+    }
+}
+
+impl<R: AsyncRead + Unpin> AsyncRead for CountingReader<R> {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut TaskContext<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<std::io::Result<()>> {
+        let this = self.get_mut();
+        let filled_before = buf.filled().len();
+
+        let poll = Pin::new(&mut this.inner).poll_read(cx, buf);
+        if let Poll::Ready(Ok(())) = poll {
+            let read = buf.filled().len() - filled_before;
+            this.count.fetch_add(read as u64, Ordering::Relaxed);
+        }
+
+        poll
+    }
+}
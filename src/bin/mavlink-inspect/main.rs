@@ -0,0 +1,261 @@
+//! `mavlink-inspect`: decodes and summarises MAVLink from a file, stdin, UDP or TCP.
+
+mod input;
+mod stats;
+
+use std::io::Write;
+
+use anyhow::Result;
+use clap::{Args, Parser, Subcommand, ValueEnum};
+use futures::StreamExt;
+use mavlink::calculate_crc;
+
+use mavlink_codec::{
+    codec::get_extra_crc,
+    dialect::{ArduPilotMega, Dialect},
+    error::DecoderError,
+    Packet,
+};
+
+use input::{Input, InputFormat};
+
+#[derive(Debug, Parser)]
+#[command(name = "mavlink-inspect", version, about)]
+struct Cli {
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Debug, Subcommand)]
+enum Command {
+    /// Prints one line per decoded packet
+    Decode {
+        #[command(flatten)]
+        input: InputArgs,
+
+        /// Output mode
+        #[arg(short, long, value_enum, default_value_t = OutputMode::Text)]
+        output: OutputMode,
+
+        /// Also print decoding errors
+        #[arg(short, long)]
+        errors: bool,
+    },
+    /// Summarises the input: message rates, sequence loss, errors and protocol usage
+    Stats {
+        #[command(flatten)]
+        input: InputArgs,
+
+        /// Output mode
+        #[arg(short, long, value_enum, default_value_t = StatsOutputMode::Table)]
+        output: StatsOutputMode,
+    },
+}
+
+#[derive(Debug, Args)]
+struct InputArgs {
+    /// Where to read from: a file path, `-` for stdin, `udp://<bind address>:<port>` or
+    /// `tcp://<address>:<port>`
+    input: Input,
+
+    /// Format of the input data. Invalid tlog records are skipped, so they are not counted as
+    /// errors.
+    #[arg(short, long, value_enum, default_value_t = InputFormat::Raw)]
+    format: InputFormat,
+
+    #[command(flatten)]
+    codec: CodecArgs,
+}
+
+/// [`mavlink_codec::codec::MavlinkCodec`] options
+#[derive(Debug, Clone, Copy, Args)]
+struct CodecArgs {
+    /// Reject MAVLink V1 packets
+    #[arg(long)]
+    no_v1: bool,
+
+    /// Reject MAVLink V2 packets
+    #[arg(long)]
+    no_v2: bool,
+
+    /// Drop packets with zeroed System ID
+    #[arg(long)]
+    drop_invalid_sysid: bool,
+
+    /// Drop packets with zeroed Component ID
+    #[arg(long)]
+    drop_invalid_compid: bool,
+
+    /// Skip the CRC validation
+    #[arg(long)]
+    skip_crc_validation: bool,
+
+    /// Drop packets with unknown Incompatibility Flags
+    #[arg(long)]
+    drop_incompatible: bool,
+
+    /// Drop packets with a payload length out of the bounds of their message
+    #[arg(long)]
+    validate_payload_length: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum OutputMode {
+    /// A human-readable summary line
+    Text,
+    /// The summary line followed by the packet bytes in hexadecimal
+    Hex,
+    /// One JSON object per line
+    Json,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum StatsOutputMode {
+    /// Human-readable tables
+    Table,
+    /// A single JSON document
+    Json,
+}
+
+#[tokio::main]
+async fn main() -> Result<()> {
+    let cli = Cli::parse();
+
+    match cli.command {
+        Command::Decode {
+            input,
+            output,
+            errors,
+        } => decode(input, output, errors).await,
+        Command::Stats { input, output } => stats::run(input, output).await,
+    }
+}
+
+async fn decode(input: InputArgs, output: OutputMode, errors: bool) -> Result<()> {
+    let mut source = input::open(&input.input, input.format, input.codec).await?;
+    let mut stdout = std::io::stdout().lock();
+
+    while let Some(result) = source.packets.next().await {
+        let (_timestamp, decoded) = result?;
+        let line = match decoded {
+            Ok(packet) => format_packet(&packet, output),
+            Err(error) if errors => format_error(&error, output),
+            Err(_) => continue,
+        };
+
+        if writeln!(stdout, "{line}").is_err() {
+            // Broken pipe, e.g. when piped into `head`
+            break;
+        }
+    }
+
+    Ok(())
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum CrcStatus {
+    Ok,
+    Invalid,
+}
+
+impl CrcStatus {
+    fn of(packet: &Packet) -> Self {
+        let valid = get_extra_crc(packet.message_id()).is_some_and(|extra_crc| {
+            calculate_crc(packet.checksum_data(), extra_crc) == packet.checksum()
+        });
+
+        if valid {
+            Self::Ok
+        } else {
+            Self::Invalid
+        }
+    }
+
+    fn as_str(&self) -> &'static str {
+        match self {
+            Self::Ok => "ok",
+            Self::Invalid => "invalid",
+        }
+    }
+}
+
+fn version(packet: &Packet) -> u8 {
+    match packet {
+        Packet::V1(_) => 1,
+        Packet::V2(_) => 2,
+    }
+}
+
+fn message_name(message_id: u32) -> Option<&'static str> {
+    ArduPilotMega.message_name(message_id)
+}
+
+fn format_packet(packet: &Packet, output: OutputMode) -> String {
+    let message_id = packet.message_id();
+    let name = message_name(message_id);
+    let crc = CrcStatus::of(packet);
+
+    match output {
+        OutputMode::Text | OutputMode::Hex => {
+            let mut line = format!(
+                "V{} seq={:<3} sys={:<3} comp={:<3} msg={:<6} {:<32} len={:<3} crc={}",
+                version(packet),
+                packet.sequence(),
+                packet.system_id(),
+                packet.component_id(),
+                message_id,
+                name.unwrap_or("UNKNOWN"),
+                packet.payload_length(),
+                crc.as_str(),
+            );
+
+            if output == OutputMode::Hex {
+                line.push('\n');
+                line.push_str(&hex(packet.as_slice()));
+            }
+
+            line
+        }
+        OutputMode::Json => serde_json::json!({
+            "version": version(packet),
+            "sequence": packet.sequence(),
+            "system_id": packet.system_id(),
+            "component_id": packet.component_id(),
+            "message_id": message_id,
+            "message_name": name,
+            "payload_length": packet.payload_length(),
+            "checksum": packet.checksum(),
+            "crc": crc.as_str(),
+            "bytes": hex(packet.as_slice()),
+        })
+        .to_string(),
+    }
+}
+
+fn format_error(error: &DecoderError, output: OutputMode) -> String {
+    match output {
+        OutputMode::Text | OutputMode::Hex => format!("error: {error}"),
+        OutputMode::Json => match error.context() {
+            Some(context) => serde_json::json!({
+                "error": error.to_string(),
+                "version": context.version,
+                "sequence": context.sequence,
+                "system_id": context.system_id,
+                "component_id": context.component_id,
+                "message_id": context.message_id,
+                "offset": context.offset,
+                "bytes": hex(&context.frame),
+            }),
+            None => serde_json::json!({ "error": error.to_string() }),
+        }
+        .to_string(),
+    }
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes
+        .iter()
+        .map(|byte| format!("{byte:02x}"))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
@@ -0,0 +1,410 @@
+use std::{
+    collections::BTreeMap,
+    fmt::Write as _,
+    sync::atomic::Ordering,
+    time::{Duration, Instant, SystemTime},
+};
+
+use anyhow::Result;
+use futures::StreamExt;
+use serde_json::json;
+
+use mavlink_codec::{error::DecoderError, Packet};
+
+use crate::{
+    input::{self, Input},
+    message_name, InputArgs, StatsOutputMode,
+};
+
+pub async fn run(input: InputArgs, output: StatsOutputMode) -> Result<()> {
+    let mut source = input::open(&input.input, input.format, input.codec).await?;
+    let mut statistics = Statistics {
+        // Reading a raw file takes no time, so arrival rates only make sense for live inputs
+        arrival_rates: !matches!(input.input, Input::File(_)),
+        ..Default::default()
+    };
+
+    while let Some(result) = source.packets.next().await {
+        let (timestamp, decoded) = result?;
+        statistics.update(timestamp, &decoded);
+    }
+
+    // Packet bytes plus the format framing around them, everything else is garbage
+    if let Some(bytes_read) = source.bytes_read {
+        let framing = statistics.packets() * source.record_overhead as u64;
+        statistics.garbage_bytes = Some(
+            bytes_read
+                .load(Ordering::Relaxed)
+                .saturating_sub(statistics.packet_bytes + framing),
+        );
+    }
+
+    match output {
+        StatsOutputMode::Table => print!("{}", statistics.to_table()),
+        StatsOutputMode::Json => println!("{:#}", statistics.to_json()),
+    }
+
+    Ok(())
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+struct MessageStatistics {
+    count: u64,
+    bytes: u64,
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+struct SourceStatistics {
+    received: u64,
+    lost: u64,
+    last_sequence: Option<u8>,
+}
+
+impl SourceStatistics {
+    /// How far behind the last sequence a packet is considered late rather than from a source
+    /// that restarted
+    const REORDER_WINDOW: u8 = 16;
+
+    fn update(&mut self, sequence: u8) {
+        self.received += 1;
+
+        let Some(last_sequence) = self.last_sequence.replace(sequence) else {
+            return;
+        };
+
+        // Anything between the expected and the received sequence was lost, modulo 256
+        let gap = sequence.wrapping_sub(last_sequence.wrapping_add(1));
+        if gap < 128 {
+            self.lost += gap as u64;
+            return;
+        }
+
+        // A backward jump
+        let behind = last_sequence.wrapping_sub(sequence);
+        if behind != 0 && behind <= Self::REORDER_WINDOW {
+            // Late, so it was counted as lost, and the last sequence stays ahead
+            self.lost = self.lost.saturating_sub(1);
+            self.last_sequence = Some(last_sequence);
+        }
+    }
+
+    fn loss_ratio(&self) -> f64 {
+        ratio(self.lost, self.received + self.lost)
+    }
+}
+
+/// Aggregated statistics of a decoded stream
+#[derive(Debug, Default)]
+struct Statistics {
+    first_timestamp: Option<SystemTime>,
+    last_timestamp: Option<SystemTime>,
+    /// Use the arrival time when the input has no timestamps
+    arrival_rates: bool,
+    started_at: Option<Instant>,
+    finished_at: Option<Instant>,
+    /// By (System ID, Component ID, Message ID)
+    messages: BTreeMap<(u8, u8, u32), MessageStatistics>,
+    /// By (System ID, Component ID)
+    sources: BTreeMap<(u8, u8), SourceStatistics>,
+    errors: BTreeMap<&'static str, u64>,
+    v1_packets: u64,
+    v2_packets: u64,
+    signed_packets: u64,
+    packet_bytes: u64,
+    garbage_bytes: Option<u64>,
+}
+
+impl Statistics {
+    fn update(&mut self, timestamp: Option<SystemTime>, decoded: &Result<Packet, DecoderError>) {
+        let now = Instant::now();
+        self.started_at.get_or_insert(now);
+        self.finished_at = Some(now);
+
+        if let Some(timestamp) = timestamp {
+            self.first_timestamp.get_or_insert(timestamp);
+            self.last_timestamp = Some(timestamp);
+        }
+
+        let packet = match decoded {
+            Ok(packet) => packet,
+            Err(error) => {
+                *self.errors.entry(error_kind(error)).or_default() += 1;
+                return;
+            }
+        };
+
+        let system_id = *packet.system_id();
+        let component_id = *packet.component_id();
+        let packet_size = packet.packet_size() as u64;
+
+        let message = self
+            .messages
+            .entry((system_id, component_id, packet.message_id()))
+            .or_default();
+        message.count += 1;
+        message.bytes += packet_size;
+
+        self.sources
+            .entry((system_id, component_id))
+            .or_default()
+            .update(*packet.sequence());
+
+        match packet {
+            Packet::V1(_) => self.v1_packets += 1,
+            Packet::V2(packet) => {
+                self.v2_packets += 1;
+                if packet.has_signature() {
+                    self.signed_packets += 1;
+                }
+            }
+        }
+
+        self.packet_bytes += packet_size;
+    }
+
+    fn packets(&self) -> u64 {
+        self.v1_packets + self.v2_packets
+    }
+
+    /// Time span covered by the input, from its timestamps if it has them, otherwise from
+    /// the arrival times of live inputs
+    fn duration(&self) -> Duration {
+        if let (Some(first), Some(last)) = (self.first_timestamp, self.last_timestamp) {
+            return last.duration_since(first).unwrap_or_default();
+        }
+
+        match (self.started_at, self.finished_at) {
+            (Some(started_at), Some(finished_at)) if self.arrival_rates => finished_at - started_at,
+            _ => Duration::ZERO,
+        }
+    }
+
+    fn rate(&self, count: u64) -> Option<f64> {
+        let duration = self.duration().as_secs_f64();
+
+        (duration > 0.0).then(|| count as f64 / duration)
+    }
+
+    fn to_table(&self) -> String {
+        let mut table = String::new();
+
+        let _ = writeln!(table, "Duration: {:.3} s", self.duration().as_secs_f64());
+        let _ = writeln!(
+            table,
+            "Packets: {} (V1: {}, V2: {}), signed: {} ({:.1}%), unsigned: {} ({:.1}%)",
+            self.packets(),
+            self.v1_packets,
+            self.v2_packets,
+            self.signed_packets,
+            100.0 * ratio(self.signed_packets, self.packets()),
+            self.packets() - self.signed_packets,
+            100.0 * ratio(self.packets() - self.signed_packets, self.packets()),
+        );
+        let _ = writeln!(
+            table,
+            "Bytes: {} in packets, {} garbage",
+            self.packet_bytes,
+            self.garbage_bytes
+                .map_or_else(|| "unknown".to_string(), |bytes| bytes.to_string()),
+        );
+
+        let _ = writeln!(table, "\nMessages:");
+        let _ = writeln!(
+            table,
+            "{:>5} {:>5} {:>8} {:<32} {:>10} {:>10} {:>12}",
+            "sys", "comp", "msg", "name", "count", "rate (Hz)", "bytes"
+        );
+        for ((system_id, component_id, message_id), message) in &self.messages {
+            let _ = writeln!(
+                table,
+                "{:>5} {:>5} {:>8} {:<32} {:>10} {:>10} {:>12}",
+                system_id,
+                component_id,
+                message_id,
+                message_name(*message_id).unwrap_or("UNKNOWN"),
+                message.count,
+                format_rate(self.rate(message.count)),
+                message.bytes,
+            );
+        }
+
+        let _ = writeln!(table, "\nSources:");
+        let _ = writeln!(
+            table,
+            "{:>5} {:>5} {:>10} {:>10} {:>8}",
+            "sys", "comp", "received", "lost", "loss"
+        );
+        for ((system_id, component_id), source) in &self.sources {
+            let _ = writeln!(
+                table,
+                "{:>5} {:>5} {:>10} {:>10} {:>7.2}%",
+                system_id,
+                component_id,
+                source.received,
+                source.lost,
+                100.0 * source.loss_ratio(),
+            );
+        }
+
+        let _ = writeln!(table, "\nErrors:");
+        if self.errors.is_empty() {
+            let _ = writeln!(table, "none");
+        }
+        for (kind, count) in &self.errors {
+            let _ = writeln!(table, "{kind:<24} {count:>10}");
+        }
+
+        table
+    }
+
+    fn to_json(&self) -> serde_json::Value {
+        let messages = self
+            .messages
+            .iter()
+            .map(|((system_id, component_id, message_id), message)| {
+                json!({
+                    "system_id": system_id,
+                    "component_id": component_id,
+                    "message_id": message_id,
+                    "message_name": message_name(*message_id),
+                    "count": message.count,
+                    "rate": self.rate(message.count),
+                    "bytes": message.bytes,
+                })
+            })
+            .collect::<Vec<_>>();
+
+        let sources = self
+            .sources
+            .iter()
+            .map(|((system_id, component_id), source)| {
+                json!({
+                    "system_id": system_id,
+                    "component_id": component_id,
+                    "received": source.received,
+                    "lost": source.lost,
+                    "loss_ratio": source.loss_ratio(),
+                })
+            })
+            .collect::<Vec<_>>();
+
+        json!({
+            "duration": self.duration().as_secs_f64(),
+            "packets": {
+                "total": self.packets(),
+                "v1": self.v1_packets,
+                "v2": self.v2_packets,
+                "signed": self.signed_packets,
+                "unsigned": self.packets() - self.signed_packets,
+            },
+            "bytes": {
+                "packets": self.packet_bytes,
+                "garbage": self.garbage_bytes,
+            },
+            "messages": messages,
+            "sources": sources,
+            "errors": self.errors,
+        })
+    }
+}
+
+fn error_kind(error: &DecoderError) -> &'static str {
+    match error {
+        DecoderError::InvalidSystemID { .. } => "invalid_system_id",
+        DecoderError::InvalidComponentID { .. } => "invalid_component_id",
+        DecoderError::Incompatible { .. } => "incompatible",
+        DecoderError::UnknownMessageID { .. } => "unknown_message_id",
+        DecoderError::InvalidPayloadLength { .. } => "invalid_payload_length",
+        DecoderError::InvalidCRC { .. } => "invalid_crc",
+        DecoderError::Io(_) => "io",
+        _ => "unknown",
+    }
+}
+
+fn ratio(part: u64, total: u64) -> f64 {
+    if total == 0 {
+        return 0.0;
+    }
+
+    part as f64 / total as f64
+}
+
+fn format_rate(rate: Option<f64>) -> String {
+    rate.map_or_else(|| "-".to_string(), |rate| format!("{rate:.2}"))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use mavlink::{ardupilotmega::MavMessage, MAVLinkV2MessageRaw, MavHeader, Message};
+
+    fn create_packet(system_id: u8, sequence: u8) -> Packet {
+        let header = MavHeader {
+            system_id,
+            component_id: 1,
+            sequence,
+        };
+
+        let message_data = MavMessage::default_message_from_id(0).unwrap();
+        let mut raw_v2_message = MAVLinkV2MessageRaw::new();
+        raw_v2_message.serialize_message(header, &message_data);
+
+        Packet::from(raw_v2_message)
+    }
+
+    #[test]
+    fn test_sequence_loss() {
+        let mut statistics = Statistics::default();
+
+        for (system_id, sequence) in [(1, 254), (1, 255), (2, 10), (1, 2), (2, 11), (1, 3)] {
+            statistics.update(None, &Ok(create_packet(system_id, sequence)));
+        }
+
+        let first = statistics.sources[&(1, 1)];
+        assert_eq!((first.received, first.lost), (4, 2));
+
+        let second = statistics.sources[&(2, 1)];
+        assert_eq!((second.received, second.lost), (2, 0));
+    }
+
+    #[test]
+    fn test_sequence_duplicates_and_reordering() {
+        let mut statistics = Statistics::default();
+
+        // 42 is duplicated, 44 arrives after 45, and then the source restarts from 0
+        for sequence in [40, 41, 42, 42, 43, 45, 44, 46, 0, 1] {
+            statistics.update(None, &Ok(create_packet(1, sequence)));
+        }
+
+        let source = statistics.sources[&(1, 1)];
+        assert_eq!((source.received, source.lost), (10, 0));
+        assert_eq!(source.last_sequence, Some(1));
+    }
+
+    #[test]
+    fn test_counts_and_rates() {
+        let mut statistics = Statistics::default();
+        let start = SystemTime::UNIX_EPOCH + Duration::from_secs(1_700_000_000);
+
+        for index in 0..11 {
+            let timestamp = start + Duration::from_millis(100 * index);
+            statistics.update(Some(timestamp), &Ok(create_packet(1, index as u8)));
+        }
+        statistics.update(
+            None,
+            &Err(DecoderError::InvalidCRC {
+                expected_crc: 0,
+                calculated_crc: 1,
+                context: Default::default(),
+            }),
+        );
+
+        let message = statistics.messages[&(1, 1, 0)];
+        assert_eq!(message.count, 11);
+        assert_eq!(statistics.duration(), Duration::from_secs(1));
+        assert_eq!(statistics.rate(message.count), Some(11.0));
+        assert_eq!(statistics.v2_packets, 11);
+        assert_eq!(statistics.signed_packets, 0);
+        assert_eq!(statistics.errors["invalid_crc"], 1);
+    }
+}
@@ -0,0 +1,240 @@
+//! Building the packets a component sends.
+
+use std::sync::{
+    atomic::{AtomicU8, Ordering},
+    Arc,
+};
+
+use bytes::{BufMut, BytesMut};
+
+use crate::{
+    dialect::{ArduPilotMega, Dialect},
+    error::ValidationError,
+    field::FieldValue,
+    v2::{trim_payload, V2Packet, V2_STX},
+    Packet,
+};
+
+/// The sequence counter of a component, shared by everything it sends so receivers don't see
+/// gaps that aren't losses
+#[derive(Debug, Clone, Default)]
+pub struct Sequence(Arc<AtomicU8>);
+
+impl Sequence {
+    /// Returns the current sequence and advances the counter, wrapping at 255
+    #[inline(always)]
+    pub fn next(&self) -> u8 {
+        self.0.fetch_add(1, Ordering::Relaxed)
+    }
+}
+
+/// Builds the packets of a component, from raw payloads.
+///
+/// Clones share the [`Sequence`].
+#[derive(Debug, Clone)]
+pub struct PacketBuilder<D: Dialect = ArduPilotMega> {
+    system_id: u8,
+    component_id: u8,
+    sequence: Sequence,
+    dialect: D,
+}
+
+impl PacketBuilder {
+    pub fn new(system_id: u8, component_id: u8) -> Self {
+        Self::with_dialect(system_id, component_id, ArduPilotMega)
+    }
+}
+
+impl<D: Dialect> PacketBuilder<D> {
+    pub fn with_dialect(system_id: u8, component_id: u8, dialect: D) -> Self {
+        Self {
+            system_id,
+            component_id,
+            sequence: Sequence::default(),
+            dialect,
+        }
+    }
+
+    /// Shares the sequence counter of something else sending as this component
+    pub fn with_sequence(mut self, sequence: Sequence) -> Self {
+        self.sequence = sequence;
+        self
+    }
+
+    pub fn system_id(&self) -> u8 {
+        self.system_id
+    }
+
+    pub fn component_id(&self) -> u8 {
+        self.component_id
+    }
+
+    pub fn sequence(&self) -> &Sequence {
+        &self.sequence
+    }
+
+    pub fn dialect(&self) -> &D {
+        &self.dialect
+    }
+
+    /// Builds an unsigned MAVLink V2 packet, with the trailing zeroes of the payload truncated
+    pub fn build(&self, message_id: u32, payload: &[u8]) -> Result<Packet, ValidationError> {
+        if message_id > 0x00FF_FFFF {
+            return Err(ValidationError::InvalidMessageID {
+                version: 2,
+                msgid: message_id,
+            });
+        }
+        if payload.len() > V2Packet::MAX_PAYLOAD_SIZE {
+            return Err(ValidationError::InvalidSize {
+                expected: V2Packet::MAX_PAYLOAD_SIZE,
+                actual: payload.len(),
+            });
+        }
+        let Some(extra_crc) = self.dialect.extra_crc(message_id) else {
+            return Err(ValidationError::UnknownMessageID { msgid: message_id });
+        };
+
+        let payload = trim_payload(payload);
+
+        let mut buf = BytesMut::with_capacity(V2Packet::MAX_PACKET_SIZE);
+        buf.put_u8(V2_STX);
+        buf.put_u8(payload.len() as u8);
+        buf.put_u8(0); // incompat flags
+        buf.put_u8(0); // compat flags
+        buf.put_u8(self.sequence.next());
+        buf.put_u8(self.system_id);
+        buf.put_u8(self.component_id);
+        buf.put_slice(&message_id.to_le_bytes()[..3]);
+        buf.put_slice(payload);
+        let crc = mavlink::calculate_crc(&buf[V2Packet::STX_SIZE..], extra_crc);
+        buf.put_u16_le(crc);
+
+        Ok(Packet::V2(V2Packet::new(buf.freeze())))
+    }
+
+    /// Builds a message from the values of its fields, by name, as described by the dialect.
+    ///
+    /// The fields left out are zero, and values that don't fit their field are an error.
+    pub fn build_fields(
+        &self,
+        message_id: u32,
+        values: &[(&str, FieldValue)],
+    ) -> Result<Packet, ValidationError> {
+        let Some(fields) = self.dialect.fields(message_id) else {
+            return Err(ValidationError::UnknownMessageID { msgid: message_id });
+        };
+
+        let mut payload = [0u8; V2Packet::MAX_PAYLOAD_SIZE];
+        for (name, value) in values {
+            let Some(field) = fields.iter().find(|field| field.name == *name) else {
+                return Err(ValidationError::UnknownField {
+                    msgid: message_id,
+                    field: name.to_string(),
+                });
+            };
+
+            field.write(&mut payload, value)?;
+        }
+
+        self.build(message_id, &payload)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{
+        field::FieldType,
+        test_util::{create_v2_packet, header},
+    };
+
+    #[test]
+    fn test_build() {
+        let builder = PacketBuilder::new(1, 2);
+
+        // The same as rust-mavlink
+        let expected = create_v2_packet(header(1, 2, 0), 30);
+
+        let packet = builder.build(30, &[0; 28]).unwrap();
+        assert_eq!(packet, expected);
+        assert_eq!(packet.payload(), &[0]);
+
+        // Validated the same as received packets
+        assert_eq!(Packet::try_from(packet.bytes().clone()), Ok(packet));
+
+        assert_eq!(
+            builder.build(u32::MAX, &[]),
+            Err(ValidationError::InvalidMessageID {
+                version: 2,
+                msgid: u32::MAX
+            })
+        );
+        assert_eq!(
+            builder.build(0x00FF_FFFF, &[0]),
+            Err(ValidationError::UnknownMessageID { msgid: 0x00FF_FFFF })
+        );
+    }
+
+    #[test]
+    fn test_build_fields() {
+        let builder = PacketBuilder::new(255, 190);
+
+        let packet = builder
+            .build_fields(
+                76,
+                &[
+                    ("target_system", FieldValue::U8(1)),
+                    ("target_component", FieldValue::U8(1)),
+                    ("command", FieldValue::U16(400)),
+                    ("param1", FieldValue::F32(1.0)),
+                ],
+            )
+            .unwrap();
+        assert_eq!(packet.message_id(), 76);
+        assert_eq!(packet.target(&ArduPilotMega), Some((1, 1)));
+        assert_eq!(
+            packet.field("command", &ArduPilotMega),
+            Some(FieldValue::U16(400))
+        );
+        assert_eq!(
+            packet.field("param1", &ArduPilotMega),
+            Some(FieldValue::F32(1.0))
+        );
+        assert_eq!(
+            packet.field("confirmation", &ArduPilotMega),
+            Some(FieldValue::U8(0))
+        );
+
+        assert_eq!(
+            builder.build_fields(76, &[("unknown", FieldValue::U8(1))]),
+            Err(ValidationError::UnknownField {
+                msgid: 76,
+                field: "unknown".to_string()
+            })
+        );
+        assert_eq!(
+            builder.build_fields(76, &[("command", FieldValue::Text("400".to_string()))]),
+            Err(ValidationError::InvalidFieldValue {
+                field: "command",
+                field_type: FieldType::U16,
+            })
+        );
+    }
+
+    #[test]
+    fn test_shared_sequence() {
+        let builder = PacketBuilder::new(1, 1);
+        let other_builder = PacketBuilder::new(1, 1).with_sequence(builder.sequence().clone());
+        let cloned_builder = builder.clone();
+
+        let sequences = [
+            builder.build(0, &[1]).unwrap(),
+            other_builder.build(30, &[1]).unwrap(),
+            cloned_builder.build(0, &[1]).unwrap(),
+        ]
+        .map(|packet| *packet.sequence());
+
+        assert_eq!(sequences, [0, 1, 2]);
+    }
+}
@@ -0,0 +1,142 @@
+//! What the clients of the MAVLink microservices, like the command protocol, have in common.
+
+use std::{io, pin::Pin};
+
+use futures::{stream::BoxStream, Sink, SinkExt, Stream, StreamExt};
+use log::trace;
+use thiserror::Error;
+use tokio::time::Instant;
+
+use crate::{
+    builder::PacketBuilder,
+    dialect::{ArduPilotMega, Dialect},
+    error::ValidationError,
+    field::FieldValue,
+    target::target_matches,
+    Packet,
+};
+
+/// Why a client of a MAVLink microservice failed, whatever the microservice
+#[derive(Error, Debug)]
+#[non_exhaustive]
+pub enum ClientError {
+    #[error("no response after {attempts} attempts")]
+    Timeout { attempts: u32 },
+
+    #[error("the connection is closed")]
+    Closed,
+
+    #[error("invalid message: {0}")]
+    Message(#[from] ValidationError),
+
+    #[error("io error")]
+    Io(#[from] io::Error),
+}
+
+type BoxSink = Pin<Box<dyn Sink<Packet, Error = io::Error> + Send>>;
+
+/// The link between a client component and the target component it talks to
+pub struct Connection<D: Dialect = ArduPilotMega> {
+    stream: BoxStream<'static, Packet>,
+    sink: BoxSink,
+    builder: PacketBuilder<D>,
+    target: (u8, u8),
+}
+
+impl<D: Dialect + std::fmt::Debug> std::fmt::Debug for Connection<D> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Connection")
+            .field("builder", &self.builder)
+            .field("target", &self.target)
+            .finish_non_exhaustive()
+    }
+}
+
+impl<D: Dialect> Connection<D> {
+    /// Sends with `builder`, as its component, to the `target` (System ID, Component ID).
+    ///
+    /// A [`BROADCAST`](crate::target::BROADCAST) target component accepts the responses of any
+    /// component of the target system.
+    pub fn new<T>(transport: T, builder: PacketBuilder<D>, target: (u8, u8)) -> Self
+    where
+        T: Stream<Item = Packet> + Sink<Packet> + Send + 'static,
+        T::Error: Into<Box<dyn std::error::Error + Send + Sync>>,
+    {
+        let (sink, stream) = transport.split();
+
+        Self::from_split(stream, sink, builder, target)
+    }
+
+    /// A connection with separate receiving and sending halves
+    pub fn from_split<St, Si>(
+        stream: St,
+        sink: Si,
+        builder: PacketBuilder<D>,
+        target: (u8, u8),
+    ) -> Self
+    where
+        St: Stream<Item = Packet> + Send + 'static,
+        Si: Sink<Packet> + Send + 'static,
+        Si::Error: Into<Box<dyn std::error::Error + Send + Sync>>,
+    {
+        Self {
+            stream: stream.boxed(),
+            sink: Box::pin(sink.sink_map_err(io::Error::other)),
+            builder,
+            target,
+        }
+    }
+
+    pub fn builder(&self) -> &PacketBuilder<D> {
+        &self.builder
+    }
+
+    /// The target (System ID, Component ID)
+    pub fn target(&self) -> (u8, u8) {
+        self.target
+    }
+
+    /// Sends a message to the target, filling its `target_system` and `target_component` fields
+    pub async fn send(
+        &mut self,
+        message_id: u32,
+        values: &[(&str, FieldValue)],
+    ) -> Result<(), ClientError> {
+        let (target_system, target_component) = self.target;
+
+        let mut values = values.to_vec();
+        values.push(("target_system", FieldValue::U8(target_system)));
+        values.push(("target_component", FieldValue::U8(target_component)));
+        let packet = self.builder.build_fields(message_id, &values)?;
+
+        trace!(
+            "Sending message {message_id} to {target_system}:{target_component}, sequence {}",
+            packet.sequence()
+        );
+        self.sink.send(packet).await?;
+
+        Ok(())
+    }
+
+    /// The next packet from the target and addressed to this component, or `None` if none came
+    /// before the deadline
+    pub async fn receive(&mut self, deadline: Instant) -> Result<Option<Packet>, ClientError> {
+        loop {
+            let packet = match tokio::time::timeout_at(deadline, self.stream.next()).await {
+                Ok(Some(packet)) => packet,
+                Ok(None) => return Err(ClientError::Closed),
+                Err(_elapsed) => return Ok(None),
+            };
+
+            if target_matches(self.target, *packet.system_id(), *packet.component_id())
+                && packet.is_addressed_to(
+                    self.builder.system_id(),
+                    self.builder.component_id(),
+                    self.builder.dialect(),
+                )
+            {
+                return Ok(Some(packet));
+            }
+        }
+    }
+}
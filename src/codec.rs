@@ -1,16 +1,17 @@
-use bytes::{Buf, BufMut, BytesMut};
+use bytes::{Buf, BufMut, Bytes, BytesMut};
 use log::trace;
 use mavlink::calculate_crc;
 use tokio_util::codec::{Decoder, Encoder};
 
 use crate::{
-    error::DecoderError,
+    dialect::{ArduPilotMega, Dialect, PayloadLength},
+    error::{DecoderError, FrameContext},
     v1::{self, V1Packet, V1_STX},
     v2::{self, V2Packet, MAVLINK_SUPPORTED_IFLAGS, V2_STX},
     Packet,
 };
 
-#[derive(Debug, Default)]
+#[derive(Debug, Default, Clone)]
 pub struct MavlinkCodec<
     const ACCEPT_V1: bool,
     const ACCEPT_V2: bool,
@@ -18,11 +19,70 @@ pub struct MavlinkCodec<
     const DROP_INVALID_COMPID: bool,
     const SKIP_CRC_VALIDATION: bool,
     const DROP_INCOMPATIBLE: bool,
+    const VALIDATE_PAYLOAD_LENGTH: bool = false,
 > {
     pub state: CodecState,
+    pub(crate) offset: u64,
 }
 
-#[derive(Debug, Default)]
+impl<
+        const ACCEPT_V1: bool,
+        const ACCEPT_V2: bool,
+        const DROP_INVALID_SYSID: bool,
+        const DROP_INVALID_COMPID: bool,
+        const SKIP_CRC_VALIDATION: bool,
+        const DROP_INCOMPATIBLE: bool,
+        const VALIDATE_PAYLOAD_LENGTH: bool,
+    >
+    MavlinkCodec<
+        ACCEPT_V1,
+        ACCEPT_V2,
+        DROP_INVALID_SYSID,
+        DROP_INVALID_COMPID,
+        SKIP_CRC_VALIDATION,
+        DROP_INCOMPATIBLE,
+        VALIDATE_PAYLOAD_LENGTH,
+    >
+{
+    /// Stream offset of the next byte to be decoded, counting every byte consumed so far
+    #[inline(always)]
+    pub fn offset(&self) -> u64 {
+        self.offset
+    }
+
+    /// Captures the candidate frame at the start of `buf`, then discards its STX
+    #[cold]
+    fn discard_stx(&mut self, buf: &mut BytesMut, version: u8) -> Box<FrameContext> {
+        let context = match version {
+            1 => FrameContext {
+                version,
+                sequence: *v1::seq(buf),
+                system_id: *v1::sysid(buf),
+                component_id: *v1::compid(buf),
+                message_id: *v1::msgid(buf) as u32,
+                frame: Bytes::copy_from_slice(&buf[..v1::packet_size(buf).min(buf.len())]),
+                offset: self.offset,
+            },
+            _ => FrameContext {
+                version,
+                sequence: *v2::seq(buf),
+                system_id: *v2::sysid(buf),
+                component_id: *v2::compid(buf),
+                message_id: v2::msgid(buf),
+                frame: Bytes::copy_from_slice(&buf[..v2::packet_size(buf).min(buf.len())]),
+                offset: self.offset,
+            },
+        };
+
+        buf.advance(V1Packet::STX_SIZE);
+        self.offset += V1Packet::STX_SIZE as u64;
+        self.state = CodecState::WaitingForStx;
+
+        Box::new(context)
+    }
+}
+
+#[derive(Debug, Default, Clone)]
 pub enum CodecState {
     #[default]
     WaitingForStx,
@@ -49,6 +109,7 @@ impl<
         const DROP_INVALID_COMPID: bool,
         const SKIP_CRC_VALIDATION: bool,
         const DROP_INCOMPATIBLE: bool,
+        const VALIDATE_PAYLOAD_LENGTH: bool,
     > Decoder
     for MavlinkCodec<
         ACCEPT_V1,
@@ -57,6 +118,7 @@ impl<
         DROP_INVALID_COMPID,
         SKIP_CRC_VALIDATION,
         DROP_INCOMPATIBLE,
+        VALIDATE_PAYLOAD_LENGTH,
     >
 {
     type Item = Result<Packet, DecoderError>;
@@ -91,13 +153,14 @@ impl<
                         _ => {
                             trace!("Invalid STX byte: {}", buf[0]);
                             buf.advance(V1Packet::STX_SIZE);
+                            self.offset += V1Packet::STX_SIZE as u64;
                             continue;
                         }
                     }
                 }
                 // V1 Codec
                 CodecState::WaitingV1PacketHeader if ACCEPT_V1 => {
-                    if buf.len() < V1Packet::HEADER_SIZE {
+                    if buf.len() < V1Packet::STX_SIZE + V1Packet::HEADER_SIZE {
                         // buf.reserve(V1Packet::HEADER_SIZE);
 
                         trace!(
@@ -129,10 +192,8 @@ impl<
                         if sysid == 0 {
                             trace!("Invalid SystemID: {sysid:?}. Data: {:?}", &buf[..]);
 
-                            buf.advance(V1Packet::STX_SIZE); // Discard this STX
-                            self.state = CodecState::WaitingForStx;
-
-                            return Ok(Some(Err(DecoderError::InvalidSystemID { sysid })));
+                            let context = self.discard_stx(buf, 1);
+                            return Ok(Some(Err(DecoderError::InvalidSystemID { sysid, context })));
                         }
                     }
 
@@ -142,10 +203,30 @@ impl<
                         if compid == 0 {
                             trace!("Invalid SystemID: {compid:?}. Data: {:?}", &buf[..]);
 
-                            buf.advance(V1Packet::STX_SIZE); // Discard this STX
-                            self.state = CodecState::WaitingForStx;
+                            let context = self.discard_stx(buf, 1);
+                            return Ok(Some(Err(DecoderError::InvalidComponentID {
+                                compid,
+                                context,
+                            })));
+                        }
+                    }
 
-                            return Ok(Some(Err(DecoderError::InvalidComponentID { compid })));
+                    // Payload length validation, unknown messages are left for the CRC validation
+                    if VALIDATE_PAYLOAD_LENGTH {
+                        let msgid = *v1::msgid(buf) as u32;
+                        let len = *v1::len(buf);
+                        if let Some(limits) = get_payload_length(msgid) {
+                            if !limits.accepts_v1(len) {
+                                trace!("Invalid payload length: {len:?}, limits: {limits:?}");
+
+                                let context = self.discard_stx(buf, 1);
+                                return Ok(Some(Err(DecoderError::InvalidPayloadLength {
+                                    len,
+                                    min: limits.min,
+                                    max: limits.max,
+                                    context,
+                                })));
+                            }
                         }
                     }
 
@@ -160,10 +241,8 @@ impl<
                     let Some(extra_crc) = get_extra_crc(msgid) else {
                         trace!("Unknown message ID {msgid:?}. Data: {:?}", &buf[..]);
 
-                        buf.advance(V1Packet::STX_SIZE); // Discard this STX
-                        self.state = CodecState::WaitingForStx;
-
-                        return Ok(Some(Err(DecoderError::UnknownMessageID { msgid })));
+                        let context = self.discard_stx(buf, 1);
+                        return Ok(Some(Err(DecoderError::UnknownMessageID { msgid, context })));
                     };
                     let checksum_data = v1::checksum_data(buf);
                     let calculated_crc = calculate_crc(checksum_data, extra_crc);
@@ -174,12 +253,11 @@ impl<
                             "Invalid CRC: expected: {expected_crc:?}, calculated: {calculated_crc:?}. checksum_data: {checksum_data:?}"
                         );
 
-                        buf.advance(V1Packet::STX_SIZE); // Discard this STX
-                        self.state = CodecState::WaitingForStx;
-
+                        let context = self.discard_stx(buf, 1);
                         return Ok(Some(Err(DecoderError::InvalidCRC {
                             expected_crc,
                             calculated_crc,
+                            context,
                         })));
                     }
 
@@ -187,16 +265,17 @@ impl<
                 }
                 CodecState::CopyV1Packet { packet_size } if ACCEPT_V1 => {
                     let buf_packet = if SKIP_CRC_VALIDATION {
-                        // Copy the entire packet consuming the source buffer
-                        let mut buf_packet = BytesMut::with_capacity(packet_size);
-                        buf_packet[..packet_size].copy_from_slice(&buf[..packet_size]);
+                        // Copy the entire packet without consuming the source buffer
+                        let buf_packet = BytesMut::from(&buf[..packet_size]);
 
                         // Since it is a non validated packet, there might be other packets within this buffer, so we can only discard this STX
                         buf.advance(V1Packet::STX_SIZE);
+                        self.offset += V1Packet::STX_SIZE as u64;
 
                         buf_packet
                     } else {
                         let buf_packet = buf.split_to(packet_size);
+                        self.offset += packet_size as u64;
                         // buf.reserve(V1Packet::MAX_PACKET_SIZE);
 
                         buf_packet
@@ -211,7 +290,7 @@ impl<
                 }
                 // V2 Codec
                 CodecState::WaitingV2PacketHeader if ACCEPT_V2 => {
-                    if buf.len() < V2Packet::HEADER_SIZE {
+                    if buf.len() < V2Packet::STX_SIZE + V2Packet::HEADER_SIZE {
                         // buf.reserve(V2Packet::HEADER_SIZE);
 
                         trace!(
@@ -225,10 +304,11 @@ impl<
                     if DROP_INCOMPATIBLE {
                         let incompat_flags = *v2::incompat_flags(buf);
                         if incompat_flags & !MAVLINK_SUPPORTED_IFLAGS > 0 {
-                            buf.advance(V1Packet::STX_SIZE); // Discard this STX
-                            self.state = CodecState::WaitingForStx;
-
-                            return Ok(Some(Err(DecoderError::Incompatible { incompat_flags })));
+                            let context = self.discard_stx(buf, 2);
+                            return Ok(Some(Err(DecoderError::Incompatible {
+                                incompat_flags,
+                                context,
+                            })));
                         }
                     }
 
@@ -253,10 +333,8 @@ impl<
                         if sysid == 0 {
                             trace!("Invalid SystemID: {sysid:?}. Data: {:?}", &buf[..]);
 
-                            buf.advance(V2Packet::STX_SIZE); // Discard this STX
-                            self.state = CodecState::WaitingForStx;
-
-                            return Ok(Some(Err(DecoderError::InvalidSystemID { sysid })));
+                            let context = self.discard_stx(buf, 2);
+                            return Ok(Some(Err(DecoderError::InvalidSystemID { sysid, context })));
                         }
                     }
 
@@ -266,10 +344,30 @@ impl<
                         if compid == 0 {
                             trace!("Invalid SystemID: {compid:?}. Data: {:?}", &buf[..]);
 
-                            buf.advance(V2Packet::STX_SIZE); // Discard this STX
-                            self.state = CodecState::WaitingForStx;
+                            let context = self.discard_stx(buf, 2);
+                            return Ok(Some(Err(DecoderError::InvalidComponentID {
+                                compid,
+                                context,
+                            })));
+                        }
+                    }
 
-                            return Ok(Some(Err(DecoderError::InvalidComponentID { compid })));
+                    // Payload length validation, unknown messages are left for the CRC validation
+                    if VALIDATE_PAYLOAD_LENGTH {
+                        let msgid = v2::msgid(buf);
+                        let len = *v2::len(buf);
+                        if let Some(limits) = get_payload_length(msgid) {
+                            if !limits.accepts_v2(len) {
+                                trace!("Invalid payload length: {len:?}, limits: {limits:?}");
+
+                                let context = self.discard_stx(buf, 2);
+                                return Ok(Some(Err(DecoderError::InvalidPayloadLength {
+                                    len,
+                                    min: limits.min,
+                                    max: limits.max,
+                                    context,
+                                })));
+                            }
                         }
                     }
 
@@ -284,10 +382,8 @@ impl<
                     let Some(extra_crc) = get_extra_crc(msgid) else {
                         trace!("Unknown message ID {msgid:?}. Data: {:?}", &buf[..]);
 
-                        buf.advance(V2Packet::STX_SIZE); // Discard this STX
-                        self.state = CodecState::WaitingForStx;
-
-                        return Ok(Some(Err(DecoderError::UnknownMessageID { msgid })));
+                        let context = self.discard_stx(buf, 2);
+                        return Ok(Some(Err(DecoderError::UnknownMessageID { msgid, context })));
                     };
                     let checksum_data = v2::checksum_data(buf);
                     let calculated_crc = calculate_crc(checksum_data, extra_crc);
@@ -298,12 +394,11 @@ impl<
                             "Invalid CRC: expected: {expected_crc:?}, calculated: {calculated_crc:?}. checksum_data: {checksum_data:?}"
                         );
 
-                        buf.advance(V2Packet::STX_SIZE); // Discard this STX
-                        self.state = CodecState::WaitingForStx;
-
+                        let context = self.discard_stx(buf, 2);
                         return Ok(Some(Err(DecoderError::InvalidCRC {
                             expected_crc,
                             calculated_crc,
+                            context,
                         })));
                     }
 
@@ -311,16 +406,17 @@ impl<
                 }
                 CodecState::CopyV2Packet { packet_size } if ACCEPT_V2 => {
                     let buf_packet = if SKIP_CRC_VALIDATION {
-                        // Copy the entire packet consuming the source buffer
-                        let mut buf_packet = BytesMut::with_capacity(packet_size);
-                        buf_packet[..packet_size].copy_from_slice(&buf[..packet_size]);
+                        // Copy the entire packet without consuming the source buffer
+                        let buf_packet = BytesMut::from(&buf[..packet_size]);
 
                         // Since it is a non validated packet, there might be other packets within this buffer, so we can only discard this STX
                         buf.advance(V2Packet::STX_SIZE);
+                        self.offset += V2Packet::STX_SIZE as u64;
 
                         buf_packet
                     } else {
                         let buf_packet = buf.split_to(packet_size);
+                        self.offset += packet_size as u64;
                         // buf.reserve(V2Packet::MAX_PACKET_SIZE);
 
                         buf_packet
@@ -348,6 +444,7 @@ impl<
         const DROP_INVALID_COMPID: bool,
         const SKIP_CRC_VALIDATION: bool,
         const DROP_INCOMPATIBLE: bool,
+        const VALIDATE_PAYLOAD_LENGTH: bool,
     > Encoder<Packet>
     for MavlinkCodec<
         ACCEPT_V1,
@@ -356,6 +453,7 @@ impl<
         DROP_INVALID_COMPID,
         SKIP_CRC_VALIDATION,
         DROP_INCOMPATIBLE,
+        VALIDATE_PAYLOAD_LENGTH,
     >
 {
     type Error = std::io::Error;
@@ -384,6 +482,11 @@ impl<
     }
 }
 
+#[inline(always)]
+pub fn get_payload_length(msgid: u32) -> Option<PayloadLength> {
+    ArduPilotMega.payload_length(msgid)
+}
+
 #[inline(always)]
 pub fn get_extra_crc(msgid: u32) -> Option<u8> {
     use mavlink::Message;
@@ -512,4 +615,150 @@ mod test_decode {
 
         assert_eq!(packet, expected_packet);
     }
+
+    #[test]
+    fn test_decode_v2_skip_crc_validation() {
+        let mut codec = MavlinkCodec::<false, true, false, false, true, false>::default();
+
+        let mut buf = BytesMut::with_capacity(V2Packet::MAX_PACKET_SIZE);
+
+        let expected_packet = {
+            let header = MavHeader {
+                system_id: 1,
+                component_id: 1,
+                sequence: 0,
+            };
+
+            let message_data = MavMessage::default_message_from_id(0).unwrap(); // Heartbeat message
+            let mut raw_v2_message = MAVLinkV2MessageRaw::new();
+            raw_v2_message.serialize_message(header, &message_data);
+
+            buf.put(raw_v2_message.raw_bytes());
+
+            Packet::V2(V2Packet::from(raw_v2_message))
+        };
+        let packet_size = buf.len();
+        let last_crc_byte = buf.len() - 1;
+        buf[last_crc_byte] ^= 0xFF; // Corrupt the CRC
+
+        let packet = codec.decode(&mut buf).unwrap().unwrap().unwrap();
+
+        assert_eq!(packet.header(), expected_packet.header());
+        assert_eq!(packet.payload(), expected_packet.payload());
+        assert_ne!(packet.checksum(), expected_packet.checksum());
+        // Only the STX is consumed, as there might be other packets within a non validated one
+        assert_eq!(buf.len(), packet_size - V2Packet::STX_SIZE);
+    }
+
+    #[test]
+    fn test_decode_error_context() {
+        let mut codec = MavlinkCodec::<true, true, false, false, false, false>::default();
+
+        let mut buf = BytesMut::with_capacity(V2Packet::MAX_PACKET_SIZE);
+        buf.put(&b"abc"[..]);
+
+        let valid_packet = {
+            let header = MavHeader {
+                system_id: 1,
+                component_id: 2,
+                sequence: 3,
+            };
+
+            let message_data = MavMessage::default_message_from_id(0).unwrap(); // Heartbeat message
+            let mut raw_v2_message = MAVLinkV2MessageRaw::new();
+            raw_v2_message.serialize_message(header, &message_data);
+
+            Packet::V2(V2Packet::from(raw_v2_message))
+        };
+        let mut corrupted_frame = valid_packet.as_slice().to_vec();
+        let last_crc_byte = corrupted_frame.len() - 1;
+        corrupted_frame[last_crc_byte] ^= 0xFF; // Corrupt the CRC
+
+        buf.put(&corrupted_frame[..]);
+        buf.put(valid_packet.as_slice());
+
+        let error = codec.decode(&mut buf).unwrap().unwrap().unwrap_err();
+        assert!(matches!(error, DecoderError::InvalidCRC { .. }));
+
+        let context = error.context().unwrap();
+        assert_eq!(context.version, 2);
+        assert_eq!(context.sequence, 3);
+        assert_eq!(context.system_id, 1);
+        assert_eq!(context.component_id, 2);
+        assert_eq!(context.message_id, 0);
+        assert_eq!(context.frame, corrupted_frame);
+        assert_eq!(context.offset, 3);
+
+        let packet = codec.decode(&mut buf).unwrap().unwrap().unwrap();
+        assert_eq!(packet, valid_packet);
+        assert_eq!(
+            codec.offset(),
+            (3 + corrupted_frame.len() + valid_packet.packet_size()) as u64
+        );
+    }
+
+    #[test]
+    fn test_decode_v1_invalid_payload_length() {
+        // A Heartbeat whose payload is one byte short, but with a matching CRC
+        let mut buf = BytesMut::with_capacity(V1Packet::MAX_PACKET_SIZE);
+        buf.put(&[V1_STX, 8, 0, 1, 1, 0][..]);
+        buf.put(&[0u8; 8][..]);
+        let crc = calculate_crc(&buf[1..], get_extra_crc(0).unwrap());
+        buf.put_u16_le(crc);
+
+        let mut codec = MavlinkCodec::<true, false, false, false, false, false>::default();
+        let packet = codec.decode(&mut buf.clone()).unwrap().unwrap();
+        assert!(packet.is_ok());
+
+        let mut codec = MavlinkCodec::<true, false, false, false, false, false, true>::default();
+        let error = codec.decode(&mut buf).unwrap().unwrap().unwrap_err();
+        assert!(matches!(
+            error,
+            DecoderError::InvalidPayloadLength {
+                len: 8,
+                min: 9,
+                max: 9,
+                ..
+            }
+        ));
+    }
+
+    #[test]
+    fn test_decode_v2_payload_length_validation() {
+        let mut codec = MavlinkCodec::<false, true, false, false, false, false, true>::default();
+
+        // Truncated V2 payloads are valid
+        let mut buf = BytesMut::with_capacity(V2Packet::MAX_PACKET_SIZE);
+        let expected_packet = {
+            let header = MavHeader {
+                system_id: 1,
+                component_id: 1,
+                sequence: 0,
+            };
+
+            let message_data = MavMessage::default_message_from_id(0).unwrap(); // Heartbeat message
+            let mut raw_v2_message = MAVLinkV2MessageRaw::new();
+            raw_v2_message.serialize_message(header, &message_data);
+
+            buf.put(raw_v2_message.raw_bytes());
+
+            Packet::V2(V2Packet::from(raw_v2_message))
+        };
+        assert!(*expected_packet.payload_length() < 9);
+
+        let packet = codec.decode(&mut buf).unwrap().unwrap().unwrap();
+        assert_eq!(packet, expected_packet);
+
+        // But not longer than the message
+        buf.put(&[V2_STX, 10, 0, 0, 0, 1, 1, 0, 0, 0][..]);
+        buf.put(&[1u8; 10][..]);
+        let crc = calculate_crc(&buf[1..], get_extra_crc(0).unwrap());
+        buf.put_u16_le(crc);
+
+        let error = codec.decode(&mut buf).unwrap().unwrap().unwrap_err();
+        assert!(matches!(
+            error,
+            DecoderError::InvalidPayloadLength { len: 10, .. }
+        ));
+    }
 }
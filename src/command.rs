@@ -0,0 +1,470 @@
+//! The command protocol: COMMAND_LONG and COMMAND_INT, acknowledged with COMMAND_ACK.
+
+use std::time::Duration;
+
+use log::debug;
+use tokio::time::Instant;
+
+use crate::{
+    client::{ClientError, Connection},
+    dialect::{ArduPilotMega, Dialect},
+    field::FieldValue,
+    Packet,
+};
+
+const COMMAND_INT: u32 = 75;
+const COMMAND_LONG: u32 = 76;
+const COMMAND_ACK: u32 = 77;
+
+/// MAV_RESULT, the outcome of a command
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MavResult {
+    Accepted,
+    TemporarilyRejected,
+    Denied,
+    Unsupported,
+    Failed,
+    /// Still running, with more acknowledgements to come
+    InProgress,
+    Cancelled,
+    CommandLongOnly,
+    CommandIntOnly,
+    UnsupportedFrame,
+    Unknown(u8),
+}
+
+impl From<u8> for MavResult {
+    fn from(value: u8) -> Self {
+        match value {
+            0 => MavResult::Accepted,
+            1 => MavResult::TemporarilyRejected,
+            2 => MavResult::Denied,
+            3 => MavResult::Unsupported,
+            4 => MavResult::Failed,
+            5 => MavResult::InProgress,
+            6 => MavResult::Cancelled,
+            7 => MavResult::CommandLongOnly,
+            8 => MavResult::CommandIntOnly,
+            9 => MavResult::UnsupportedFrame,
+            value => MavResult::Unknown(value),
+        }
+    }
+}
+
+/// A command sent with COMMAND_LONG
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct CommandLong {
+    /// MAV_CMD
+    pub command: u16,
+    pub params: [f32; 7],
+}
+
+/// A command sent with COMMAND_INT, for positions as scaled integers
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct CommandInt {
+    /// MAV_CMD
+    pub command: u16,
+    /// MAV_FRAME
+    pub frame: u8,
+    pub params: [f32; 4],
+    pub x: i32,
+    pub y: i32,
+    pub z: f32,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Command {
+    Long(CommandLong),
+    Int(CommandInt),
+}
+
+impl Command {
+    /// MAV_CMD
+    pub fn command(&self) -> u16 {
+        match self {
+            Command::Long(command) => command.command,
+            Command::Int(command) => command.command,
+        }
+    }
+
+    /// The fields of the message, as sent for the attempt `confirmation`
+    fn fields(&self, confirmation: u8) -> (u32, Vec<(&'static str, FieldValue)>) {
+        let params = |params: &[f32]| {
+            [
+                "param1", "param2", "param3", "param4", "param5", "param6", "param7",
+            ]
+            .into_iter()
+            .zip(params.iter())
+            .map(|(name, param)| (name, FieldValue::F32(*param)))
+            .collect::<Vec<_>>()
+        };
+
+        match self {
+            Command::Long(command) => {
+                let mut values = params(&command.params);
+                values.push(("command", FieldValue::U16(command.command)));
+                values.push(("confirmation", FieldValue::U8(confirmation)));
+
+                (COMMAND_LONG, values)
+            }
+            // COMMAND_INT has no confirmation, the retries look the same
+            Command::Int(command) => {
+                let mut values = params(&command.params);
+                values.push(("x", FieldValue::I32(command.x)));
+                values.push(("y", FieldValue::I32(command.y)));
+                values.push(("z", FieldValue::F32(command.z)));
+                values.push(("command", FieldValue::U16(command.command)));
+                values.push(("frame", FieldValue::U8(command.frame)));
+
+                (COMMAND_INT, values)
+            }
+        }
+    }
+}
+
+impl From<CommandLong> for Command {
+    fn from(command: CommandLong) -> Self {
+        Command::Long(command)
+    }
+}
+
+impl From<CommandInt> for Command {
+    fn from(command: CommandInt) -> Self {
+        Command::Int(command)
+    }
+}
+
+/// The final COMMAND_ACK of a command
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CommandAck {
+    /// MAV_CMD
+    pub command: u16,
+    pub result: MavResult,
+    /// For [`MavResult::InProgress`], the percentage done, or 255 if unknown
+    pub progress: u8,
+    /// Additional result information, specific to each command
+    pub result_param2: i32,
+}
+
+impl CommandAck {
+    fn from_packet<D: Dialect>(packet: &Packet, dialect: &D) -> Option<Self> {
+        if packet.message_id() != COMMAND_ACK {
+            return None;
+        }
+
+        let read = |name| {
+            packet
+                .field(name, dialect)
+                .and_then(|value| value.as_i64())
+                .unwrap_or_default()
+        };
+
+        Some(Self {
+            command: read("command") as u16,
+            result: MavResult::from(read("result") as u8),
+            progress: read("progress") as u8,
+            result_param2: read("result_param2") as i32,
+        })
+    }
+}
+
+/// Sends commands to a component, and waits for their acknowledgement.
+///
+/// Unacknowledged commands are sent again, with an incremented `confirmation` for COMMAND_LONG.
+/// Once a command is [`MavResult::InProgress`], it isn't sent again, and the client waits for its
+/// final acknowledgement for as long as progress is reported.
+#[derive(Debug)]
+pub struct CommandClient<D: Dialect = ArduPilotMega> {
+    connection: Connection<D>,
+    timeout: Duration,
+    retries: u32,
+    progress_timeout: Duration,
+}
+
+impl<D: Dialect> CommandClient<D> {
+    pub const DEFAULT_TIMEOUT: Duration = Duration::from_secs(1);
+    pub const DEFAULT_RETRIES: u32 = 2;
+    pub const DEFAULT_PROGRESS_TIMEOUT: Duration = Duration::from_secs(5);
+
+    pub fn new(connection: Connection<D>) -> Self {
+        Self {
+            connection,
+            timeout: Self::DEFAULT_TIMEOUT,
+            retries: Self::DEFAULT_RETRIES,
+            progress_timeout: Self::DEFAULT_PROGRESS_TIMEOUT,
+        }
+    }
+
+    /// How long to wait for an acknowledgement before sending again
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// How many times to send again unacknowledged commands
+    pub fn with_retries(mut self, retries: u32) -> Self {
+        self.retries = retries;
+        self
+    }
+
+    /// How long to wait for the next acknowledgement of a command in progress
+    pub fn with_progress_timeout(mut self, progress_timeout: Duration) -> Self {
+        self.progress_timeout = progress_timeout;
+        self
+    }
+
+    pub fn connection(&self) -> &Connection<D> {
+        &self.connection
+    }
+
+    pub fn into_connection(self) -> Connection<D> {
+        self.connection
+    }
+
+    /// Sends a command, returning its final acknowledgement, whatever its result
+    pub async fn send(&mut self, command: impl Into<Command>) -> Result<CommandAck, ClientError> {
+        self.send_with_progress(command, |_| ()).await
+    }
+
+    /// Sends a command, calling `on_progress` with the progress of each
+    /// [`MavResult::InProgress`] acknowledgement
+    pub async fn send_with_progress(
+        &mut self,
+        command: impl Into<Command>,
+        mut on_progress: impl FnMut(u8),
+    ) -> Result<CommandAck, ClientError> {
+        let command = command.into();
+        let mut attempts = 0;
+
+        while attempts <= self.retries {
+            let (message_id, values) = command.fields(attempts.min(u8::MAX as u32) as u8);
+            self.connection.send(message_id, &values).await?;
+            attempts += 1;
+
+            let mut deadline = Instant::now() + self.timeout;
+            let mut in_progress = false;
+
+            while let Some(packet) = self.connection.receive(deadline).await? {
+                let Some(ack) =
+                    CommandAck::from_packet(&packet, self.connection.builder().dialect())
+                else {
+                    continue;
+                };
+                if ack.command != command.command() {
+                    continue;
+                }
+
+                if ack.result != MavResult::InProgress {
+                    return Ok(ack);
+                }
+
+                debug!("Command {} in progress: {}%", ack.command, ack.progress);
+                on_progress(ack.progress);
+                in_progress = true;
+                deadline = Instant::now() + self.progress_timeout;
+            }
+
+            if in_progress {
+                break;
+            }
+        }
+
+        Err(ClientError::Timeout { attempts })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::builder::PacketBuilder;
+    use futures::{channel::mpsc, SinkExt, StreamExt};
+    use std::sync::{Arc, Mutex};
+
+    type Received = Arc<Mutex<Vec<Packet>>>;
+
+    /// A vehicle 1:1 answering each packet from the script, with (delay, answer) pairs
+    fn fake_vehicle<F>(mut script: F) -> (CommandClient, Received)
+    where
+        F: FnMut(&Packet, &PacketBuilder) -> Vec<(u64, Packet)> + Send + 'static,
+    {
+        let (client_sink, mut vehicle_stream) = mpsc::channel::<Packet>(16);
+        let (mut vehicle_sink, client_stream) = mpsc::channel::<Packet>(16);
+        let received = Received::default();
+
+        let vehicle_received = received.clone();
+        tokio::spawn(async move {
+            let builder = PacketBuilder::new(1, 1);
+            while let Some(packet) = vehicle_stream.next().await {
+                vehicle_received.lock().unwrap().push(packet.clone());
+
+                for (delay, answer) in script(&packet, &builder) {
+                    tokio::time::sleep(Duration::from_millis(delay)).await;
+                    if vehicle_sink.send(answer).await.is_err() {
+                        return;
+                    }
+                }
+            }
+        });
+
+        let connection = Connection::from_split(
+            client_stream,
+            client_sink,
+            PacketBuilder::new(255, 190),
+            (1, 1),
+        );
+
+        (CommandClient::new(connection), received)
+    }
+
+    fn ack(builder: &PacketBuilder, command: u16, result: u8, progress: u8) -> Packet {
+        ack_to(builder, 255, command, result, progress)
+    }
+
+    fn ack_to(
+        builder: &PacketBuilder,
+        target_system: u8,
+        command: u16,
+        result: u8,
+        progress: u8,
+    ) -> Packet {
+        builder
+            .build_fields(
+                COMMAND_ACK,
+                &[
+                    ("command", FieldValue::U16(command)),
+                    ("result", FieldValue::U8(result)),
+                    ("progress", FieldValue::U8(progress)),
+                    ("target_system", FieldValue::U8(target_system)),
+                    ("target_component", FieldValue::U8(190)),
+                ],
+            )
+            .unwrap()
+    }
+
+    fn read(packet: &Packet, name: &str) -> i64 {
+        packet
+            .field(name, &ArduPilotMega)
+            .and_then(|value| value.as_i64())
+            .unwrap()
+    }
+
+    const ARM: CommandLong = CommandLong {
+        command: 400, // MAV_CMD_COMPONENT_ARM_DISARM
+        params: [1.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0],
+    };
+
+    #[tokio::test(start_paused = true)]
+    async fn test_accepted() {
+        let (mut client, received) = fake_vehicle(|packet, builder| {
+            vec![(10, ack(builder, read(packet, "command") as u16, 0, 0))]
+        });
+
+        let ack = client.send(ARM).await.unwrap();
+        assert_eq!(ack.command, 400);
+        assert_eq!(ack.result, MavResult::Accepted);
+
+        let received = received.lock().unwrap();
+        assert_eq!(received.len(), 1);
+        assert_eq!(received[0].message_id(), COMMAND_LONG);
+        assert_eq!(received[0].target(&ArduPilotMega), Some((1, 1)));
+        assert_eq!(
+            received[0].field("param1", &ArduPilotMega),
+            Some(FieldValue::F32(1.0))
+        );
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_retries() {
+        // The first two commands are lost
+        let (mut client, received) =
+            fake_vehicle(|packet, builder| match read(packet, "confirmation") {
+                0 | 1 => vec![],
+                _ => vec![(10, ack(builder, 400, 0, 0))],
+            });
+
+        let start = Instant::now();
+        assert_eq!(client.send(ARM).await.unwrap().result, MavResult::Accepted);
+        assert_eq!(start.elapsed(), Duration::from_millis(2010));
+
+        let confirmations = received
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|packet| read(packet, "confirmation"))
+            .collect::<Vec<_>>();
+        assert_eq!(confirmations, vec![0, 1, 2]);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_timeout() {
+        let (client, received) = fake_vehicle(|_, _| vec![]);
+        let mut client = client.with_retries(3);
+
+        assert!(matches!(
+            client.send(ARM).await,
+            Err(ClientError::Timeout { attempts: 4 })
+        ));
+        assert_eq!(received.lock().unwrap().len(), 4);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_in_progress() {
+        // A calibration, taking longer than the timeout
+        let (mut client, received) = fake_vehicle(|packet, builder| {
+            let command = read(packet, "command") as u16;
+            vec![
+                (100, ack(builder, command, 5, 0)),
+                (1500, ack(builder, command, 5, 50)),
+                (1500, ack(builder, command, 0, 100)),
+            ]
+        });
+
+        let mut progress = Vec::new();
+        let ack = client
+            .send_with_progress(
+                CommandLong {
+                    command: 241, // MAV_CMD_PREFLIGHT_CALIBRATION
+                    ..Default::default()
+                },
+                |percentage| progress.push(percentage),
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(ack.result, MavResult::Accepted);
+        assert_eq!(progress, vec![0, 50]);
+        // Not sent again while in progress
+        assert_eq!(received.lock().unwrap().len(), 1);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_unrelated_acks() {
+        let (mut client, _) = fake_vehicle(|packet, builder| {
+            let command = read(packet, "command") as u16;
+            let other_vehicle = PacketBuilder::new(2, 1);
+
+            vec![
+                // Another command, another GCS, another vehicle
+                (10, ack(builder, 22, 0, 0)),
+                (10, ack_to(builder, 254, command, 0, 0)),
+                (10, ack(&other_vehicle, command, 0, 0)),
+                (10, ack(builder, command, 2, 0)),
+            ]
+        });
+
+        let ack = client
+            .send(CommandInt {
+                command: 192, // MAV_CMD_DO_REPOSITION
+                frame: 6,
+                x: 473_977_420,
+                y: 85_455_940,
+                z: 10.0,
+                ..Default::default()
+            })
+            .await
+            .unwrap();
+
+        assert_eq!(ack.command, 192);
+        assert_eq!(ack.result, MavResult::Denied);
+    }
+}
@@ -0,0 +1,206 @@
+//! Suppression of the packets received more than once, like through redundant links.
+
+use std::{
+    collections::{HashMap, VecDeque},
+    time::Duration,
+};
+
+use futures::{future, stream, Stream, StreamExt};
+use tokio::time::Instant;
+
+use crate::{v2::V2Packet, Packet};
+
+/// What identifies a packet among the recent ones
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum PacketKey {
+    /// (System ID, Component ID, sequence, Message ID, checksum)
+    Header(u8, u8, u8, u32, u16),
+    /// The signature has a timestamp, so it is unique to each signed packet
+    Signature([u8; V2Packet::SIGNATURE_SIZE]),
+}
+
+impl From<&Packet> for PacketKey {
+    fn from(packet: &Packet) -> Self {
+        if let Packet::V2(v2_packet) = packet {
+            if let Some(signature) = v2_packet
+                .signature()
+                .and_then(|signature| signature.try_into().ok())
+            {
+                return Self::Signature(signature);
+            }
+        }
+
+        Self::Header(
+            *packet.system_id(),
+            *packet.component_id(),
+            *packet.sequence(),
+            packet.message_id(),
+            packet.checksum(),
+        )
+    }
+}
+
+/// Recognizes the packets already seen within a time window.
+///
+/// Unlike the byte-exact equality of [`Packet`], packets are compared by their [`PacketKey`],
+/// and only the keys of the window are kept. The sequence wraps every 256 packets of a source,
+/// so the window should be shorter than the time the fastest source takes to send as many.
+#[derive(Debug)]
+pub struct Deduplicator {
+    window: Duration,
+    /// When each packet was first seen
+    keys: HashMap<PacketKey, Instant>,
+    arrivals: VecDeque<(Instant, PacketKey)>,
+}
+
+impl Deduplicator {
+    pub fn new(window: Duration) -> Self {
+        Self {
+            window,
+            keys: HashMap::new(),
+            arrivals: VecDeque::new(),
+        }
+    }
+
+    pub fn window(&self) -> Duration {
+        self.window
+    }
+
+    /// Returns whether the packet wasn't seen within the window, and remembers it
+    pub fn is_new(&mut self, packet: &Packet) -> bool {
+        self.seen_at(packet).is_none()
+    }
+
+    /// When the packet was first seen within the window, otherwise remembers it as seen now
+    pub fn seen_at(&mut self, packet: &Packet) -> Option<Instant> {
+        let now = Instant::now();
+        self.expire(now);
+
+        let key = PacketKey::from(packet);
+        if let Some(first_seen) = self.keys.get(&key) {
+            return Some(*first_seen);
+        }
+
+        self.keys.insert(key, now);
+        self.arrivals.push_back((now, key));
+
+        None
+    }
+
+    /// Number of packets remembered
+    pub fn len(&self) -> usize {
+        self.keys.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.keys.is_empty()
+    }
+
+    fn expire(&mut self, now: Instant) {
+        while let Some((arrival, key)) = self.arrivals.front() {
+            if now.duration_since(*arrival) < self.window {
+                break;
+            }
+
+            self.keys.remove(key);
+            self.arrivals.pop_front();
+        }
+    }
+
+    /// Removes the duplicates from a stream
+    pub fn deduplicate<S: Stream<Item = Packet>>(
+        mut self,
+        stream: S,
+    ) -> impl Stream<Item = Packet> {
+        stream.filter(move |packet| future::ready(self.is_new(packet)))
+    }
+
+    /// Merges several streams into one, without duplicates.
+    ///
+    /// The first copy of each packet goes through, from whichever stream is the fastest.
+    pub fn merge<S: Stream<Item = Packet>>(
+        self,
+        streams: impl IntoIterator<Item = S>,
+    ) -> impl Stream<Item = Packet> {
+        self.deduplicate(stream::select_all(streams.into_iter().map(Box::pin)))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test_util::{create_v2_packet, header};
+    use crate::v2::MAVLINK_IFLAG_SIGNED;
+    use bytes::Bytes;
+
+    /// The packet with the signed flag and a signature, which isn't verified here
+    fn sign(packet: &Packet, timestamp: u8) -> Packet {
+        let mut bytes = packet.as_slice().to_vec();
+        bytes[2] |= MAVLINK_IFLAG_SIGNED;
+        bytes.push(1); // link ID
+        bytes.extend([timestamp, 0, 0, 0, 0, 0]);
+        bytes.extend([0xAA; 6]);
+
+        Packet::V2(V2Packet::new(Bytes::from(bytes)))
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_window() {
+        let mut deduplicator = Deduplicator::new(Duration::from_secs(1));
+
+        assert!(deduplicator.is_new(&create_v2_packet(header(1, 1, 0), 0)));
+        assert!(!deduplicator.is_new(&create_v2_packet(header(1, 1, 0), 0)));
+        assert!(deduplicator.is_new(&create_v2_packet(header(1, 1, 1), 0)));
+
+        tokio::time::sleep(Duration::from_millis(500)).await;
+        assert!(!deduplicator.is_new(&create_v2_packet(header(1, 1, 0), 0)));
+        assert_eq!(deduplicator.len(), 2);
+
+        tokio::time::sleep(Duration::from_millis(500)).await;
+        assert!(deduplicator.is_new(&create_v2_packet(header(1, 1, 0), 0)));
+        assert_eq!(deduplicator.len(), 1);
+
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        assert_eq!(
+            deduplicator.seen_at(&create_v2_packet(header(1, 1, 0), 0)),
+            Some(Instant::now() - Duration::from_millis(100))
+        );
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_signed_packets() {
+        let mut deduplicator = Deduplicator::new(Duration::from_secs(1));
+        let packet = create_v2_packet(header(1, 1, 0), 0);
+
+        // Same header and checksum, but signed at different times
+        assert!(deduplicator.is_new(&sign(&packet, 1)));
+        assert!(deduplicator.is_new(&sign(&packet, 2)));
+        assert!(!deduplicator.is_new(&sign(&packet, 2)));
+
+        assert!(matches!(
+            PacketKey::from(&sign(&packet, 1)),
+            PacketKey::Signature(_)
+        ));
+        assert!(matches!(PacketKey::from(&packet), PacketKey::Header(..)));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_merge() {
+        let radio = futures::stream::iter(
+            (0..10).map(|sequence| create_v2_packet(header(1, 1, sequence), 0)),
+        );
+        // The LTE link lost some packets
+        let lte = futures::stream::iter(
+            [0, 2, 3, 7, 9, 10, 11].map(|sequence| create_v2_packet(header(1, 1, sequence), 0)),
+        );
+
+        let mut sequences = Deduplicator::new(Duration::from_secs(1))
+            .merge([radio.boxed(), lte.boxed()])
+            .map(|packet| *packet.sequence())
+            .collect::<Vec<_>>()
+            .await;
+
+        sequences.sort();
+        assert_eq!(sequences, (0..12).collect::<Vec<_>>());
+    }
+}
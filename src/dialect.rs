@@ -0,0 +1,1090 @@
+//! MAVLink dialects, which give meaning to the Message IDs.
+
+use mavlink::{MavlinkVersion, Message};
+
+use crate::field::{
+    Field,
+    FieldType::{self, *},
+};
+
+/// Bounds of the payload length of a message
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PayloadLength {
+    /// Without extension fields, the fixed length of MAVLink V1 payloads
+    pub min: u8,
+    /// With all the extension fields
+    pub max: u8,
+}
+
+impl PayloadLength {
+    #[inline(always)]
+    pub fn accepts_v1(&self, len: u8) -> bool {
+        (self.min..=self.max).contains(&len)
+    }
+
+    /// MAVLink V2 payloads have their trailing zeroes truncated, down to a single byte
+    #[inline(always)]
+    pub fn accepts_v2(&self, len: u8) -> bool {
+        (1..=self.max).contains(&len)
+    }
+}
+
+/// A MAVLink dialect, backed by a rust-mavlink generated message set
+pub trait Dialect {
+    type Message: Message;
+
+    /// The message name, or `None` if the dialect doesn't know this Message ID
+    fn message_name(&self, message_id: u32) -> Option<&'static str> {
+        Self::Message::default_message_from_id(message_id)
+            .ok()
+            .map(|message| message.message_name())
+    }
+
+    /// The CRC_EXTRA, or `None` if the dialect doesn't know this Message ID
+    fn extra_crc(&self, message_id: u32) -> Option<u8> {
+        Self::Message::default_message_from_id(message_id)
+            .ok()
+            .map(|_| Self::Message::extra_crc(message_id))
+    }
+
+    /// The payload length bounds, or `None` if the dialect doesn't know this Message ID.
+    ///
+    /// By default, messages are assumed to have no extension fields.
+    fn payload_length(&self, message_id: u32) -> Option<PayloadLength> {
+        let max = max_payload_length::<Self::Message>(message_id)?;
+
+        Some(PayloadLength { min: max, max })
+    }
+
+    /// The payload fields in wire order, or `None` if the dialect doesn't describe this Message ID.
+    ///
+    /// rust-mavlink doesn't expose field metadata, so dialects describe the messages they need.
+    fn fields(&self, _message_id: u32) -> Option<&'static [Field]> {
+        None
+    }
+
+    /// Where the target fields are, or `None` if this message isn't targeted.
+    ///
+    /// By default, they are looked up in [`Dialect::fields`].
+    fn target_offsets(&self, message_id: u32) -> Option<TargetOffsets> {
+        target_offsets(self.fields(message_id)?)
+    }
+}
+
+/// Offsets of the target fields in the payload
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TargetOffsets {
+    pub system: u8,
+    /// `None` for messages targeting a whole system
+    pub component: Option<u8>,
+}
+
+fn target_offsets(fields: &[Field]) -> Option<TargetOffsets> {
+    let offset = |name| {
+        fields
+            .iter()
+            .find(|field| field.name == name)
+            .map(|field| field.offset)
+    };
+
+    Some(TargetOffsets {
+        system: offset("target_system")?,
+        component: offset("target_component"),
+    })
+}
+
+/// Length of the payload rust-mavlink serializes
+fn max_payload_length<M: Message>(message_id: u32) -> Option<u8> {
+    let message = M::default_message_from_id(message_id).ok()?;
+
+    let mut payload = [0u8; 255];
+    Some(message.ser(MavlinkVersion::V1, &mut payload) as u8)
+}
+
+/// The ArduPilot dialect, a superset of the common dialect
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct ArduPilotMega;
+
+impl Dialect for ArduPilotMega {
+    type Message = mavlink::ardupilotmega::MavMessage;
+
+    fn payload_length(&self, message_id: u32) -> Option<PayloadLength> {
+        let index = ARDUPILOTMEGA_PAYLOAD_LENGTHS
+            .binary_search_by_key(&message_id, |(id, ..)| *id)
+            .ok()?;
+        let (_, min, max) = ARDUPILOTMEGA_PAYLOAD_LENGTHS[index];
+
+        Some(PayloadLength { min, max })
+    }
+
+    fn fields(&self, message_id: u32) -> Option<&'static [Field]> {
+        ardupilotmega_fields()
+            .find(|(id, _)| *id == message_id)
+            .map(|(_, fields)| *fields)
+    }
+
+    /// Covers every message of the dialect, not only the ones described by [`Dialect::fields`]
+    fn target_offsets(&self, message_id: u32) -> Option<TargetOffsets> {
+        let index = ARDUPILOTMEGA_TARGET_OFFSETS
+            .binary_search_by_key(&message_id, |(id, _)| *id)
+            .ok()?;
+
+        Some(ARDUPILOTMEGA_TARGET_OFFSETS[index].1)
+    }
+}
+
+/// `(Message ID, length without the extension fields, length with them)` of every message, by
+/// Message ID.
+///
+/// rust-mavlink is built without the extension fields, so it only knows about the first length.
+const ARDUPILOTMEGA_PAYLOAD_LENGTHS: &[(u32, u8, u8)] = &[
+    (0, 9, 9),         // HEARTBEAT
+    (1, 31, 43),       // SYS_STATUS
+    (2, 12, 12),       // SYSTEM_TIME
+    (4, 14, 14),       // PING
+    (5, 28, 28),       // CHANGE_OPERATOR_CONTROL
+    (6, 3, 3),         // CHANGE_OPERATOR_CONTROL_ACK
+    (7, 32, 32),       // AUTH_KEY
+    (8, 36, 36),       // LINK_NODE_STATUS
+    (11, 6, 6),        // SET_MODE
+    (20, 20, 20),      // PARAM_REQUEST_READ
+    (21, 2, 2),        // PARAM_REQUEST_LIST
+    (22, 25, 25),      // PARAM_VALUE
+    (23, 23, 23),      // PARAM_SET
+    (24, 30, 52),      // GPS_RAW_INT
+    (25, 101, 101),    // GPS_STATUS
+    (26, 22, 24),      // SCALED_IMU
+    (27, 26, 29),      // RAW_IMU
+    (28, 16, 16),      // RAW_PRESSURE
+    (29, 14, 16),      // SCALED_PRESSURE
+    (30, 28, 28),      // ATTITUDE
+    (31, 32, 48),      // ATTITUDE_QUATERNION
+    (32, 28, 28),      // LOCAL_POSITION_NED
+    (33, 28, 28),      // GLOBAL_POSITION_INT
+    (34, 22, 22),      // RC_CHANNELS_SCALED
+    (35, 22, 22),      // RC_CHANNELS_RAW
+    (36, 21, 37),      // SERVO_OUTPUT_RAW
+    (37, 6, 7),        // MISSION_REQUEST_PARTIAL_LIST
+    (38, 6, 7),        // MISSION_WRITE_PARTIAL_LIST
+    (39, 37, 38),      // MISSION_ITEM
+    (40, 4, 5),        // MISSION_REQUEST
+    (41, 4, 4),        // MISSION_SET_CURRENT
+    (42, 2, 18),       // MISSION_CURRENT
+    (43, 2, 3),        // MISSION_REQUEST_LIST
+    (44, 4, 9),        // MISSION_COUNT
+    (45, 2, 3),        // MISSION_CLEAR_ALL
+    (46, 2, 2),        // MISSION_ITEM_REACHED
+    (47, 3, 8),        // MISSION_ACK
+    (48, 13, 21),      // SET_GPS_GLOBAL_ORIGIN
+    (49, 12, 20),      // GPS_GLOBAL_ORIGIN
+    (50, 37, 37),      // PARAM_MAP_RC
+    (51, 4, 5),        // MISSION_REQUEST_INT
+    (54, 27, 27),      // SAFETY_SET_ALLOWED_AREA
+    (55, 25, 25),      // SAFETY_ALLOWED_AREA
+    (61, 72, 72),      // ATTITUDE_QUATERNION_COV
+    (62, 26, 26),      // NAV_CONTROLLER_OUTPUT
+    (63, 181, 181),    // GLOBAL_POSITION_INT_COV
+    (64, 225, 225),    // LOCAL_POSITION_NED_COV
+    (65, 42, 42),      // RC_CHANNELS
+    (66, 6, 6),        // REQUEST_DATA_STREAM
+    (67, 4, 4),        // DATA_STREAM
+    (69, 11, 30),      // MANUAL_CONTROL
+    (70, 18, 38),      // RC_CHANNELS_OVERRIDE
+    (73, 37, 38),      // MISSION_ITEM_INT
+    (74, 20, 20),      // VFR_HUD
+    (75, 35, 35),      // COMMAND_INT
+    (76, 33, 33),      // COMMAND_LONG
+    (77, 3, 10),       // COMMAND_ACK
+    (80, 4, 4),        // COMMAND_CANCEL
+    (81, 22, 22),      // MANUAL_SETPOINT
+    (82, 39, 51),      // SET_ATTITUDE_TARGET
+    (83, 37, 37),      // ATTITUDE_TARGET
+    (84, 53, 53),      // SET_POSITION_TARGET_LOCAL_NED
+    (85, 51, 51),      // POSITION_TARGET_LOCAL_NED
+    (86, 53, 53),      // SET_POSITION_TARGET_GLOBAL_INT
+    (87, 51, 51),      // POSITION_TARGET_GLOBAL_INT
+    (89, 28, 28),      // LOCAL_POSITION_NED_SYSTEM_GLOBAL_OFFSET
+    (90, 56, 56),      // HIL_STATE
+    (91, 42, 42),      // HIL_CONTROLS
+    (92, 33, 33),      // HIL_RC_INPUTS_RAW
+    (93, 81, 81),      // HIL_ACTUATOR_CONTROLS
+    (100, 26, 34),     // OPTICAL_FLOW
+    (101, 32, 117),    // GLOBAL_VISION_POSITION_ESTIMATE
+    (102, 32, 117),    // VISION_POSITION_ESTIMATE
+    (103, 20, 57),     // VISION_SPEED_ESTIMATE
+    (104, 32, 116),    // VICON_POSITION_ESTIMATE
+    (105, 62, 63),     // HIGHRES_IMU
+    (106, 44, 44),     // OPTICAL_FLOW_RAD
+    (107, 64, 65),     // HIL_SENSOR
+    (108, 84, 92),     // SIM_STATE
+    (109, 9, 9),       // RADIO_STATUS
+    (110, 254, 254),   // FILE_TRANSFER_PROTOCOL
+    (111, 16, 18),     // TIMESYNC
+    (112, 12, 12),     // CAMERA_TRIGGER
+    (113, 36, 39),     // HIL_GPS
+    (114, 44, 44),     // HIL_OPTICAL_FLOW
+    (115, 64, 64),     // HIL_STATE_QUATERNION
+    (116, 22, 24),     // SCALED_IMU2
+    (117, 6, 6),       // LOG_REQUEST_LIST
+    (118, 14, 14),     // LOG_ENTRY
+    (119, 12, 12),     // LOG_REQUEST_DATA
+    (120, 97, 97),     // LOG_DATA
+    (121, 2, 2),       // LOG_ERASE
+    (122, 2, 2),       // LOG_REQUEST_END
+    (123, 113, 113),   // GPS_INJECT_DATA
+    (124, 35, 57),     // GPS2_RAW
+    (125, 6, 6),       // POWER_STATUS
+    (126, 79, 81),     // SERIAL_CONTROL
+    (127, 35, 35),     // GPS_RTK
+    (128, 35, 35),     // GPS2_RTK
+    (129, 22, 24),     // SCALED_IMU3
+    (130, 13, 13),     // DATA_TRANSMISSION_HANDSHAKE
+    (131, 255, 255),   // ENCAPSULATED_DATA
+    (132, 14, 39),     // DISTANCE_SENSOR
+    (133, 18, 18),     // TERRAIN_REQUEST
+    (134, 43, 43),     // TERRAIN_DATA
+    (135, 8, 8),       // TERRAIN_CHECK
+    (136, 22, 22),     // TERRAIN_REPORT
+    (137, 14, 16),     // SCALED_PRESSURE2
+    (138, 36, 120),    // ATT_POS_MOCAP
+    (139, 43, 43),     // SET_ACTUATOR_CONTROL_TARGET
+    (140, 41, 41),     // ACTUATOR_CONTROL_TARGET
+    (141, 32, 32),     // ALTITUDE
+    (142, 243, 243),   // RESOURCE_REQUEST
+    (143, 14, 16),     // SCALED_PRESSURE3
+    (144, 93, 93),     // FOLLOW_TARGET
+    (146, 100, 100),   // CONTROL_SYSTEM_STATE
+    (147, 36, 54),     // BATTERY_STATUS
+    (148, 60, 78),     // AUTOPILOT_VERSION
+    (149, 30, 60),     // LANDING_TARGET
+    (150, 42, 42),     // SENSOR_OFFSETS
+    (151, 8, 8),       // SET_MAG_OFFSETS
+    (152, 4, 8),       // MEMINFO
+    (153, 12, 12),     // AP_ADC
+    (154, 15, 15),     // DIGICAM_CONFIGURE
+    (155, 13, 13),     // DIGICAM_CONTROL
+    (156, 6, 6),       // MOUNT_CONFIGURE
+    (157, 15, 15),     // MOUNT_CONTROL
+    (158, 14, 15),     // MOUNT_STATUS
+    (160, 12, 12),     // FENCE_POINT
+    (161, 3, 3),       // FENCE_FETCH_POINT
+    (162, 8, 9),       // FENCE_STATUS
+    (163, 28, 28),     // AHRS
+    (164, 44, 44),     // SIMSTATE
+    (165, 3, 3),       // HWSTATUS
+    (166, 9, 9),       // RADIO
+    (167, 22, 22),     // LIMITS_STATUS
+    (168, 12, 12),     // WIND
+    (169, 18, 18),     // DATA16
+    (170, 34, 34),     // DATA32
+    (171, 66, 66),     // DATA64
+    (172, 98, 98),     // DATA96
+    (173, 8, 8),       // RANGEFINDER
+    (174, 48, 48),     // AIRSPEED_AUTOCAL
+    (175, 19, 19),     // RALLY_POINT
+    (176, 3, 3),       // RALLY_FETCH_POINT
+    (177, 20, 20),     // COMPASSMOT_STATUS
+    (178, 24, 24),     // AHRS2
+    (179, 29, 29),     // CAMERA_STATUS
+    (180, 45, 47),     // CAMERA_FEEDBACK
+    (181, 4, 4),       // BATTERY2
+    (182, 40, 40),     // AHRS3
+    (183, 2, 2),       // AUTOPILOT_VERSION_REQUEST
+    (184, 206, 206),   // REMOTE_LOG_DATA_BLOCK
+    (185, 7, 7),       // REMOTE_LOG_BLOCK_STATUS
+    (186, 29, 29),     // LED_CONTROL
+    (191, 27, 27),     // MAG_CAL_PROGRESS
+    (192, 44, 54),     // MAG_CAL_REPORT
+    (193, 22, 26),     // EKF_STATUS_REPORT
+    (194, 25, 33),     // PID_TUNING
+    (195, 37, 37),     // DEEPSTALL
+    (200, 42, 42),     // GIMBAL_REPORT
+    (201, 14, 14),     // GIMBAL_CONTROL
+    (214, 8, 8),       // GIMBAL_TORQUE_CMD_REPORT
+    (215, 3, 3),       // GOPRO_HEARTBEAT
+    (216, 3, 3),       // GOPRO_GET_REQUEST
+    (217, 6, 6),       // GOPRO_GET_RESPONSE
+    (218, 7, 7),       // GOPRO_SET_REQUEST
+    (219, 2, 2),       // GOPRO_SET_RESPONSE
+    (225, 65, 73),     // EFI_STATUS
+    (226, 8, 8),       // RPM
+    (230, 42, 42),     // ESTIMATOR_STATUS
+    (231, 40, 40),     // WIND_COV
+    (232, 63, 65),     // GPS_INPUT
+    (233, 182, 182),   // GPS_RTCM_DATA
+    (234, 40, 40),     // HIGH_LATENCY
+    (235, 42, 42),     // HIGH_LATENCY2
+    (241, 32, 32),     // VIBRATION
+    (242, 52, 60),     // HOME_POSITION
+    (243, 53, 61),     // SET_HOME_POSITION
+    (244, 6, 6),       // MESSAGE_INTERVAL
+    (245, 2, 2),       // EXTENDED_SYS_STATE
+    (246, 38, 38),     // ADSB_VEHICLE
+    (247, 19, 19),     // COLLISION
+    (248, 254, 254),   // V2_EXTENSION
+    (249, 36, 36),     // MEMORY_VECT
+    (250, 30, 30),     // DEBUG_VECT
+    (251, 18, 18),     // NAMED_VALUE_FLOAT
+    (252, 18, 18),     // NAMED_VALUE_INT
+    (253, 51, 54),     // STATUSTEXT
+    (254, 9, 9),       // DEBUG
+    (256, 42, 42),     // SETUP_SIGNING
+    (257, 9, 9),       // BUTTON_CHANGE
+    (258, 32, 232),    // PLAY_TUNE
+    (259, 235, 237),   // CAMERA_INFORMATION
+    (260, 5, 14),      // CAMERA_SETTINGS
+    (261, 27, 61),     // STORAGE_INFORMATION
+    (262, 18, 23),     // CAMERA_CAPTURE_STATUS
+    (263, 255, 255),   // CAMERA_IMAGE_CAPTURED
+    (264, 28, 32),     // FLIGHT_INFORMATION
+    (265, 16, 20),     // MOUNT_ORIENTATION
+    (266, 255, 255),   // LOGGING_DATA
+    (267, 255, 255),   // LOGGING_DATA_ACKED
+    (268, 4, 4),       // LOGGING_ACK
+    (269, 213, 215),   // VIDEO_STREAM_INFORMATION
+    (270, 19, 20),     // VIDEO_STREAM_STATUS
+    (271, 52, 53),     // CAMERA_FOV_STATUS
+    (275, 31, 32),     // CAMERA_TRACKING_IMAGE_STATUS
+    (276, 49, 50),     // CAMERA_TRACKING_GEO_STATUS
+    (277, 30, 30),     // CAMERA_THERMAL_RANGE
+    (280, 33, 33),     // GIMBAL_MANAGER_INFORMATION
+    (281, 13, 13),     // GIMBAL_MANAGER_STATUS
+    (282, 35, 35),     // GIMBAL_MANAGER_SET_ATTITUDE
+    (283, 144, 145),   // GIMBAL_DEVICE_INFORMATION
+    (284, 32, 32),     // GIMBAL_DEVICE_SET_ATTITUDE
+    (285, 40, 49),     // GIMBAL_DEVICE_ATTITUDE_STATUS
+    (286, 53, 57),     // AUTOPILOT_STATE_FOR_GIMBAL_DEVICE
+    (287, 23, 23),     // GIMBAL_MANAGER_SET_PITCHYAW
+    (288, 23, 23),     // GIMBAL_MANAGER_SET_MANUAL_CONTROL
+    (290, 46, 46),     // ESC_INFO
+    (291, 57, 57),     // ESC_STATUS
+    (299, 96, 98),     // WIFI_CONFIG_AP
+    (300, 22, 22),     // PROTOCOL_VERSION
+    (301, 58, 58),     // AIS_VESSEL
+    (310, 17, 17),     // UAVCAN_NODE_STATUS
+    (311, 116, 116),   // UAVCAN_NODE_INFO
+    (320, 20, 20),     // PARAM_EXT_REQUEST_READ
+    (321, 2, 2),       // PARAM_EXT_REQUEST_LIST
+    (322, 149, 149),   // PARAM_EXT_VALUE
+    (323, 147, 147),   // PARAM_EXT_SET
+    (324, 146, 146),   // PARAM_EXT_ACK
+    (330, 158, 167),   // OBSTACLE_DISTANCE
+    (331, 230, 233),   // ODOMETRY
+    (332, 239, 239),   // TRAJECTORY_REPRESENTATION_WAYPOINTS
+    (333, 109, 109),   // TRAJECTORY_REPRESENTATION_BEZIER
+    (334, 10, 10),     // CELLULAR_STATUS
+    (335, 24, 24),     // ISBD_LINK_STATUS
+    (336, 84, 84),     // CELLULAR_CONFIG
+    (339, 5, 5),       // RAW_RPM
+    (340, 70, 70),     // UTM_GLOBAL_POSITION
+    (350, 20, 252),    // DEBUG_FLOAT_ARRAY
+    (360, 25, 25),     // ORBIT_EXECUTION_STATUS
+    (370, 87, 109),    // SMART_BATTERY_INFO
+    (371, 26, 26),     // FUEL_STATUS
+    (372, 140, 140),   // BATTERY_INFO
+    (373, 42, 42),     // GENERATOR_STATUS
+    (375, 140, 140),   // ACTUATOR_OUTPUT_STATUS
+    (380, 20, 20),     // TIME_ESTIMATE_TO_TARGET
+    (385, 133, 133),   // TUNNEL
+    (386, 16, 16),     // CAN_FRAME
+    (387, 72, 72),     // CANFD_FRAME
+    (388, 37, 37),     // CAN_FILTER_MODIFY
+    (390, 238, 238),   // ONBOARD_COMPUTER_STATUS
+    (395, 212, 212),   // COMPONENT_INFORMATION
+    (396, 160, 160),   // COMPONENT_INFORMATION_BASIC
+    (397, 108, 108),   // COMPONENT_METADATA
+    (400, 254, 254),   // PLAY_TUNE_V2
+    (401, 6, 6),       // SUPPORTED_TUNES
+    (410, 53, 53),     // EVENT
+    (411, 3, 3),       // CURRENT_EVENT_SEQUENCE
+    (412, 6, 6),       // REQUEST_EVENT
+    (413, 7, 7),       // RESPONSE_EVENT_ERROR
+    (435, 46, 46),     // AVAILABLE_MODES
+    (436, 9, 9),       // CURRENT_MODE
+    (437, 1, 1),       // AVAILABLE_MODES_MONITOR
+    (440, 35, 35),     // ILLUMINATOR_STATUS
+    (9000, 137, 137),  // WHEEL_DISTANCE
+    (9005, 34, 34),    // WINCH_STATUS
+    (10001, 20, 20),   // UAVIONIX_ADSB_OUT_CFG
+    (10002, 41, 41),   // UAVIONIX_ADSB_OUT_DYNAMIC
+    (10003, 1, 1),     // UAVIONIX_ADSB_TRANSCEIVER_HEALTH_REPORT
+    (10004, 9, 9),     // UAVIONIX_ADSB_OUT_CFG_REGISTRATION
+    (10005, 9, 9),     // UAVIONIX_ADSB_OUT_CFG_FLIGHTID
+    (10006, 4, 4),     // UAVIONIX_ADSB_GET
+    (10007, 17, 17),   // UAVIONIX_ADSB_OUT_CONTROL
+    (10008, 14, 14),   // UAVIONIX_ADSB_OUT_STATUS
+    (10151, 85, 85),   // LOWEHEISER_GOV_EFI
+    (11000, 51, 52),   // DEVICE_OP_READ
+    (11001, 135, 136), // DEVICE_OP_READ_REPLY
+    (11002, 179, 180), // DEVICE_OP_WRITE
+    (11003, 5, 5),     // DEVICE_OP_WRITE_REPLY
+    (11004, 232, 232), // SECURE_COMMAND
+    (11005, 230, 230), // SECURE_COMMAND_REPLY
+    (11010, 49, 49),   // ADAP_TUNING
+    (11011, 44, 44),   // VISION_POSITION_DELTA
+    (11020, 16, 16),   // AOA_SSA
+    (11030, 44, 44),   // ESC_TELEMETRY_1_TO_4
+    (11031, 44, 44),   // ESC_TELEMETRY_5_TO_8
+    (11032, 44, 44),   // ESC_TELEMETRY_9_TO_12
+    (11033, 37, 37),   // OSD_PARAM_CONFIG
+    (11034, 5, 5),     // OSD_PARAM_CONFIG_REPLY
+    (11035, 8, 8),     // OSD_PARAM_SHOW_CONFIG
+    (11036, 34, 34),   // OSD_PARAM_SHOW_CONFIG_REPLY
+    (11037, 28, 28),   // OBSTACLE_DISTANCE_3D
+    (11038, 38, 38),   // WATER_DEPTH
+    (11039, 9, 9),     // MCU_STATUS
+    (11040, 44, 44),   // ESC_TELEMETRY_13_TO_16
+    (11041, 44, 44),   // ESC_TELEMETRY_17_TO_20
+    (11042, 44, 44),   // ESC_TELEMETRY_21_TO_24
+    (11043, 44, 44),   // ESC_TELEMETRY_25_TO_28
+    (11044, 44, 44),   // ESC_TELEMETRY_29_TO_32
+    (12900, 44, 44),   // OPEN_DRONE_ID_BASIC_ID
+    (12901, 59, 59),   // OPEN_DRONE_ID_LOCATION
+    (12902, 53, 53),   // OPEN_DRONE_ID_AUTHENTICATION
+    (12903, 46, 46),   // OPEN_DRONE_ID_SELF_ID
+    (12904, 54, 54),   // OPEN_DRONE_ID_SYSTEM
+    (12905, 43, 43),   // OPEN_DRONE_ID_OPERATOR_ID
+    (12915, 249, 249), // OPEN_DRONE_ID_MESSAGE_PACK
+    (12918, 51, 51),   // OPEN_DRONE_ID_ARM_STATUS
+    (12919, 18, 18),   // OPEN_DRONE_ID_SYSTEM_UPDATE
+    (12920, 5, 5),     // HYGROMETER_SENSOR
+    (42000, 1, 1),     // ICAROUS_HEARTBEAT
+    (42001, 46, 46),   // ICAROUS_KINEMATIC_BANDS
+    (50001, 0, 0),     // CUBEPILOT_RAW_RC
+    (50002, 246, 246), // HERELINK_VIDEO_STREAM_INFORMATION
+    (50003, 0, 0),     // HERELINK_TELEM
+    (50004, 10, 10),   // CUBEPILOT_FIRMWARE_UPDATE_START
+    (50005, 6, 6),     // CUBEPILOT_FIRMWARE_UPDATE_RESP
+    (52000, 100, 100), // AIRLINK_AUTH
+    (52001, 1, 1),     // AIRLINK_AUTH_RESPONSE
+];
+
+/// Target fields of every targeted message, by Message ID.
+///
+/// `MANUAL_CONTROL` calls its target system field `target`, and the target fields of
+/// `COMMAND_ACK`, `TIMESYNC` and `SERIAL_CONTROL` are extension fields.
+const ARDUPILOTMEGA_TARGET_OFFSETS: &[(u32, TargetOffsets)] = &[
+    (4, targets(12, Some(13))),     // PING
+    (5, targets(0, None)),          // CHANGE_OPERATOR_CONTROL
+    (11, targets(4, None)),         // SET_MODE
+    (20, targets(2, Some(3))),      // PARAM_REQUEST_READ
+    (21, targets(0, Some(1))),      // PARAM_REQUEST_LIST
+    (23, targets(4, Some(5))),      // PARAM_SET
+    (37, targets(4, Some(5))),      // MISSION_REQUEST_PARTIAL_LIST
+    (38, targets(4, Some(5))),      // MISSION_WRITE_PARTIAL_LIST
+    (39, targets(32, Some(33))),    // MISSION_ITEM
+    (40, targets(2, Some(3))),      // MISSION_REQUEST
+    (41, targets(2, Some(3))),      // MISSION_SET_CURRENT
+    (43, targets(0, Some(1))),      // MISSION_REQUEST_LIST
+    (44, targets(2, Some(3))),      // MISSION_COUNT
+    (45, targets(0, Some(1))),      // MISSION_CLEAR_ALL
+    (47, targets(0, Some(1))),      // MISSION_ACK
+    (48, targets(12, None)),        // SET_GPS_GLOBAL_ORIGIN
+    (50, targets(18, Some(19))),    // PARAM_MAP_RC
+    (51, targets(2, Some(3))),      // MISSION_REQUEST_INT
+    (54, targets(24, Some(25))),    // SAFETY_SET_ALLOWED_AREA
+    (66, targets(2, Some(3))),      // REQUEST_DATA_STREAM
+    (69, targets(10, None)),        // MANUAL_CONTROL
+    (70, targets(16, Some(17))),    // RC_CHANNELS_OVERRIDE
+    (73, targets(32, Some(33))),    // MISSION_ITEM_INT
+    (75, targets(30, Some(31))),    // COMMAND_INT
+    (76, targets(30, Some(31))),    // COMMAND_LONG
+    (77, targets(8, Some(9))),      // COMMAND_ACK
+    (80, targets(2, Some(3))),      // COMMAND_CANCEL
+    (82, targets(36, Some(37))),    // SET_ATTITUDE_TARGET
+    (84, targets(50, Some(51))),    // SET_POSITION_TARGET_LOCAL_NED
+    (86, targets(50, Some(51))),    // SET_POSITION_TARGET_GLOBAL_INT
+    (110, targets(1, Some(2))),     // FILE_TRANSFER_PROTOCOL
+    (111, targets(16, Some(17))),   // TIMESYNC
+    (117, targets(4, Some(5))),     // LOG_REQUEST_LIST
+    (119, targets(10, Some(11))),   // LOG_REQUEST_DATA
+    (121, targets(0, Some(1))),     // LOG_ERASE
+    (122, targets(0, Some(1))),     // LOG_REQUEST_END
+    (123, targets(0, Some(1))),     // GPS_INJECT_DATA
+    (126, targets(79, Some(80))),   // SERIAL_CONTROL
+    (139, targets(41, Some(42))),   // SET_ACTUATOR_CONTROL_TARGET
+    (151, targets(6, Some(7))),     // SET_MAG_OFFSETS
+    (154, targets(6, Some(7))),     // DIGICAM_CONFIGURE
+    (155, targets(4, Some(5))),     // DIGICAM_CONTROL
+    (156, targets(0, Some(1))),     // MOUNT_CONFIGURE
+    (157, targets(12, Some(13))),   // MOUNT_CONTROL
+    (158, targets(12, Some(13))),   // MOUNT_STATUS
+    (160, targets(8, Some(9))),     // FENCE_POINT
+    (161, targets(0, Some(1))),     // FENCE_FETCH_POINT
+    (175, targets(14, Some(15))),   // RALLY_POINT
+    (176, targets(0, Some(1))),     // RALLY_FETCH_POINT
+    (179, targets(26, None)),       // CAMERA_STATUS
+    (180, targets(42, None)),       // CAMERA_FEEDBACK
+    (183, targets(0, Some(1))),     // AUTOPILOT_VERSION_REQUEST
+    (184, targets(4, Some(5))),     // REMOTE_LOG_DATA_BLOCK
+    (185, targets(4, Some(5))),     // REMOTE_LOG_BLOCK_STATUS
+    (186, targets(0, Some(1))),     // LED_CONTROL
+    (200, targets(40, Some(41))),   // GIMBAL_REPORT
+    (201, targets(12, Some(13))),   // GIMBAL_CONTROL
+    (214, targets(6, Some(7))),     // GIMBAL_TORQUE_CMD_REPORT
+    (216, targets(0, Some(1))),     // GOPRO_GET_REQUEST
+    (218, targets(0, Some(1))),     // GOPRO_SET_REQUEST
+    (243, targets(52, None)),       // SET_HOME_POSITION
+    (248, targets(3, Some(4))),     // V2_EXTENSION
+    (256, targets(8, Some(9))),     // SETUP_SIGNING
+    (258, targets(0, Some(1))),     // PLAY_TUNE
+    (266, targets(2, Some(3))),     // LOGGING_DATA
+    (267, targets(2, Some(3))),     // LOGGING_DATA_ACKED
+    (268, targets(2, Some(3))),     // LOGGING_ACK
+    (282, targets(32, Some(33))),   // GIMBAL_MANAGER_SET_ATTITUDE
+    (284, targets(30, Some(31))),   // GIMBAL_DEVICE_SET_ATTITUDE
+    (285, targets(38, Some(39))),   // GIMBAL_DEVICE_ATTITUDE_STATUS
+    (286, targets(50, Some(51))),   // AUTOPILOT_STATE_FOR_GIMBAL_DEVICE
+    (287, targets(20, Some(21))),   // GIMBAL_MANAGER_SET_PITCHYAW
+    (288, targets(20, Some(21))),   // GIMBAL_MANAGER_SET_MANUAL_CONTROL
+    (320, targets(2, Some(3))),     // PARAM_EXT_REQUEST_READ
+    (321, targets(0, Some(1))),     // PARAM_EXT_REQUEST_LIST
+    (323, targets(0, Some(1))),     // PARAM_EXT_SET
+    (385, targets(2, Some(3))),     // TUNNEL
+    (386, targets(4, Some(5))),     // CAN_FRAME
+    (387, targets(4, Some(5))),     // CANFD_FRAME
+    (388, targets(32, Some(33))),   // CAN_FILTER_MODIFY
+    (400, targets(4, Some(5))),     // PLAY_TUNE_V2
+    (401, targets(4, Some(5))),     // SUPPORTED_TUNES
+    (412, targets(4, Some(5))),     // REQUEST_EVENT
+    (413, targets(4, Some(5))),     // RESPONSE_EVENT_ERROR
+    (11000, targets(4, Some(5))),   // DEVICE_OP_READ
+    (11002, targets(4, Some(5))),   // DEVICE_OP_WRITE
+    (11004, targets(8, Some(9))),   // SECURE_COMMAND
+    (11033, targets(16, Some(17))), // OSD_PARAM_CONFIG
+    (11035, targets(4, Some(5))),   // OSD_PARAM_SHOW_CONFIG
+    (12900, targets(0, Some(1))),   // OPEN_DRONE_ID_BASIC_ID
+    (12901, targets(30, Some(31))), // OPEN_DRONE_ID_LOCATION
+    (12902, targets(4, Some(5))),   // OPEN_DRONE_ID_AUTHENTICATION
+    (12903, targets(0, Some(1))),   // OPEN_DRONE_ID_SELF_ID
+    (12904, targets(28, Some(29))), // OPEN_DRONE_ID_SYSTEM
+    (12905, targets(0, Some(1))),   // OPEN_DRONE_ID_OPERATOR_ID
+    (12915, targets(0, Some(1))),   // OPEN_DRONE_ID_MESSAGE_PACK
+    (12919, targets(16, Some(17))), // OPEN_DRONE_ID_SYSTEM_UPDATE
+    (50004, targets(8, Some(9))),   // CUBEPILOT_FIRMWARE_UPDATE_START
+    (50005, targets(4, Some(5))),   // CUBEPILOT_FIRMWARE_UPDATE_RESP
+];
+
+const fn targets(system: u8, component: Option<u8>) -> TargetOffsets {
+    TargetOffsets { system, component }
+}
+
+/// Every message layout of the ArduPilot dialect
+fn ardupilotmega_fields() -> impl Iterator<Item = &'static (u32, &'static [Field])> {
+    [
+        ARDUPILOTMEGA_FIELDS,
+        ARDUPILOTMEGA_COMMAND_FIELDS,
+        ARDUPILOTMEGA_PARAM_FIELDS,
+        ARDUPILOTMEGA_MISSION_FIELDS,
+        ARDUPILOTMEGA_FTP_FIELDS,
+    ]
+    .into_iter()
+    .flatten()
+}
+
+const fn scalar(name: &'static str, offset: u8, field_type: FieldType) -> Field {
+    Field::scalar(name, offset, field_type)
+}
+
+const fn array(name: &'static str, offset: u8, field_type: FieldType, len: u8) -> Field {
+    Field::array(name, offset, field_type, len)
+}
+
+/// Wire layout of common messages, for routing and filtering.
+///
+/// Fields are sorted by type size on the wire, with the extension fields last.
+const ARDUPILOTMEGA_FIELDS: &[(u32, &[Field])] = &[
+    (
+        0, // HEARTBEAT
+        &[
+            scalar("custom_mode", 0, U32),
+            scalar("type", 4, U8),
+            scalar("autopilot", 5, U8),
+            scalar("base_mode", 6, U8),
+            scalar("system_status", 7, U8),
+            scalar("mavlink_version", 8, U8),
+        ],
+    ),
+    (
+        2, // SYSTEM_TIME
+        &[
+            scalar("time_unix_usec", 0, U64),
+            scalar("time_boot_ms", 8, U32),
+        ],
+    ),
+    (
+        4, // PING
+        &[
+            scalar("time_usec", 0, U64),
+            scalar("seq", 8, U32),
+            scalar("target_system", 12, U8),
+            scalar("target_component", 13, U8),
+        ],
+    ),
+    (
+        5, // CHANGE_OPERATOR_CONTROL
+        &[
+            scalar("target_system", 0, U8),
+            scalar("control_request", 1, U8),
+            scalar("version", 2, U8),
+            array("passkey", 3, Char, 25),
+        ],
+    ),
+    (
+        11, // SET_MODE
+        &[
+            scalar("custom_mode", 0, U32),
+            scalar("target_system", 4, U8),
+            scalar("base_mode", 5, U8),
+        ],
+    ),
+    (
+        30, // ATTITUDE
+        &[
+            scalar("time_boot_ms", 0, U32),
+            scalar("roll", 4, F32),
+            scalar("pitch", 8, F32),
+            scalar("yaw", 12, F32),
+            scalar("rollspeed", 16, F32),
+            scalar("pitchspeed", 20, F32),
+            scalar("yawspeed", 24, F32),
+        ],
+    ),
+    (
+        33, // GLOBAL_POSITION_INT
+        &[
+            scalar("time_boot_ms", 0, U32),
+            scalar("lat", 4, I32),
+            scalar("lon", 8, I32),
+            scalar("alt", 12, I32),
+            scalar("relative_alt", 16, I32),
+            scalar("vx", 20, I16),
+            scalar("vy", 22, I16),
+            scalar("vz", 24, I16),
+            scalar("hdg", 26, U16),
+        ],
+    ),
+    (
+        66, // REQUEST_DATA_STREAM
+        &[
+            scalar("req_message_rate", 0, U16),
+            scalar("target_system", 2, U8),
+            scalar("target_component", 3, U8),
+            scalar("req_stream_id", 4, U8),
+            scalar("start_stop", 5, U8),
+        ],
+    ),
+    (
+        253, // STATUSTEXT
+        &[
+            scalar("severity", 0, U8),
+            array("text", 1, Char, 50),
+            scalar("id", 51, U16),
+            scalar("chunk_seq", 53, U8),
+        ],
+    ),
+];
+
+/// Wire layout of the file transfer protocol messages
+const ARDUPILOTMEGA_FTP_FIELDS: &[(u32, &[Field])] = &[(
+    110, // FILE_TRANSFER_PROTOCOL
+    &[
+        scalar("target_network", 0, U8),
+        scalar("target_system", 1, U8),
+        scalar("target_component", 2, U8),
+        array("payload", 3, U8, 251),
+    ],
+)];
+
+/// Wire layout of the mission protocol messages
+const ARDUPILOTMEGA_MISSION_FIELDS: &[(u32, &[Field])] = &[
+    (
+        39, // MISSION_ITEM
+        &[
+            scalar("param1", 0, F32),
+            scalar("param2", 4, F32),
+            scalar("param3", 8, F32),
+            scalar("param4", 12, F32),
+            scalar("x", 16, F32),
+            scalar("y", 20, F32),
+            scalar("z", 24, F32),
+            scalar("seq", 28, U16),
+            scalar("command", 30, U16),
+            scalar("target_system", 32, U8),
+            scalar("target_component", 33, U8),
+            scalar("frame", 34, U8),
+            scalar("current", 35, U8),
+            scalar("autocontinue", 36, U8),
+            scalar("mission_type", 37, U8),
+        ],
+    ),
+    (
+        40, // MISSION_REQUEST
+        &[
+            scalar("seq", 0, U16),
+            scalar("target_system", 2, U8),
+            scalar("target_component", 3, U8),
+            scalar("mission_type", 4, U8),
+        ],
+    ),
+    (
+        41, // MISSION_SET_CURRENT
+        &[
+            scalar("seq", 0, U16),
+            scalar("target_system", 2, U8),
+            scalar("target_component", 3, U8),
+        ],
+    ),
+    (
+        43, // MISSION_REQUEST_LIST
+        &[
+            scalar("target_system", 0, U8),
+            scalar("target_component", 1, U8),
+            scalar("mission_type", 2, U8),
+        ],
+    ),
+    (
+        44, // MISSION_COUNT
+        &[
+            scalar("count", 0, U16),
+            scalar("target_system", 2, U8),
+            scalar("target_component", 3, U8),
+            scalar("mission_type", 4, U8),
+            scalar("opaque_id", 5, U32),
+        ],
+    ),
+    (
+        45, // MISSION_CLEAR_ALL
+        &[
+            scalar("target_system", 0, U8),
+            scalar("target_component", 1, U8),
+            scalar("mission_type", 2, U8),
+        ],
+    ),
+    (
+        47, // MISSION_ACK
+        &[
+            scalar("target_system", 0, U8),
+            scalar("target_component", 1, U8),
+            scalar("type", 2, U8),
+            scalar("mission_type", 3, U8),
+            scalar("opaque_id", 4, U32),
+        ],
+    ),
+    (
+        51, // MISSION_REQUEST_INT
+        &[
+            scalar("seq", 0, U16),
+            scalar("target_system", 2, U8),
+            scalar("target_component", 3, U8),
+            scalar("mission_type", 4, U8),
+        ],
+    ),
+    (
+        73, // MISSION_ITEM_INT
+        &[
+            scalar("param1", 0, F32),
+            scalar("param2", 4, F32),
+            scalar("param3", 8, F32),
+            scalar("param4", 12, F32),
+            scalar("x", 16, I32),
+            scalar("y", 20, I32),
+            scalar("z", 24, F32),
+            scalar("seq", 28, U16),
+            scalar("command", 30, U16),
+            scalar("target_system", 32, U8),
+            scalar("target_component", 33, U8),
+            scalar("frame", 34, U8),
+            scalar("current", 35, U8),
+            scalar("autocontinue", 36, U8),
+            scalar("mission_type", 37, U8),
+        ],
+    ),
+];
+
+/// Wire layout of the parameter protocol messages
+const ARDUPILOTMEGA_PARAM_FIELDS: &[(u32, &[Field])] = &[
+    (
+        20, // PARAM_REQUEST_READ
+        &[
+            scalar("param_index", 0, I16),
+            scalar("target_system", 2, U8),
+            scalar("target_component", 3, U8),
+            array("param_id", 4, Char, 16),
+        ],
+    ),
+    (
+        21, // PARAM_REQUEST_LIST
+        &[
+            scalar("target_system", 0, U8),
+            scalar("target_component", 1, U8),
+        ],
+    ),
+    (
+        22, // PARAM_VALUE
+        &[
+            scalar("param_value", 0, F32),
+            scalar("param_count", 4, U16),
+            scalar("param_index", 6, U16),
+            array("param_id", 8, Char, 16),
+            scalar("param_type", 24, U8),
+        ],
+    ),
+    (
+        23, // PARAM_SET
+        &[
+            scalar("param_value", 0, F32),
+            scalar("target_system", 4, U8),
+            scalar("target_component", 5, U8),
+            array("param_id", 6, Char, 16),
+            scalar("param_type", 22, U8),
+        ],
+    ),
+];
+
+/// Wire layout of the command protocol messages
+const ARDUPILOTMEGA_COMMAND_FIELDS: &[(u32, &[Field])] = &[
+    (
+        75, // COMMAND_INT
+        &[
+            scalar("param1", 0, F32),
+            scalar("param2", 4, F32),
+            scalar("param3", 8, F32),
+            scalar("param4", 12, F32),
+            scalar("x", 16, I32),
+            scalar("y", 20, I32),
+            scalar("z", 24, F32),
+            scalar("command", 28, U16),
+            scalar("target_system", 30, U8),
+            scalar("target_component", 31, U8),
+            scalar("frame", 32, U8),
+            scalar("current", 33, U8),
+            scalar("autocontinue", 34, U8),
+        ],
+    ),
+    (
+        76, // COMMAND_LONG
+        &[
+            scalar("param1", 0, F32),
+            scalar("param2", 4, F32),
+            scalar("param3", 8, F32),
+            scalar("param4", 12, F32),
+            scalar("param5", 16, F32),
+            scalar("param6", 20, F32),
+            scalar("param7", 24, F32),
+            scalar("command", 28, U16),
+            scalar("target_system", 30, U8),
+            scalar("target_component", 31, U8),
+            scalar("confirmation", 32, U8),
+        ],
+    ),
+    (
+        77, // COMMAND_ACK
+        &[
+            scalar("command", 0, U16),
+            scalar("result", 2, U8),
+            scalar("progress", 3, U8),
+            scalar("result_param2", 4, I32),
+            scalar("target_system", 8, U8),
+            scalar("target_component", 9, U8),
+        ],
+    ),
+];
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use mavlink::ardupilotmega::MavMessage;
+
+    #[test]
+    fn test_ardupilotmega() {
+        assert_eq!(ArduPilotMega.message_name(0), Some("HEARTBEAT"));
+        assert_eq!(ArduPilotMega.message_name(30), Some("ATTITUDE"));
+        assert_eq!(ArduPilotMega.extra_crc(30), Some(39));
+
+        assert_eq!(ArduPilotMega.message_name(u32::MAX), None);
+        assert_eq!(ArduPilotMega.extra_crc(u32::MAX), None);
+    }
+
+    #[test]
+    fn test_payload_length() {
+        let heartbeat = ArduPilotMega.payload_length(0).unwrap();
+        assert_eq!(heartbeat, PayloadLength { min: 9, max: 9 });
+        assert!(heartbeat.accepts_v1(9));
+        assert!(!heartbeat.accepts_v1(8));
+        assert!(heartbeat.accepts_v2(1));
+        assert!(!heartbeat.accepts_v2(0));
+        assert!(!heartbeat.accepts_v2(10));
+
+        // With extensions
+        let sys_status = ArduPilotMega.payload_length(1).unwrap();
+        assert_eq!(sys_status, PayloadLength { min: 31, max: 43 });
+        assert!(sys_status.accepts_v1(43));
+        assert!(!sys_status.accepts_v1(30));
+
+        assert_eq!(ArduPilotMega.payload_length(u32::MAX), None);
+    }
+
+    #[test]
+    fn test_payload_lengths_table() {
+        assert!(ARDUPILOTMEGA_PAYLOAD_LENGTHS
+            .windows(2)
+            .all(|pair| pair[0].0 < pair[1].0));
+
+        for (message_id, min, max) in ARDUPILOTMEGA_PAYLOAD_LENGTHS {
+            assert!(min <= max, "{message_id}");
+            // Without extension fields, rust-mavlink serializes the base fields only
+            assert_eq!(
+                max_payload_length::<MavMessage>(*message_id),
+                Some(*min),
+                "{message_id}"
+            );
+        }
+
+        // Every message of the dialect
+        let message_ids = (0..=u16::MAX as u32)
+            .filter(|message_id| MavMessage::default_message_from_id(*message_id).is_ok())
+            .collect::<Vec<_>>();
+        let table_ids = ARDUPILOTMEGA_PAYLOAD_LENGTHS
+            .iter()
+            .map(|(message_id, ..)| *message_id)
+            .collect::<Vec<_>>();
+        assert_eq!(table_ids, message_ids);
+    }
+
+    #[test]
+    fn test_fields_layout() {
+        for (message_id, fields) in ardupilotmega_fields() {
+            // Contiguous, from the start of the payload
+            let mut offset = 0;
+            for field in *fields {
+                assert_eq!(field.offset as usize, offset, "{message_id} {}", field.name);
+                offset += field.size();
+            }
+
+            if let Some(payload_length) = ArduPilotMega.payload_length(*message_id) {
+                assert_eq!(offset, payload_length.max as usize, "{message_id}");
+            }
+        }
+
+        assert!(ArduPilotMega.fields(0).is_some());
+        assert!(ArduPilotMega.fields(u32::MAX).is_none());
+    }
+
+    #[test]
+    fn test_target_offsets() {
+        // COMMAND_LONG
+        assert_eq!(
+            ArduPilotMega.target_offsets(76),
+            Some(TargetOffsets {
+                system: 30,
+                component: Some(31)
+            })
+        );
+        // SET_MODE has no target component
+        assert_eq!(
+            ArduPilotMega.target_offsets(11),
+            Some(TargetOffsets {
+                system: 4,
+                component: None
+            })
+        );
+        // HEARTBEAT isn't targeted
+        assert_eq!(ArduPilotMega.target_offsets(0), None);
+
+        // From the XML definitions: (Message ID, target system, target component)
+        let expected = [
+            (37, 4, Some(5)),    // MISSION_REQUEST_PARTIAL_LIST
+            (38, 4, Some(5)),    // MISSION_WRITE_PARTIAL_LIST
+            (69, 10, None),      // MANUAL_CONTROL, with a `target` field
+            (70, 16, Some(17)),  // RC_CHANNELS_OVERRIDE
+            (77, 8, Some(9)),    // COMMAND_ACK, in its extension fields
+            (82, 36, Some(37)),  // SET_ATTITUDE_TARGET
+            (84, 50, Some(51)),  // SET_POSITION_TARGET_LOCAL_NED
+            (86, 50, Some(51)),  // SET_POSITION_TARGET_GLOBAL_INT
+            (110, 1, Some(2)),   // FILE_TRANSFER_PROTOCOL
+            (117, 4, Some(5)),   // LOG_REQUEST_LIST
+            (119, 10, Some(11)), // LOG_REQUEST_DATA
+            (123, 0, Some(1)),   // GPS_INJECT_DATA
+            (157, 12, Some(13)), // MOUNT_CONTROL
+            (320, 2, Some(3)),   // PARAM_EXT_REQUEST_READ
+            (321, 0, Some(1)),   // PARAM_EXT_REQUEST_LIST
+            (323, 0, Some(1)),   // PARAM_EXT_SET
+        ];
+        for (message_id, system, component) in expected {
+            assert_eq!(
+                ArduPilotMega.target_offsets(message_id),
+                Some(TargetOffsets { system, component }),
+                "{message_id}"
+            );
+        }
+        // PARAM_EXT_VALUE and PARAM_EXT_ACK aren't targeted
+        assert_eq!(ArduPilotMega.target_offsets(322), None);
+        assert_eq!(ArduPilotMega.target_offsets(324), None);
+    }
+
+    /// Names of the target system field, `MANUAL_CONTROL` calling it `target`
+    const TARGET_SYSTEM_FIELDS: [&str; 2] = ["target_system", "target"];
+
+    /// Whether the `Debug` output of a message has the field `name` set to `value`
+    fn prints_value(printed: &str, name: &str, value: u8) -> bool {
+        let pattern = format!(" {name}: {value}");
+
+        printed.match_indices(&pattern).any(|(index, _)| {
+            !printed[index + pattern.len()..].starts_with(|c: char| c.is_ascii_digit())
+        })
+    }
+
+    #[test]
+    fn test_target_offsets_table() {
+        const MARKER: u8 = 0xAB;
+
+        assert!(ARDUPILOTMEGA_TARGET_OFFSETS
+            .windows(2)
+            .all(|pair| pair[0].0 < pair[1].0));
+
+        for message_id in 0..=u16::MAX as u32 {
+            let Ok(message) = MavMessage::default_message_from_id(message_id) else {
+                continue;
+            };
+            let printed = format!("{message:?}");
+            let name = message.message_name();
+            let system_field = TARGET_SYSTEM_FIELDS
+                .into_iter()
+                .find(|field| printed.contains(&format!(" {field}: ")));
+            let length = ArduPilotMega.payload_length(message_id).unwrap();
+
+            let Some(offsets) = ArduPilotMega.target_offsets(message_id) else {
+                assert_eq!(system_field, None, "{name}");
+                continue;
+            };
+
+            // rust-mavlink doesn't know about extension fields
+            let Some(system_field) = system_field else {
+                assert!(offsets.system >= length.min, "{name}");
+                assert!(offsets.system < length.max, "{name}");
+                continue;
+            };
+
+            // The marked bytes are read back as the target fields
+            let mut payload = [0u8; 255];
+            let serialized = message.ser(MavlinkVersion::V1, &mut payload);
+            let payload = &mut payload[..serialized];
+            payload[offsets.system as usize] = MARKER;
+            if let Some(component) = offsets.component {
+                payload[component as usize] = MARKER;
+            }
+            let printed = format!(
+                "{:?}",
+                MavMessage::parse(MavlinkVersion::V2, message_id, payload).unwrap()
+            );
+
+            assert!(prints_value(&printed, system_field, MARKER), "{name}");
+            assert_eq!(
+                offsets.component.is_some(),
+                prints_value(&printed, "target_component", MARKER),
+                "{name}"
+            );
+        }
+    }
+}
@@ -0,0 +1,242 @@
+//! Human-readable formatting of packets.
+//!
+//! The [`Display`] implementations of the packets name the messages using the
+//! [`ArduPilotMega`] dialect, the same one used by the codec. Other dialects can be used through
+//! [`Packet::display`], [`V1Packet::display`] and [`V2Packet::display`].
+//!
+//! The alternate form (`{:#}`) prints one field per line.
+
+use std::fmt::{self, Display};
+
+use crate::{
+    dialect::{ArduPilotMega, Dialect},
+    v1::V1Packet,
+    v2::V2Packet,
+    Packet,
+};
+
+/// Formats a packet naming its message with a [`Dialect`]
+#[derive(Debug, Clone, Copy)]
+pub struct PacketDisplay<'a, P, D> {
+    packet: &'a P,
+    dialect: &'a D,
+}
+
+struct Fields<'a> {
+    version: u8,
+    sequence: u8,
+    system_id: u8,
+    component_id: u8,
+    message_id: u32,
+    message_name: Option<&'a str>,
+    payload_length: u8,
+    /// Incompatibility and Compatibility Flags, only on V2
+    flags: Option<(u8, u8)>,
+    signed: bool,
+    checksum: u16,
+}
+
+impl Fields<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = self.message_name.unwrap_or("UNKNOWN");
+
+        if !f.alternate() {
+            write!(
+                f,
+                "V{} {name}({}) seq={} sys={} comp={} len={}",
+                self.version,
+                self.message_id,
+                self.sequence,
+                self.system_id,
+                self.component_id,
+                self.payload_length,
+            )?;
+            if let Some((incompat_flags, compat_flags)) = self.flags {
+                write!(
+                    f,
+                    " iflags={incompat_flags:#04x} cflags={compat_flags:#04x}"
+                )?;
+            }
+            if self.signed {
+                write!(f, " signed")?;
+            }
+
+            return Ok(());
+        }
+
+        writeln!(f, "MAVLink V{} packet", self.version)?;
+        writeln!(f, "  message:               {name} ({})", self.message_id)?;
+        writeln!(f, "  sequence:              {}", self.sequence)?;
+        writeln!(f, "  system ID:             {}", self.system_id)?;
+        writeln!(f, "  component ID:          {}", self.component_id)?;
+        writeln!(f, "  payload length:        {}", self.payload_length)?;
+        if let Some((incompat_flags, compat_flags)) = self.flags {
+            writeln!(f, "  incompatibility flags: {incompat_flags:#04x}")?;
+            writeln!(f, "  compatibility flags:   {compat_flags:#04x}")?;
+        }
+        writeln!(f, "  signed:                {}", self.signed)?;
+        write!(f, "  checksum:              {:#06x}", self.checksum)
+    }
+}
+
+impl<D: Dialect> Display for PacketDisplay<'_, V1Packet, D> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let packet = self.packet;
+        let message_id = *packet.message_id() as u32;
+
+        Fields {
+            version: 1,
+            sequence: *packet.sequence(),
+            system_id: *packet.system_id(),
+            component_id: *packet.component_id(),
+            message_id,
+            message_name: self.dialect.message_name(message_id),
+            payload_length: *packet.payload_length(),
+            flags: None,
+            signed: false,
+            checksum: packet.checksum(),
+        }
+        .fmt(f)
+    }
+}
+
+impl<D: Dialect> Display for PacketDisplay<'_, V2Packet, D> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let packet = self.packet;
+        let message_id = packet.message_id();
+
+        Fields {
+            version: 2,
+            sequence: *packet.sequence(),
+            system_id: *packet.system_id(),
+            component_id: *packet.component_id(),
+            message_id,
+            message_name: self.dialect.message_name(message_id),
+            payload_length: *packet.payload_length(),
+            flags: Some((
+                *packet.incompatibility_flags(),
+                *packet.compatibility_flags(),
+            )),
+            signed: packet.has_signature(),
+            checksum: packet.checksum(),
+        }
+        .fmt(f)
+    }
+}
+
+impl<D: Dialect> Display for PacketDisplay<'_, Packet, D> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.packet {
+            Packet::V1(v1_packet) => v1_packet.display(self.dialect).fmt(f),
+            Packet::V2(v2_packet) => v2_packet.display(self.dialect).fmt(f),
+        }
+    }
+}
+
+macro_rules! impl_display {
+    ($($packet:ty),*) => {$(
+        impl $packet {
+            /// Formats the packet, naming its message with `dialect`
+            pub fn display<'a, D: Dialect>(&'a self, dialect: &'a D) -> PacketDisplay<'a, Self, D> {
+                PacketDisplay {
+                    packet: self,
+                    dialect,
+                }
+            }
+        }
+
+        impl Display for $packet {
+            fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                self.display(&ArduPilotMega).fmt(f)
+            }
+        }
+    )*};
+}
+
+impl_display!(Packet, V1Packet, V2Packet);
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test_util::{create_v1_packet, create_v2_packet, header};
+    use mavlink::{ardupilotmega::MavMessage, MavHeader};
+
+    const HEADER: MavHeader = header(1, 2, 3);
+
+    /// Knows no message at all
+    struct EmptyDialect;
+
+    impl Dialect for EmptyDialect {
+        type Message = MavMessage;
+
+        fn message_name(&self, _message_id: u32) -> Option<&'static str> {
+            None
+        }
+    }
+
+    #[test]
+    fn test_display_v1() {
+        let packet = create_v1_packet(HEADER, 0);
+
+        assert_eq!(
+            packet.to_string(),
+            format!(
+                "V1 HEARTBEAT(0) seq=3 sys=1 comp=2 len={}",
+                packet.payload_length()
+            )
+        );
+        let Packet::V1(v1_packet) = &packet else {
+            unreachable!()
+        };
+        assert_eq!(packet.to_string(), v1_packet.to_string());
+    }
+
+    #[test]
+    fn test_display_v2() {
+        let packet = create_v2_packet(HEADER, 30);
+
+        assert_eq!(
+            packet.to_string(),
+            format!(
+                "V2 ATTITUDE(30) seq=3 sys=1 comp=2 len={} iflags=0x00 cflags=0x00",
+                packet.payload_length()
+            )
+        );
+    }
+
+    #[test]
+    fn test_display_signed() {
+        let mut bytes = create_v2_packet(HEADER, 30).as_slice().to_vec();
+        bytes[2] |= crate::v2::MAVLINK_IFLAG_SIGNED;
+        bytes.extend_from_slice(&[0; V2Packet::SIGNATURE_SIZE]);
+        let packet = V2Packet::new(bytes.into());
+
+        assert!(packet
+            .to_string()
+            .ends_with("iflags=0x01 cflags=0x00 signed"));
+        assert!(format!("{packet:#}").contains("signed:                true"));
+    }
+
+    #[test]
+    fn test_display_alternate() {
+        let packet = create_v2_packet(HEADER, 30);
+
+        let lines = format!("{packet:#}");
+        let lines = lines.lines().collect::<Vec<_>>();
+
+        assert_eq!(lines[0], "MAVLink V2 packet");
+        assert_eq!(lines[1], "  message:               ATTITUDE (30)");
+        assert_eq!(lines[2], "  sequence:              3");
+        assert_eq!(lines.len(), 10);
+    }
+
+    #[test]
+    fn test_display_unknown_message() {
+        let packet = create_v2_packet(HEADER, 30);
+
+        assert!(packet
+            .display(&EmptyDialect)
+            .to_string()
+            .starts_with("V2 UNKNOWN(30) "));
+    }
+}
@@ -1,25 +1,50 @@
+use bytes::Bytes;
 use thiserror::Error;
 
 use std::io;
 
+use crate::field::FieldType;
+
 #[derive(Error, Debug)]
+#[non_exhaustive]
 pub enum DecoderError {
-    #[error("invalid System ID: {sysid}")]
-    InvalidSystemID { sysid: u8 },
+    #[error("invalid System ID: {sysid}, {context}")]
+    InvalidSystemID {
+        sysid: u8,
+        context: Box<FrameContext>,
+    },
 
-    #[error("invalid Component ID: {compid}")]
-    InvalidComponentID { compid: u8 },
+    #[error("invalid Component ID: {compid}, {context}")]
+    InvalidComponentID {
+        compid: u8,
+        context: Box<FrameContext>,
+    },
 
-    #[error("found incompatible flags in {incompat_flags}")]
-    Incompatible { incompat_flags: u8 },
+    #[error("found incompatible flags in {incompat_flags}, {context}")]
+    Incompatible {
+        incompat_flags: u8,
+        context: Box<FrameContext>,
+    },
 
-    #[error("unknown Message ID")]
-    UnknownMessageID { msgid: u32 },
+    #[error("unknown Message ID: {msgid}, {context}")]
+    UnknownMessageID {
+        msgid: u32,
+        context: Box<FrameContext>,
+    },
 
-    #[error("invalid CRC: expected {expected_crc}, calculated {calculated_crc}")]
+    #[error("invalid payload length: {len}, expected {min}..={max}, {context}")]
+    InvalidPayloadLength {
+        len: u8,
+        min: u8,
+        max: u8,
+        context: Box<FrameContext>,
+    },
+
+    #[error("invalid CRC: expected {expected_crc}, calculated {calculated_crc}, {context}")]
     InvalidCRC {
         expected_crc: u16,
         calculated_crc: u16,
+        context: Box<FrameContext>,
     },
 
     #[error("io error")]
@@ -28,3 +53,103 @@ pub enum DecoderError {
     #[error("unknown error")]
     Unknown,
 }
+
+impl DecoderError {
+    /// The rejected candidate frame, when the error is about one
+    pub fn context(&self) -> Option<&FrameContext> {
+        match self {
+            DecoderError::InvalidSystemID { context, .. }
+            | DecoderError::InvalidComponentID { context, .. }
+            | DecoderError::Incompatible { context, .. }
+            | DecoderError::UnknownMessageID { context, .. }
+            | DecoderError::InvalidPayloadLength { context, .. }
+            | DecoderError::InvalidCRC { context, .. } => Some(context),
+            DecoderError::Io(_) | DecoderError::Unknown => None,
+        }
+    }
+}
+
+/// The candidate frame a [`DecoderError`] is about
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct FrameContext {
+    /// MAVLink version, from the STX
+    pub version: u8,
+    pub sequence: u8,
+    pub system_id: u8,
+    pub component_id: u8,
+    pub message_id: u32,
+    /// The candidate frame bytes, as far as they were available
+    pub frame: Bytes,
+    /// Stream offset of the frame's STX
+    pub offset: u64,
+}
+
+impl std::fmt::Display for FrameContext {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "V{} seq={} sys={} comp={} msg={} at offset {}",
+            self.version,
+            self.sequence,
+            self.system_id,
+            self.component_id,
+            self.message_id,
+            self.offset
+        )
+    }
+}
+
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
+pub enum ValidationError {
+    #[error("invalid MAVLink version: {version}")]
+    InvalidVersion { version: u8 },
+
+    #[error("invalid STX: {stx}")]
+    InvalidStx { stx: u8 },
+
+    #[error("invalid packet size: expected {expected}, got {actual}")]
+    InvalidSize { expected: usize, actual: usize },
+
+    #[error("invalid Message ID for MAVLink V{version}: {msgid}")]
+    InvalidMessageID { version: u8, msgid: u32 },
+
+    #[error("invalid incompatibility flags: {incompat_flags}")]
+    InvalidFlags { incompat_flags: u8 },
+
+    #[error("invalid signature size: {size}")]
+    InvalidSignatureSize { size: usize },
+
+    #[error("signature doesn't match the signed flag")]
+    SignatureMismatch,
+
+    #[error("unknown Message ID: {msgid}")]
+    UnknownMessageID { msgid: u32 },
+
+    #[error("unknown field {field} of Message ID {msgid}")]
+    UnknownField { msgid: u32, field: String },
+
+    #[error("invalid value for field {field} of type {field_type:?}")]
+    InvalidFieldValue {
+        field: &'static str,
+        field_type: FieldType,
+    },
+
+    #[error("invalid CRC: expected {expected_crc}, calculated {calculated_crc}")]
+    InvalidCRC {
+        expected_crc: u16,
+        calculated_crc: u16,
+    },
+}
+
+#[cfg(feature = "json")]
+#[derive(Error, Debug)]
+pub enum JsonError {
+    #[error("missing or invalid field: {field}")]
+    InvalidHeader { field: &'static str },
+
+    #[error("failed to parse the payload: {0:?}")]
+    Parse(mavlink::error::ParserError),
+
+    #[error("json error: {0}")]
+    Json(#[from] serde_json::Error),
+}
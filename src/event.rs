@@ -0,0 +1,315 @@
+//! A decode mode that surfaces everything found in the stream, not only the packets.
+
+use bytes::{Bytes, BytesMut};
+use log::trace;
+use tokio_util::codec::{Decoder, Encoder};
+
+use crate::{
+    codec::{CodecState, MavlinkCodec},
+    error::DecoderError,
+    v1::V1_STX,
+    v2::V2_STX,
+    Packet,
+};
+
+/// The items of [`MavlinkEventCodec`]
+#[derive(Debug)]
+pub enum DecoderEvent {
+    Packet(Packet),
+    /// Bytes that are not part of any MAVLink packet, like bootloader text or NMEA sentences
+    Discarded(Bytes),
+    Error(DecoderError),
+}
+
+/// Wraps a [`MavlinkCodec`], yielding the bytes it would silently drop as
+/// [`DecoderEvent::Discarded`].
+///
+/// When a candidate packet is rejected, its STX is reported by the [`DecoderEvent::Error`], and
+/// the bytes after it are reported as discarded until the next STX.
+#[derive(Debug, Default)]
+pub struct MavlinkEventCodec<
+    const ACCEPT_V1: bool,
+    const ACCEPT_V2: bool,
+    const DROP_INVALID_SYSID: bool,
+    const DROP_INVALID_COMPID: bool,
+    const SKIP_CRC_VALIDATION: bool,
+    const DROP_INCOMPATIBLE: bool,
+    const VALIDATE_PAYLOAD_LENGTH: bool = false,
+> {
+    pub codec: MavlinkCodec<
+        ACCEPT_V1,
+        ACCEPT_V2,
+        DROP_INVALID_SYSID,
+        DROP_INVALID_COMPID,
+        SKIP_CRC_VALIDATION,
+        DROP_INCOMPATIBLE,
+        VALIDATE_PAYLOAD_LENGTH,
+    >,
+}
+
+impl<
+        const ACCEPT_V1: bool,
+        const ACCEPT_V2: bool,
+        const DROP_INVALID_SYSID: bool,
+        const DROP_INVALID_COMPID: bool,
+        const SKIP_CRC_VALIDATION: bool,
+        const DROP_INCOMPATIBLE: bool,
+        const VALIDATE_PAYLOAD_LENGTH: bool,
+    >
+    MavlinkEventCodec<
+        ACCEPT_V1,
+        ACCEPT_V2,
+        DROP_INVALID_SYSID,
+        DROP_INVALID_COMPID,
+        SKIP_CRC_VALIDATION,
+        DROP_INCOMPATIBLE,
+        VALIDATE_PAYLOAD_LENGTH,
+    >
+{
+    /// Number of bytes before the first accepted STX found from `start`
+    #[inline(always)]
+    fn non_stx_len(buf: &[u8], start: usize) -> usize {
+        buf[start..]
+            .iter()
+            .position(|byte| (ACCEPT_V1 && *byte == V1_STX) || (ACCEPT_V2 && *byte == V2_STX))
+            .map_or(buf.len(), |position| start + position)
+    }
+}
+
+impl<
+        const ACCEPT_V1: bool,
+        const ACCEPT_V2: bool,
+        const DROP_INVALID_SYSID: bool,
+        const DROP_INVALID_COMPID: bool,
+        const SKIP_CRC_VALIDATION: bool,
+        const DROP_INCOMPATIBLE: bool,
+        const VALIDATE_PAYLOAD_LENGTH: bool,
+    >
+    From<
+        MavlinkCodec<
+            ACCEPT_V1,
+            ACCEPT_V2,
+            DROP_INVALID_SYSID,
+            DROP_INVALID_COMPID,
+            SKIP_CRC_VALIDATION,
+            DROP_INCOMPATIBLE,
+            VALIDATE_PAYLOAD_LENGTH,
+        >,
+    >
+    for MavlinkEventCodec<
+        ACCEPT_V1,
+        ACCEPT_V2,
+        DROP_INVALID_SYSID,
+        DROP_INVALID_COMPID,
+        SKIP_CRC_VALIDATION,
+        DROP_INCOMPATIBLE,
+        VALIDATE_PAYLOAD_LENGTH,
+    >
+{
+    fn from(
+        codec: MavlinkCodec<
+            ACCEPT_V1,
+            ACCEPT_V2,
+            DROP_INVALID_SYSID,
+            DROP_INVALID_COMPID,
+            SKIP_CRC_VALIDATION,
+            DROP_INCOMPATIBLE,
+            VALIDATE_PAYLOAD_LENGTH,
+        >,
+    ) -> Self {
+        Self { codec }
+    }
+}
+
+impl<
+        const ACCEPT_V1: bool,
+        const ACCEPT_V2: bool,
+        const DROP_INVALID_SYSID: bool,
+        const DROP_INVALID_COMPID: bool,
+        const SKIP_CRC_VALIDATION: bool,
+        const DROP_INCOMPATIBLE: bool,
+        const VALIDATE_PAYLOAD_LENGTH: bool,
+    > Decoder
+    for MavlinkEventCodec<
+        ACCEPT_V1,
+        ACCEPT_V2,
+        DROP_INVALID_SYSID,
+        DROP_INVALID_COMPID,
+        SKIP_CRC_VALIDATION,
+        DROP_INCOMPATIBLE,
+        VALIDATE_PAYLOAD_LENGTH,
+    >
+{
+    type Item = DecoderEvent;
+    type Error = std::io::Error;
+
+    fn decode(&mut self, buf: &mut BytesMut) -> Result<Option<Self::Item>, Self::Error> {
+        // Only between packets the inner codec would be skipping bytes
+        if matches!(self.codec.state, CodecState::WaitingForStx) {
+            let discarded_len = Self::non_stx_len(buf, 0);
+            if discarded_len > 0 {
+                trace!("Discarding {discarded_len} bytes");
+                self.codec.offset += discarded_len as u64;
+                return Ok(Some(DecoderEvent::Discarded(
+                    buf.split_to(discarded_len).freeze(),
+                )));
+            }
+        }
+
+        Ok(self.codec.decode(buf)?.map(|result| match result {
+            Ok(packet) => DecoderEvent::Packet(packet),
+            Err(error) => DecoderEvent::Error(error),
+        }))
+    }
+
+    fn decode_eof(&mut self, buf: &mut BytesMut) -> Result<Option<Self::Item>, Self::Error> {
+        if let Some(event) = self.decode(buf)? {
+            return Ok(Some(event));
+        }
+
+        if buf.is_empty() {
+            return Ok(None);
+        }
+
+        // A truncated packet: its STX is discarded, and the decoding resumes from the next one
+        self.codec.state = CodecState::WaitingForStx;
+        let discarded_len = Self::non_stx_len(buf, 1);
+        self.codec.offset += discarded_len as u64;
+
+        Ok(Some(DecoderEvent::Discarded(
+            buf.split_to(discarded_len).freeze(),
+        )))
+    }
+}
+
+impl<
+        const ACCEPT_V1: bool,
+        const ACCEPT_V2: bool,
+        const DROP_INVALID_SYSID: bool,
+        const DROP_INVALID_COMPID: bool,
+        const SKIP_CRC_VALIDATION: bool,
+        const DROP_INCOMPATIBLE: bool,
+        const VALIDATE_PAYLOAD_LENGTH: bool,
+    > Encoder<Packet>
+    for MavlinkEventCodec<
+        ACCEPT_V1,
+        ACCEPT_V2,
+        DROP_INVALID_SYSID,
+        DROP_INVALID_COMPID,
+        SKIP_CRC_VALIDATION,
+        DROP_INCOMPATIBLE,
+        VALIDATE_PAYLOAD_LENGTH,
+    >
+{
+    type Error = std::io::Error;
+
+    fn encode(&mut self, packet: Packet, buf: &mut BytesMut) -> Result<(), Self::Error> {
+        self.codec.encode(packet, buf)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test_util::{create_v2_packet, header};
+    use bytes::BufMut;
+
+    type Codec = MavlinkEventCodec<true, true, false, false, false, false>;
+
+    fn decode_all(codec: &mut Codec, buf: &mut BytesMut) -> Vec<DecoderEvent> {
+        let mut events = Vec::new();
+        while let Some(event) = codec.decode_eof(buf).unwrap() {
+            events.push(event);
+        }
+
+        events
+    }
+
+    #[test]
+    fn test_discarded_bytes() {
+        let first_packet = create_v2_packet(header(1, 1, 0), 0);
+        let second_packet = create_v2_packet(header(1, 1, 1), 0);
+
+        let mut buf = BytesMut::new();
+        buf.put(&b"U-Boot 2024.01\r\n"[..]);
+        buf.put(first_packet.as_slice());
+        buf.put(&b"$GPGGA,123519,4807.038,N*47\r\n"[..]);
+        buf.put(second_packet.as_slice());
+
+        let events = decode_all(&mut Codec::default(), &mut buf);
+
+        assert_eq!(events.len(), 4);
+        assert!(
+            matches!(&events[0], DecoderEvent::Discarded(bytes) if bytes == &b"U-Boot 2024.01\r\n"[..])
+        );
+        assert!(matches!(&events[1], DecoderEvent::Packet(packet) if packet == &first_packet));
+        assert!(
+            matches!(&events[2], DecoderEvent::Discarded(bytes) if bytes == &b"$GPGGA,123519,4807.038,N*47\r\n"[..])
+        );
+        assert!(matches!(&events[3], DecoderEvent::Packet(packet) if packet == &second_packet));
+    }
+
+    #[test]
+    fn test_partial_input() {
+        let packet = create_v2_packet(header(1, 1, 0), 0);
+        let mut codec = Codec::default();
+
+        let mut buf = BytesMut::new();
+        buf.put(&b"garbage"[..]);
+        buf.put(&packet.as_slice()[..5]);
+
+        // The garbage is released right away, and the partial packet waits for more data
+        assert!(matches!(
+            codec.decode(&mut buf).unwrap(),
+            Some(DecoderEvent::Discarded(bytes)) if bytes == b"garbage"[..]
+        ));
+        assert!(codec.decode(&mut buf).unwrap().is_none());
+
+        buf.put(&packet.as_slice()[5..]);
+
+        assert!(matches!(
+            codec.decode(&mut buf).unwrap(),
+            Some(DecoderEvent::Packet(decoded)) if decoded == packet
+        ));
+    }
+
+    #[test]
+    fn test_invalid_crc() {
+        let packet = create_v2_packet(header(1, 1, 0), 0);
+
+        let mut buf = BytesMut::new();
+        buf.put(packet.as_slice());
+        let last_crc_byte = buf.len() - 1;
+        buf[last_crc_byte] ^= 0xFF; // Corrupt the CRC
+
+        let events = decode_all(&mut Codec::default(), &mut buf);
+
+        assert!(matches!(
+            &events[0],
+            DecoderEvent::Error(DecoderError::InvalidCRC { .. })
+        ));
+        // Everything after the rejected STX
+        assert!(
+            matches!(&events[1], DecoderEvent::Discarded(bytes) if bytes.len() == packet.packet_size() - 1)
+        );
+        assert_eq!(events.len(), 2);
+    }
+
+    #[test]
+    fn test_truncated_packet_at_eof() {
+        let packet = create_v2_packet(header(1, 1, 0), 0);
+        let truncated_packet = create_v2_packet(header(1, 1, 1), 0);
+
+        let mut buf = BytesMut::new();
+        buf.put(packet.as_slice());
+        buf.put(&truncated_packet.as_slice()[..6]);
+
+        let events = decode_all(&mut Codec::default(), &mut buf);
+
+        assert!(matches!(&events[0], DecoderEvent::Packet(decoded) if decoded == &packet));
+        assert!(
+            matches!(&events[1], DecoderEvent::Discarded(bytes) if bytes == &truncated_packet.as_slice()[..6])
+        );
+        assert_eq!(events.len(), 2);
+    }
+}
@@ -0,0 +1,472 @@
+//! Access to single payload fields, without deserializing the whole message.
+
+use crate::{dialect::Dialect, error::ValidationError, Packet};
+
+/// The wire type of a payload field
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FieldType {
+    U8,
+    I8,
+    U16,
+    I16,
+    U32,
+    I32,
+    U64,
+    I64,
+    F32,
+    F64,
+    Char,
+}
+
+impl FieldType {
+    /// Size of a single value on the wire
+    #[inline(always)]
+    pub const fn size(&self) -> usize {
+        match self {
+            FieldType::U8 | FieldType::I8 | FieldType::Char => 1,
+            FieldType::U16 | FieldType::I16 => 2,
+            FieldType::U32 | FieldType::I32 | FieldType::F32 => 4,
+            FieldType::U64 | FieldType::I64 | FieldType::F64 => 8,
+        }
+    }
+}
+
+/// Where a field is found in the payload
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Field {
+    pub name: &'static str,
+    /// Offset from the start of the payload, in wire order
+    pub offset: u8,
+    pub field_type: FieldType,
+    /// `None` for scalar fields
+    pub array_length: Option<u8>,
+}
+
+impl Field {
+    pub const fn scalar(name: &'static str, offset: u8, field_type: FieldType) -> Self {
+        Self {
+            name,
+            offset,
+            field_type,
+            array_length: None,
+        }
+    }
+
+    pub const fn array(
+        name: &'static str,
+        offset: u8,
+        field_type: FieldType,
+        array_length: u8,
+    ) -> Self {
+        Self {
+            name,
+            offset,
+            field_type,
+            array_length: Some(array_length),
+        }
+    }
+
+    /// Size of the field on the wire
+    #[inline(always)]
+    pub const fn size(&self) -> usize {
+        match self.array_length {
+            Some(array_length) => self.field_type.size() * array_length as usize,
+            None => self.field_type.size(),
+        }
+    }
+
+    /// Reads the field from a payload, or `None` if the payload is too short to hold it.
+    ///
+    /// MAVLink V2 payloads have to be zero-extended first, see [`Packet::payload_extended`].
+    pub fn read(&self, payload: &[u8]) -> Option<FieldValue> {
+        let offset = self.offset as usize;
+        let bytes = payload.get(offset..offset + self.size())?;
+
+        Some(match self.array_length {
+            // Text is NUL-terminated, unless it fills the whole array
+            Some(_) if self.field_type == FieldType::Char => {
+                let text = bytes.split(|byte| *byte == 0).next().unwrap_or_default();
+
+                FieldValue::Text(String::from_utf8_lossy(text).into_owned())
+            }
+            Some(_) => FieldValue::Array(
+                bytes
+                    .chunks_exact(self.field_type.size())
+                    .map(|bytes| read_value(bytes, self.field_type))
+                    .collect(),
+            ),
+            None => read_value(bytes, self.field_type),
+        })
+    }
+
+    /// Writes the field into a payload, converting numbers to the type of the field.
+    ///
+    /// Text and arrays longer than the field are truncated, and shorter ones are zero-padded.
+    /// Text only fits `char` arrays, arrays only fit array fields and numbers only fit scalar
+    /// fields within the range of their type, anything else is an
+    /// [`ValidationError::InvalidFieldValue`]. A payload too short for the field is an
+    /// [`ValidationError::InvalidSize`].
+    pub fn write(&self, payload: &mut [u8], value: &FieldValue) -> Result<(), ValidationError> {
+        let offset = self.offset as usize;
+        let actual = payload.len();
+        let Some(bytes) = payload.get_mut(offset..offset + self.size()) else {
+            return Err(ValidationError::InvalidSize {
+                expected: offset + self.size(),
+                actual,
+            });
+        };
+        let invalid = || ValidationError::InvalidFieldValue {
+            field: self.name,
+            field_type: self.field_type,
+        };
+
+        match (value, self.array_length) {
+            (FieldValue::Text(text), Some(_)) if self.field_type == FieldType::Char => {
+                let text = &text.as_bytes()[..text.len().min(bytes.len())];
+                bytes.fill(0);
+                bytes[..text.len()].copy_from_slice(text);
+            }
+            (FieldValue::Array(values), Some(_)) => {
+                bytes.fill(0);
+                for (bytes, value) in bytes.chunks_exact_mut(self.field_type.size()).zip(values) {
+                    write_value(bytes, self.field_type, value).ok_or_else(invalid)?;
+                }
+            }
+            (_, None) => write_value(bytes, self.field_type, value).ok_or_else(invalid)?,
+            _ => return Err(invalid()),
+        }
+
+        Ok(())
+    }
+}
+
+/// A field read from a payload
+#[derive(Debug, Clone, PartialEq)]
+pub enum FieldValue {
+    U8(u8),
+    I8(i8),
+    U16(u16),
+    I16(i16),
+    U32(u32),
+    I32(i32),
+    U64(u64),
+    I64(i64),
+    F32(f32),
+    F64(f64),
+    Char(u8),
+    /// A `char` array
+    Text(String),
+    Array(Vec<FieldValue>),
+}
+
+impl FieldValue {
+    /// The value of integer fields
+    pub fn as_i64(&self) -> Option<i64> {
+        match *self {
+            FieldValue::U8(value) | FieldValue::Char(value) => Some(value as i64),
+            FieldValue::I8(value) => Some(value as i64),
+            FieldValue::U16(value) => Some(value as i64),
+            FieldValue::I16(value) => Some(value as i64),
+            FieldValue::U32(value) => Some(value as i64),
+            FieldValue::I32(value) => Some(value as i64),
+            FieldValue::U64(value) => i64::try_from(value).ok(),
+            FieldValue::I64(value) => Some(value),
+            _ => None,
+        }
+    }
+
+    /// The value of numeric fields
+    pub fn as_f64(&self) -> Option<f64> {
+        match *self {
+            FieldValue::F32(value) => Some(value as f64),
+            FieldValue::F64(value) => Some(value),
+            FieldValue::U64(value) => Some(value as f64),
+            _ => self.as_i64().map(|value| value as f64),
+        }
+    }
+}
+
+/// Reads a little-endian scalar from the bytes of the field
+fn read_value(value: &[u8], field_type: FieldType) -> FieldValue {
+    let mut bytes = [0u8; 8];
+    bytes[..value.len()].copy_from_slice(value);
+
+    let [b0, b1, b2, b3, ..] = bytes;
+    match field_type {
+        FieldType::U8 => FieldValue::U8(b0),
+        FieldType::I8 => FieldValue::I8(b0 as i8),
+        FieldType::Char => FieldValue::Char(b0),
+        FieldType::U16 => FieldValue::U16(u16::from_le_bytes([b0, b1])),
+        FieldType::I16 => FieldValue::I16(i16::from_le_bytes([b0, b1])),
+        FieldType::U32 => FieldValue::U32(u32::from_le_bytes([b0, b1, b2, b3])),
+        FieldType::I32 => FieldValue::I32(i32::from_le_bytes([b0, b1, b2, b3])),
+        FieldType::F32 => FieldValue::F32(f32::from_le_bytes([b0, b1, b2, b3])),
+        FieldType::U64 => FieldValue::U64(u64::from_le_bytes(bytes)),
+        FieldType::I64 => FieldValue::I64(i64::from_le_bytes(bytes)),
+        FieldType::F64 => FieldValue::F64(f64::from_le_bytes(bytes)),
+    }
+}
+
+/// Writes a little-endian scalar, or `None` if the value isn't a number or doesn't fit an
+/// integer field. Floats are truncated into integer fields.
+fn write_value(bytes: &mut [u8], field_type: FieldType, value: &FieldValue) -> Option<()> {
+    let float = value.as_f64()?;
+    let integer = || match *value {
+        FieldValue::U64(value) => Some(i128::from(value)),
+        FieldValue::F32(_) | FieldValue::F64(_) => float.is_finite().then_some(float as i128),
+        _ => value.as_i64().map(i128::from),
+    };
+
+    match field_type {
+        FieldType::U8 | FieldType::Char => bytes[0] = u8::try_from(integer()?).ok()?,
+        FieldType::I8 => bytes.copy_from_slice(&i8::try_from(integer()?).ok()?.to_le_bytes()),
+        FieldType::U16 => bytes.copy_from_slice(&u16::try_from(integer()?).ok()?.to_le_bytes()),
+        FieldType::I16 => bytes.copy_from_slice(&i16::try_from(integer()?).ok()?.to_le_bytes()),
+        FieldType::U32 => bytes.copy_from_slice(&u32::try_from(integer()?).ok()?.to_le_bytes()),
+        FieldType::I32 => bytes.copy_from_slice(&i32::try_from(integer()?).ok()?.to_le_bytes()),
+        FieldType::U64 => bytes.copy_from_slice(&u64::try_from(integer()?).ok()?.to_le_bytes()),
+        FieldType::I64 => bytes.copy_from_slice(&i64::try_from(integer()?).ok()?.to_le_bytes()),
+        // Without going through f64, which could change the bits of NaN
+        FieldType::F32 => match *value {
+            FieldValue::F32(value) => bytes.copy_from_slice(&value.to_le_bytes()),
+            _ => bytes.copy_from_slice(&(float as f32).to_le_bytes()),
+        },
+        FieldType::F64 => bytes.copy_from_slice(&float.to_le_bytes()),
+    }
+
+    Some(())
+}
+
+impl Packet {
+    /// Reads a single field of the payload, by name.
+    ///
+    /// `None` if the dialect doesn't describe the fields of this message, or it has no such field.
+    pub fn field<D: Dialect>(&self, name: &str, dialect: &D) -> Option<FieldValue> {
+        let field = dialect
+            .fields(self.message_id())?
+            .iter()
+            .find(|field| field.name == name)?;
+
+        field.read(&self.payload_extended(dialect))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{
+        dialect::ArduPilotMega,
+        test_util::{create_raw_packet, header},
+    };
+
+    #[test]
+    fn test_heartbeat_fields() {
+        // custom_mode, type, autopilot, base_mode, system_status, mavlink_version
+        let packet = create_raw_packet(header(1, 1, 0), 0, &[4, 0, 0, 0, 2, 3, 81, 4, 3]);
+
+        assert_eq!(
+            packet.field("custom_mode", &ArduPilotMega),
+            Some(FieldValue::U32(4))
+        );
+        assert_eq!(
+            packet.field("type", &ArduPilotMega),
+            Some(FieldValue::U8(2))
+        );
+        assert_eq!(
+            packet.field("base_mode", &ArduPilotMega),
+            Some(FieldValue::U8(81))
+        );
+        assert_eq!(packet.field("unknown", &ArduPilotMega), None);
+    }
+
+    #[test]
+    fn test_truncated_payload() {
+        // SET_MODE with everything after custom_mode truncated
+        let packet = create_raw_packet(header(1, 1, 0), 11, &[4]);
+
+        assert_eq!(
+            packet.field("custom_mode", &ArduPilotMega),
+            Some(FieldValue::U32(4))
+        );
+        assert_eq!(
+            packet.field("target_system", &ArduPilotMega),
+            Some(FieldValue::U8(0))
+        );
+    }
+
+    #[test]
+    fn test_text_fields() {
+        // STATUSTEXT
+        let mut payload = vec![0u8; 54];
+        payload[0] = 6; // MAV_SEVERITY_INFO
+        payload[1..1 + 7].copy_from_slice(b"Armed!!");
+        payload[51..53].copy_from_slice(&300u16.to_le_bytes());
+        let packet = create_raw_packet(header(1, 1, 0), 253, &payload);
+
+        assert_eq!(
+            packet.field("text", &ArduPilotMega),
+            Some(FieldValue::Text("Armed!!".to_string()))
+        );
+        assert_eq!(
+            packet
+                .field("severity", &ArduPilotMega)
+                .and_then(|value| value.as_i64()),
+            Some(6)
+        );
+        assert_eq!(
+            packet.field("id", &ArduPilotMega),
+            Some(FieldValue::U16(300))
+        );
+
+        // Filling the whole array, without a NUL terminator
+        payload[1..51].fill(b'a');
+        let packet = create_raw_packet(header(1, 1, 0), 253, &payload);
+        assert_eq!(
+            packet.field("text", &ArduPilotMega),
+            Some(FieldValue::Text("a".repeat(50)))
+        );
+    }
+
+    #[test]
+    fn test_write() {
+        let fields = ArduPilotMega.fields(22).unwrap();
+        let field = |name| fields.iter().find(|field| field.name == name).unwrap();
+
+        let mut payload = [0xFFu8; 25];
+        let text = FieldValue::Text("SYSID_THISMAV".to_string());
+        field("param_value")
+            .write(&mut payload, &FieldValue::F32(2.5))
+            .unwrap();
+        field("param_count")
+            .write(&mut payload, &FieldValue::U16(1000))
+            .unwrap();
+        // Converted to the type of the field
+        field("param_index")
+            .write(&mut payload, &FieldValue::U8(7))
+            .unwrap();
+        field("param_id").write(&mut payload, &text).unwrap();
+        field("param_type")
+            .write(&mut payload, &FieldValue::F64(9.0))
+            .unwrap();
+
+        assert_eq!(
+            field("param_value").read(&payload).unwrap(),
+            FieldValue::F32(2.5)
+        );
+        assert_eq!(
+            field("param_count").read(&payload).unwrap(),
+            FieldValue::U16(1000)
+        );
+        assert_eq!(
+            field("param_id").read(&payload).unwrap(),
+            FieldValue::Text("SYSID_THISMAV".to_string())
+        );
+        assert_eq!(
+            field("param_index").read(&payload).unwrap(),
+            FieldValue::U16(7)
+        );
+        assert_eq!(
+            field("param_type").read(&payload).unwrap(),
+            FieldValue::U8(9)
+        );
+
+        // Arrays are zero-padded
+        let mut payload = [0xFFu8; 254];
+        let field = ArduPilotMega.fields(110).unwrap()[3];
+        field
+            .write(
+                &mut payload,
+                &FieldValue::Array(vec![FieldValue::U8(1), FieldValue::U8(2)]),
+            )
+            .unwrap();
+        assert_eq!(&payload[3..6], &[1, 2, 0]);
+        assert_eq!(payload[253], 0);
+    }
+
+    #[test]
+    fn test_write_invalid() {
+        let fields = ArduPilotMega.fields(22).unwrap();
+        let field = |name| fields.iter().find(|field| field.name == name).unwrap();
+        let invalid = |name| {
+            Err(ValidationError::InvalidFieldValue {
+                field: name,
+                field_type: field(name).field_type,
+            })
+        };
+
+        let mut payload = [0u8; 25];
+        let text = FieldValue::Text("SYSID_THISMAV".to_string());
+        let array = FieldValue::Array(vec![FieldValue::U8(1)]);
+
+        // Text into a number
+        assert_eq!(
+            field("param_count").write(&mut payload, &text),
+            invalid("param_count")
+        );
+        // An array into a scalar
+        assert_eq!(
+            field("param_value").write(&mut payload, &array),
+            invalid("param_value")
+        );
+        // A number into an array
+        assert_eq!(
+            field("param_id").write(&mut payload, &FieldValue::U8(1)),
+            invalid("param_id")
+        );
+        // Text inside an array
+        assert_eq!(
+            field("param_id").write(&mut payload, &FieldValue::Array(vec![text])),
+            invalid("param_id")
+        );
+        // Integers out of the range of the field
+        assert_eq!(
+            field("param_type").write(&mut payload, &FieldValue::U16(1000)),
+            invalid("param_type")
+        );
+        assert_eq!(
+            field("param_index").write(&mut payload, &FieldValue::I32(-1)),
+            invalid("param_index")
+        );
+        assert_eq!(
+            field("param_count").write(&mut payload, &FieldValue::F64(70000.0)),
+            invalid("param_count")
+        );
+        assert_eq!(
+            field("param_count").write(&mut payload, &FieldValue::F32(f32::NAN)),
+            invalid("param_count")
+        );
+        assert_eq!(payload, [0u8; 25]);
+
+        // In range, whatever the type of the value
+        field("param_type")
+            .write(&mut payload, &FieldValue::U64(9))
+            .unwrap();
+        field("param_count")
+            .write(&mut payload, &FieldValue::F64(1000.7))
+            .unwrap();
+        assert_eq!(field("param_type").read(&payload), Some(FieldValue::U8(9)));
+        assert_eq!(
+            field("param_count").read(&payload),
+            Some(FieldValue::U16(1000))
+        );
+
+        // Text only fits `char` arrays
+        let field = ArduPilotMega.fields(110).unwrap()[3];
+        assert_eq!(
+            field.write(&mut [0u8; 254], &FieldValue::Text("a".to_string())),
+            Err(ValidationError::InvalidFieldValue {
+                field: field.name,
+                field_type: FieldType::U8,
+            })
+        );
+
+        // The payload is too short
+        assert_eq!(
+            field.write(&mut [0u8; 25], &array),
+            Err(ValidationError::InvalidSize {
+                expected: 254,
+                actual: 25,
+            })
+        );
+    }
+}
@@ -0,0 +1,295 @@
+//! Composable packet filters, on header fields only.
+//!
+//! A [`PacketFilter`] can be checked directly with [`PacketFilter::matches`], applied to a packet
+//! stream with `PacketFilter::filter_stream` (with the `async` feature), or attached to a decoder
+//! with [`FilteredCodec`].
+
+use std::collections::BTreeSet;
+
+use bytes::BytesMut;
+#[cfg(feature = "async")]
+use futures::{future, Stream, StreamExt};
+use log::trace;
+use tokio_util::codec::{Decoder, Encoder};
+
+use crate::{error::DecoderError, Packet};
+
+/// A rule on the packet header, built from allow and deny lists combined with AND and OR.
+///
+/// ```
+/// use mavlink_codec::filter::PacketFilter;
+///
+/// // Everything from system 1 but its ATTITUDE, and any signed packet
+/// let filter = PacketFilter::allow_system_ids([1])
+///     .and(PacketFilter::deny_message_ids([30]))
+///     .or(PacketFilter::signed(true));
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub enum PacketFilter {
+    /// Matches every packet
+    #[default]
+    Pass,
+    MessageIds(BTreeSet<u32>),
+    SystemIds(BTreeSet<u8>),
+    ComponentIds(BTreeSet<u8>),
+    /// The MAVLink version, 1 or 2
+    Version(u8),
+    /// MAVLink V1 packets are never signed
+    Signed(bool),
+    Not(Box<PacketFilter>),
+    /// Matches when every filter matches, so an empty list matches every packet
+    And(Vec<PacketFilter>),
+    /// Matches when any filter matches, so an empty list matches no packet
+    Or(Vec<PacketFilter>),
+}
+
+impl PacketFilter {
+    pub fn allow_message_ids(message_ids: impl IntoIterator<Item = u32>) -> Self {
+        Self::MessageIds(message_ids.into_iter().collect())
+    }
+
+    pub fn deny_message_ids(message_ids: impl IntoIterator<Item = u32>) -> Self {
+        Self::allow_message_ids(message_ids).negate()
+    }
+
+    pub fn allow_system_ids(system_ids: impl IntoIterator<Item = u8>) -> Self {
+        Self::SystemIds(system_ids.into_iter().collect())
+    }
+
+    pub fn deny_system_ids(system_ids: impl IntoIterator<Item = u8>) -> Self {
+        Self::allow_system_ids(system_ids).negate()
+    }
+
+    pub fn allow_component_ids(component_ids: impl IntoIterator<Item = u8>) -> Self {
+        Self::ComponentIds(component_ids.into_iter().collect())
+    }
+
+    pub fn deny_component_ids(component_ids: impl IntoIterator<Item = u8>) -> Self {
+        Self::allow_component_ids(component_ids).negate()
+    }
+
+    pub fn version(version: u8) -> Self {
+        Self::Version(version)
+    }
+
+    pub fn signed(signed: bool) -> Self {
+        Self::Signed(signed)
+    }
+
+    /// Matches when both filters match
+    pub fn and(self, other: PacketFilter) -> Self {
+        match self {
+            Self::And(mut filters) => {
+                filters.push(other);
+                Self::And(filters)
+            }
+            filter => Self::And(vec![filter, other]),
+        }
+    }
+
+    /// Matches when any of the filters match
+    pub fn or(self, other: PacketFilter) -> Self {
+        match self {
+            Self::Or(mut filters) => {
+                filters.push(other);
+                Self::Or(filters)
+            }
+            filter => Self::Or(vec![filter, other]),
+        }
+    }
+
+    /// Matches when this filter doesn't
+    pub fn negate(self) -> Self {
+        match self {
+            Self::Not(filter) => *filter,
+            filter => Self::Not(Box::new(filter)),
+        }
+    }
+
+    pub fn matches(&self, packet: &Packet) -> bool {
+        match self {
+            Self::Pass => true,
+            Self::MessageIds(message_ids) => message_ids.contains(&packet.message_id()),
+            Self::SystemIds(system_ids) => system_ids.contains(packet.system_id()),
+            Self::ComponentIds(component_ids) => component_ids.contains(packet.component_id()),
+            Self::Version(version) => match packet {
+                Packet::V1(_) => *version == 1,
+                Packet::V2(_) => *version == 2,
+            },
+            Self::Signed(signed) => match packet {
+                Packet::V1(_) => !*signed,
+                Packet::V2(packet) => packet.has_signature() == *signed,
+            },
+            Self::Not(filter) => !filter.matches(packet),
+            Self::And(filters) => filters.iter().all(|filter| filter.matches(packet)),
+            Self::Or(filters) => filters.iter().any(|filter| filter.matches(packet)),
+        }
+    }
+
+    /// Only lets the matching packets through
+    #[cfg(feature = "async")]
+    pub fn filter_stream<S: Stream<Item = Packet>>(self, stream: S) -> impl Stream<Item = Packet> {
+        stream.filter(move |packet| future::ready(self.matches(packet)))
+    }
+}
+
+/// Wraps a packet decoder, silently dropping the packets not matching its filter.
+///
+/// Decoding errors are let through, and encoding isn't filtered.
+#[derive(Debug, Default)]
+pub struct FilteredCodec<C> {
+    pub codec: C,
+    pub filter: PacketFilter,
+}
+
+impl<C> FilteredCodec<C> {
+    pub fn new(codec: C, filter: PacketFilter) -> Self {
+        Self { codec, filter }
+    }
+}
+
+impl<C> Decoder for FilteredCodec<C>
+where
+    C: Decoder<Item = Result<Packet, DecoderError>>,
+{
+    type Item = C::Item;
+    type Error = C::Error;
+
+    fn decode(&mut self, buf: &mut BytesMut) -> Result<Option<Self::Item>, Self::Error> {
+        loop {
+            match self.codec.decode(buf)? {
+                Some(Ok(packet)) if !self.filter.matches(&packet) => {
+                    trace!("Filtered out message {}", packet.message_id());
+                }
+                item => return Ok(item),
+            }
+        }
+    }
+
+    fn decode_eof(&mut self, buf: &mut BytesMut) -> Result<Option<Self::Item>, Self::Error> {
+        loop {
+            match self.codec.decode_eof(buf)? {
+                Some(Ok(packet)) if !self.filter.matches(&packet) => {
+                    trace!("Filtered out message {}", packet.message_id());
+                }
+                item => return Ok(item),
+            }
+        }
+    }
+}
+
+impl<C: Encoder<Packet>> Encoder<Packet> for FilteredCodec<C> {
+    type Error = C::Error;
+
+    fn encode(&mut self, packet: Packet, buf: &mut BytesMut) -> Result<(), Self::Error> {
+        self.codec.encode(packet, buf)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{
+        codec::MavlinkCodec,
+        test_util::{create_v1_packet, create_v2_packet, header},
+    };
+    use bytes::BufMut;
+
+    #[test]
+    fn test_allow_deny() {
+        let heartbeat = create_v2_packet(header(1, 1, 0), 0);
+        let attitude = create_v2_packet(header(1, 1, 0), 30);
+        let gcs_heartbeat = create_v1_packet(header(255, 190, 0), 0);
+
+        let filter = PacketFilter::allow_message_ids([0]);
+        assert!(filter.matches(&heartbeat));
+        assert!(!filter.matches(&attitude));
+
+        let filter = PacketFilter::deny_system_ids([255]);
+        assert!(filter.matches(&attitude));
+        assert!(!filter.matches(&gcs_heartbeat));
+
+        let filter = PacketFilter::allow_component_ids([190]);
+        assert!(filter.matches(&gcs_heartbeat));
+        assert!(!filter.matches(&heartbeat));
+
+        assert!(PacketFilter::version(1).matches(&gcs_heartbeat));
+        assert!(!PacketFilter::version(1).matches(&heartbeat));
+        assert!(PacketFilter::signed(false).matches(&gcs_heartbeat));
+        assert!(!PacketFilter::signed(true).matches(&heartbeat));
+
+        assert!(PacketFilter::default().matches(&heartbeat));
+    }
+
+    #[test]
+    fn test_composition() {
+        let heartbeat = create_v2_packet(header(1, 1, 0), 0);
+        let attitude = create_v2_packet(header(1, 1, 0), 30);
+        let other_heartbeat = create_v2_packet(header(2, 1, 0), 0);
+        let gcs_heartbeat = create_v1_packet(header(255, 190, 0), 0);
+
+        // Everything from system 1 but its ATTITUDE, and MAVLink V1 packets
+        let filter = PacketFilter::allow_system_ids([1])
+            .and(PacketFilter::deny_message_ids([30]))
+            .or(PacketFilter::version(1));
+
+        assert!(filter.matches(&heartbeat));
+        assert!(!filter.matches(&attitude));
+        assert!(!filter.matches(&other_heartbeat));
+        assert!(filter.matches(&gcs_heartbeat));
+
+        assert!(!filter.clone().negate().matches(&heartbeat));
+        assert_eq!(filter.clone().negate().negate(), filter);
+
+        assert!(PacketFilter::And(vec![]).matches(&heartbeat));
+        assert!(!PacketFilter::Or(vec![]).matches(&heartbeat));
+    }
+
+    #[cfg(feature = "async")]
+    #[tokio::test]
+    async fn test_filter_stream() {
+        let packets = vec![
+            create_v2_packet(header(1, 1, 0), 0),
+            create_v2_packet(header(1, 1, 0), 30),
+            create_v2_packet(header(2, 1, 0), 0),
+        ];
+
+        let filtered = PacketFilter::allow_message_ids([0])
+            .filter_stream(futures::stream::iter(packets.clone()))
+            .collect::<Vec<_>>()
+            .await;
+
+        assert_eq!(filtered, vec![packets[0].clone(), packets[2].clone()]);
+    }
+
+    #[test]
+    fn test_filtered_codec() {
+        let packets = [
+            create_v2_packet(header(1, 1, 0), 30),
+            create_v2_packet(header(1, 1, 0), 0),
+            create_v2_packet(header(1, 1, 0), 30),
+        ];
+
+        let mut buf = BytesMut::new();
+        for packet in &packets {
+            buf.put(packet.as_slice());
+        }
+        let last_crc_byte = buf.len() - 1;
+        buf[last_crc_byte] ^= 0xFF; // Corrupt the CRC of the last packet
+
+        let mut codec = FilteredCodec::new(
+            MavlinkCodec::<true, true, false, false, false, false>::default(),
+            PacketFilter::allow_message_ids([0]),
+        );
+
+        assert!(matches!(
+            codec.decode(&mut buf).unwrap(),
+            Some(Ok(packet)) if packet == packets[1]
+        ));
+        // Errors aren't filtered
+        assert!(matches!(
+            codec.decode(&mut buf).unwrap(),
+            Some(Err(DecoderError::InvalidCRC { .. }))
+        ));
+    }
+}
@@ -0,0 +1,1023 @@
+//! MAVLink FTP, the file transfer protocol carried by FILE_TRANSFER_PROTOCOL.
+
+use std::time::Duration;
+
+use log::debug;
+use thiserror::Error;
+use tokio::time::Instant;
+
+use crate::{
+    client::{ClientError, Connection},
+    dialect::{ArduPilotMega, Dialect},
+    field::FieldValue,
+    Packet,
+};
+
+const FILE_TRANSFER_PROTOCOL: u32 = 110;
+
+/// Size of the header of the FTP payload, before its data
+const HEADER_SIZE: usize = 12;
+
+/// Maximum size of the data of a single FTP message
+pub const MAX_DATA_SIZE: usize = 239;
+
+/// Most memory reserved ahead for a file read, whatever size the component reports
+const MAX_READ_RESERVE: u32 = 1 << 20;
+
+/// Why an FTP client failed
+#[derive(Error, Debug)]
+#[non_exhaustive]
+pub enum FtpError {
+    #[error("FTP request refused: {0:?}")]
+    Nak(FtpNak),
+
+    #[error("path longer than the data of an FTP message: {path}")]
+    PathTooLong { path: String },
+
+    #[error(transparent)]
+    Client(#[from] ClientError),
+}
+
+/// The operation of an FTP message
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum FtpOpcode {
+    #[default]
+    None,
+    TerminateSession,
+    ResetSessions,
+    ListDirectory,
+    OpenFileRO,
+    ReadFile,
+    CreateFile,
+    WriteFile,
+    RemoveFile,
+    CreateDirectory,
+    RemoveDirectory,
+    OpenFileWO,
+    TruncateFile,
+    Rename,
+    CalcFileCRC32,
+    BurstReadFile,
+    Ack,
+    Nak,
+    Unknown(u8),
+}
+
+impl From<u8> for FtpOpcode {
+    fn from(value: u8) -> Self {
+        match value {
+            0 => FtpOpcode::None,
+            1 => FtpOpcode::TerminateSession,
+            2 => FtpOpcode::ResetSessions,
+            3 => FtpOpcode::ListDirectory,
+            4 => FtpOpcode::OpenFileRO,
+            5 => FtpOpcode::ReadFile,
+            6 => FtpOpcode::CreateFile,
+            7 => FtpOpcode::WriteFile,
+            8 => FtpOpcode::RemoveFile,
+            9 => FtpOpcode::CreateDirectory,
+            10 => FtpOpcode::RemoveDirectory,
+            11 => FtpOpcode::OpenFileWO,
+            12 => FtpOpcode::TruncateFile,
+            13 => FtpOpcode::Rename,
+            14 => FtpOpcode::CalcFileCRC32,
+            15 => FtpOpcode::BurstReadFile,
+            128 => FtpOpcode::Ack,
+            129 => FtpOpcode::Nak,
+            value => FtpOpcode::Unknown(value),
+        }
+    }
+}
+
+impl FtpOpcode {
+    pub fn as_u8(&self) -> u8 {
+        match *self {
+            FtpOpcode::None => 0,
+            FtpOpcode::TerminateSession => 1,
+            FtpOpcode::ResetSessions => 2,
+            FtpOpcode::ListDirectory => 3,
+            FtpOpcode::OpenFileRO => 4,
+            FtpOpcode::ReadFile => 5,
+            FtpOpcode::CreateFile => 6,
+            FtpOpcode::WriteFile => 7,
+            FtpOpcode::RemoveFile => 8,
+            FtpOpcode::CreateDirectory => 9,
+            FtpOpcode::RemoveDirectory => 10,
+            FtpOpcode::OpenFileWO => 11,
+            FtpOpcode::TruncateFile => 12,
+            FtpOpcode::Rename => 13,
+            FtpOpcode::CalcFileCRC32 => 14,
+            FtpOpcode::BurstReadFile => 15,
+            FtpOpcode::Ack => 128,
+            FtpOpcode::Nak => 129,
+            FtpOpcode::Unknown(value) => value,
+        }
+    }
+}
+
+/// Why a request was refused, from the data of a NAK
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FtpNak {
+    None,
+    Fail,
+    /// A failure with its errno
+    FailErrno(u8),
+    InvalidDataSize,
+    InvalidSession,
+    NoSessionsAvailable,
+    /// The offset is past the end of the file or directory
+    Eof,
+    UnknownCommand,
+    FileExists,
+    FileProtected,
+    FileNotFound,
+    Unknown(u8),
+}
+
+impl FtpNak {
+    pub fn from_data(data: &[u8]) -> Self {
+        match data.first().copied().unwrap_or_default() {
+            0 => FtpNak::None,
+            1 => FtpNak::Fail,
+            2 => FtpNak::FailErrno(data.get(1).copied().unwrap_or_default()),
+            3 => FtpNak::InvalidDataSize,
+            4 => FtpNak::InvalidSession,
+            5 => FtpNak::NoSessionsAvailable,
+            6 => FtpNak::Eof,
+            7 => FtpNak::UnknownCommand,
+            8 => FtpNak::FileExists,
+            9 => FtpNak::FileProtected,
+            10 => FtpNak::FileNotFound,
+            value => FtpNak::Unknown(value),
+        }
+    }
+
+    pub fn to_data(&self) -> Vec<u8> {
+        match *self {
+            FtpNak::None => vec![0],
+            FtpNak::Fail => vec![1],
+            FtpNak::FailErrno(errno) => vec![2, errno],
+            FtpNak::InvalidDataSize => vec![3],
+            FtpNak::InvalidSession => vec![4],
+            FtpNak::NoSessionsAvailable => vec![5],
+            FtpNak::Eof => vec![6],
+            FtpNak::UnknownCommand => vec![7],
+            FtpNak::FileExists => vec![8],
+            FtpNak::FileProtected => vec![9],
+            FtpNak::FileNotFound => vec![10],
+            FtpNak::Unknown(value) => vec![value],
+        }
+    }
+}
+
+/// The `payload` of FILE_TRANSFER_PROTOCOL
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct FtpMessage {
+    pub seq_number: u16,
+    pub session: u8,
+    pub opcode: FtpOpcode,
+    /// Size of the data, or of the data to read
+    pub size: u8,
+    /// The opcode of the request, in ACK and NAK
+    pub req_opcode: FtpOpcode,
+    /// The last reply of a burst
+    pub burst_complete: bool,
+    pub offset: u32,
+    pub data: Vec<u8>,
+}
+
+impl FtpMessage {
+    pub fn new(opcode: FtpOpcode) -> Self {
+        Self {
+            opcode,
+            ..Default::default()
+        }
+    }
+
+    pub fn with_session(mut self, session: u8) -> Self {
+        self.session = session;
+        self
+    }
+
+    pub fn with_offset(mut self, offset: u32) -> Self {
+        self.offset = offset;
+        self
+    }
+
+    /// Sets the data and its size, truncated to [`MAX_DATA_SIZE`]
+    pub fn with_data(mut self, data: impl Into<Vec<u8>>) -> Self {
+        self.data = data.into();
+        self.data.truncate(MAX_DATA_SIZE);
+        self.size = self.data.len() as u8;
+        self
+    }
+
+    /// Reads the FTP payload, zero-extended like truncated MAVLink V2 payloads
+    pub fn from_payload(payload: &[u8]) -> Self {
+        let byte = |index: usize| payload.get(index).copied().unwrap_or_default();
+
+        let size = byte(4);
+        let data = (0..(size as usize).min(MAX_DATA_SIZE))
+            .map(|index| byte(HEADER_SIZE + index))
+            .collect();
+
+        Self {
+            seq_number: u16::from_le_bytes([byte(0), byte(1)]),
+            session: byte(2),
+            opcode: FtpOpcode::from(byte(3)),
+            size,
+            req_opcode: FtpOpcode::from(byte(5)),
+            burst_complete: byte(6) != 0,
+            offset: u32::from_le_bytes([byte(8), byte(9), byte(10), byte(11)]),
+            data,
+        }
+    }
+
+    pub fn to_payload(&self) -> Vec<u8> {
+        let mut payload = Vec::with_capacity(HEADER_SIZE + self.data.len());
+        payload.extend(self.seq_number.to_le_bytes());
+        payload.push(self.session);
+        payload.push(self.opcode.as_u8());
+        payload.push(self.size);
+        payload.push(self.req_opcode.as_u8());
+        payload.push(self.burst_complete as u8);
+        payload.push(0); // padding
+        payload.extend(self.offset.to_le_bytes());
+        payload.extend(&self.data[..self.data.len().min(MAX_DATA_SIZE)]);
+
+        payload
+    }
+
+    /// Reads a FILE_TRANSFER_PROTOCOL, or `None` for any other message
+    pub fn from_packet<D: Dialect>(packet: &Packet, dialect: &D) -> Option<Self> {
+        if packet.message_id() != FILE_TRANSFER_PROTOCOL {
+            return None;
+        }
+
+        let Some(FieldValue::Array(values)) = packet.field("payload", dialect) else {
+            return None;
+        };
+        let payload = values
+            .iter()
+            .map(|value| value.as_i64().unwrap_or_default() as u8)
+            .collect::<Vec<_>>();
+
+        Some(Self::from_payload(&payload))
+    }
+
+    /// The fields of the FILE_TRANSFER_PROTOCOL of this message, on the default network
+    pub fn fields(&self) -> Vec<(&'static str, FieldValue)> {
+        vec![
+            ("target_network", FieldValue::U8(0)),
+            (
+                "payload",
+                FieldValue::Array(self.to_payload().into_iter().map(FieldValue::U8).collect()),
+            ),
+        ]
+    }
+}
+
+/// An entry of a directory listing
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DirectoryEntry {
+    File { name: String, size: u32 },
+    Directory { name: String },
+}
+
+impl DirectoryEntry {
+    pub fn name(&self) -> &str {
+        match self {
+            DirectoryEntry::File { name, .. } | DirectoryEntry::Directory { name } => name,
+        }
+    }
+
+    /// Parses `F<name>\t<size>` and `D<name>`, or `None` for skipped entries and the like
+    fn parse(entry: &[u8]) -> Option<Self> {
+        let entry = String::from_utf8_lossy(entry);
+
+        match entry.split_at_checked(1)? {
+            ("F", file) => {
+                let (name, size) = file.split_once('\t').unwrap_or((file, "0"));
+                Some(DirectoryEntry::File {
+                    name: name.to_string(),
+                    size: size.parse().unwrap_or_default(),
+                })
+            }
+            ("D", name) => Some(DirectoryEntry::Directory {
+                name: name.to_string(),
+            }),
+            _ => None,
+        }
+    }
+}
+
+/// The CRC-32 of CalcFileCRC32: reflected, of polynomial 0x04C11DB7, without the initial and
+/// final inversions
+pub fn crc32(data: &[u8]) -> u32 {
+    data.iter().fold(0, |crc, byte| {
+        (0..8).fold(crc ^ *byte as u32, |crc, _| {
+            (crc >> 1) ^ (0xEDB8_8320 & (crc & 1).wrapping_neg())
+        })
+    })
+}
+
+/// Lists, reads, writes and removes the files of a component, with MAVLink FTP.
+///
+/// Each operation on a file opens its own session, terminated even when the operation fails.
+/// Unanswered requests are sent again with the same sequence number, for the component to
+/// send its reply again.
+#[derive(Debug)]
+pub struct FtpClient<D: Dialect = ArduPilotMega> {
+    connection: Connection<D>,
+    timeout: Duration,
+    retries: u32,
+    seq_number: u16,
+}
+
+impl<D: Dialect> FtpClient<D> {
+    pub const DEFAULT_TIMEOUT: Duration = Duration::from_secs(1);
+    pub const DEFAULT_RETRIES: u32 = 2;
+
+    pub fn new(connection: Connection<D>) -> Self {
+        Self {
+            connection,
+            timeout: Self::DEFAULT_TIMEOUT,
+            retries: Self::DEFAULT_RETRIES,
+            seq_number: 0,
+        }
+    }
+
+    /// How long to wait for a reply, or for the next reply of a burst, before requesting again
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// How many times to send again unanswered requests
+    pub fn with_retries(mut self, retries: u32) -> Self {
+        self.retries = retries;
+        self
+    }
+
+    pub fn connection(&self) -> &Connection<D> {
+        &self.connection
+    }
+
+    pub fn into_connection(self) -> Connection<D> {
+        self.connection
+    }
+
+    /// The entries of a directory, in the order listed by the component
+    pub async fn list_directory(&mut self, path: &str) -> Result<Vec<DirectoryEntry>, FtpError> {
+        let mut entries = Vec::new();
+        let mut offset = 0;
+
+        loop {
+            let request = path_request(FtpOpcode::ListDirectory, path)?.with_offset(offset);
+            let reply = match self.request(request).await {
+                Ok(reply) => reply,
+                Err(FtpError::Nak(FtpNak::Eof)) => break,
+                Err(error) => return Err(error),
+            };
+
+            let listed = reply
+                .data
+                .split(|byte| *byte == 0)
+                .filter(|entry| !entry.is_empty())
+                .collect::<Vec<_>>();
+            if listed.is_empty() {
+                break;
+            }
+
+            offset += listed.len() as u32;
+            entries.extend(listed.into_iter().filter_map(DirectoryEntry::parse));
+        }
+
+        Ok(entries)
+    }
+
+    /// Reads a whole file, with burst reads
+    pub async fn read_file(&mut self, path: &str) -> Result<Vec<u8>, FtpError> {
+        let reply = self
+            .request(path_request(FtpOpcode::OpenFileRO, path)?)
+            .await?;
+        let session = reply.session;
+        let mut size = [0u8; 4];
+        for (byte, data) in size.iter_mut().zip(&reply.data) {
+            *byte = *data;
+        }
+
+        let data = self.burst_read(session, u32::from_le_bytes(size)).await;
+        let terminated = self.terminate_session(session).await;
+
+        let data = data?;
+        terminated?;
+        debug!("Read {} bytes from {path}", data.len());
+
+        Ok(data)
+    }
+
+    /// Creates or truncates a file, and writes `data` to it
+    pub async fn write_file(&mut self, path: &str, data: &[u8]) -> Result<(), FtpError> {
+        let reply = self
+            .request(path_request(FtpOpcode::CreateFile, path)?)
+            .await?;
+        let session = reply.session;
+
+        let written = self.write(session, data).await;
+        let terminated = self.terminate_session(session).await;
+
+        written?;
+        terminated?;
+        debug!("Wrote {} bytes to {path}", data.len());
+
+        Ok(())
+    }
+
+    pub async fn remove_file(&mut self, path: &str) -> Result<(), FtpError> {
+        self.request(path_request(FtpOpcode::RemoveFile, path)?)
+            .await?;
+
+        Ok(())
+    }
+
+    /// The [`crc32`] of a file, computed by the component
+    pub async fn crc32(&mut self, path: &str) -> Result<u32, FtpError> {
+        let reply = self
+            .request(path_request(FtpOpcode::CalcFileCRC32, path)?)
+            .await?;
+
+        let mut crc = [0u8; 4];
+        for (byte, data) in crc.iter_mut().zip(&reply.data) {
+            *byte = *data;
+        }
+
+        Ok(u32::from_le_bytes(crc))
+    }
+
+    /// Terminates all the sessions of the component, like the ones left by another client
+    pub async fn reset_sessions(&mut self) -> Result<(), FtpError> {
+        self.request(FtpMessage::new(FtpOpcode::ResetSessions))
+            .await?;
+
+        Ok(())
+    }
+
+    async fn terminate_session(&mut self, session: u8) -> Result<(), FtpError> {
+        self.request(FtpMessage::new(FtpOpcode::TerminateSession).with_session(session))
+            .await?;
+
+        Ok(())
+    }
+
+    /// Reads an open file, up to `size`, with bursts starting where the data stops.
+    ///
+    /// The replies after a lost one are dropped, and read again by the next burst.
+    async fn burst_read(&mut self, session: u8, size: u32) -> Result<Vec<u8>, FtpError> {
+        let mut data = Vec::with_capacity(size.min(MAX_READ_RESERVE) as usize);
+        let mut attempts = 0;
+
+        while data.len() < size as usize {
+            if attempts > self.retries {
+                return Err(ClientError::Timeout { attempts }.into());
+            }
+
+            let mut request = FtpMessage::new(FtpOpcode::BurstReadFile)
+                .with_session(session)
+                .with_offset(data.len() as u32);
+            request.seq_number = self.seq_number;
+            request.size = MAX_DATA_SIZE as u8;
+            self.send(&request).await?;
+            attempts += 1;
+
+            let mut deadline = Instant::now() + self.timeout;
+            while let Some(reply) = self.receive(deadline).await? {
+                if reply.req_opcode != FtpOpcode::BurstReadFile || reply.session != session {
+                    continue;
+                }
+                self.seq_number = reply.seq_number.wrapping_add(1);
+
+                match reply.opcode {
+                    FtpOpcode::Ack => {
+                        if reply.offset as usize == data.len() && !reply.data.is_empty() {
+                            data.extend(&reply.data);
+                            attempts = 0;
+                        }
+                        if reply.burst_complete {
+                            break;
+                        }
+                        deadline = Instant::now() + self.timeout;
+                    }
+                    // Shorter than announced
+                    FtpOpcode::Nak if FtpNak::from_data(&reply.data) == FtpNak::Eof => {
+                        return Ok(data);
+                    }
+                    FtpOpcode::Nak => {
+                        return Err(FtpError::Nak(FtpNak::from_data(&reply.data)));
+                    }
+                    _ => (),
+                }
+            }
+        }
+
+        Ok(data)
+    }
+
+    async fn write(&mut self, session: u8, data: &[u8]) -> Result<(), FtpError> {
+        for (index, chunk) in data.chunks(MAX_DATA_SIZE).enumerate() {
+            let request = FtpMessage::new(FtpOpcode::WriteFile)
+                .with_session(session)
+                .with_offset((index * MAX_DATA_SIZE) as u32)
+                .with_data(chunk);
+
+            self.request(request).await?;
+        }
+
+        Ok(())
+    }
+
+    /// Sends a request until its ACK, or NAK as a [`FtpError::Nak`]
+    async fn request(&mut self, mut request: FtpMessage) -> Result<FtpMessage, FtpError> {
+        request.seq_number = self.seq_number;
+        let reply_seq_number = request.seq_number.wrapping_add(1);
+        let mut attempts = 0;
+
+        while attempts <= self.retries {
+            self.send(&request).await?;
+            attempts += 1;
+
+            let deadline = Instant::now() + self.timeout;
+            while let Some(reply) = self.receive(deadline).await? {
+                if reply.seq_number != reply_seq_number || reply.req_opcode != request.opcode {
+                    continue;
+                }
+
+                match reply.opcode {
+                    FtpOpcode::Ack => {
+                        self.seq_number = reply_seq_number.wrapping_add(1);
+                        return Ok(reply);
+                    }
+                    FtpOpcode::Nak => {
+                        self.seq_number = reply_seq_number.wrapping_add(1);
+                        return Err(FtpError::Nak(FtpNak::from_data(&reply.data)));
+                    }
+                    _ => (),
+                }
+            }
+        }
+
+        Err(ClientError::Timeout { attempts }.into())
+    }
+
+    async fn send(&mut self, request: &FtpMessage) -> Result<(), ClientError> {
+        self.connection
+            .send(FILE_TRANSFER_PROTOCOL, &request.fields())
+            .await
+    }
+
+    async fn receive(&mut self, deadline: Instant) -> Result<Option<FtpMessage>, ClientError> {
+        while let Some(packet) = self.connection.receive(deadline).await? {
+            if let Some(reply) =
+                FtpMessage::from_packet(&packet, self.connection.builder().dialect())
+            {
+                return Ok(Some(reply));
+            }
+        }
+
+        Ok(None)
+    }
+}
+
+/// A request about the file or directory at `path`, which has to fit whole in the data
+fn path_request(opcode: FtpOpcode, path: &str) -> Result<FtpMessage, FtpError> {
+    if path.len() > MAX_DATA_SIZE {
+        return Err(FtpError::PathTooLong {
+            path: path.to_string(),
+        });
+    }
+
+    Ok(FtpMessage::new(opcode).with_data(path))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::builder::PacketBuilder;
+    use futures::{channel::mpsc, SinkExt, StreamExt};
+    use std::{
+        collections::BTreeSet,
+        fs,
+        io::{Seek, SeekFrom, Write},
+        path::PathBuf,
+    };
+
+    /// A directory removed when dropped
+    struct TempDir(PathBuf);
+
+    impl TempDir {
+        fn new(name: &str) -> Self {
+            let path = std::env::temp_dir()
+                .join(format!("mavlink-codec-ftp-{}-{name}", std::process::id()));
+            let _ = fs::remove_dir_all(&path);
+            fs::create_dir_all(&path).unwrap();
+
+            Self(path)
+        }
+    }
+
+    impl Drop for TempDir {
+        fn drop(&mut self) {
+            let _ = fs::remove_dir_all(&self.0);
+        }
+    }
+
+    enum Session {
+        Read(Vec<u8>),
+        Write(PathBuf),
+    }
+
+    /// A component serving the files of a directory, with a single session
+    struct FtpServer {
+        root: PathBuf,
+        session: Option<Session>,
+        /// Replies per burst
+        burst_length: usize,
+        /// The burst replies lost, by their number
+        lost: BTreeSet<usize>,
+        burst_replies: usize,
+    }
+
+    impl FtpServer {
+        fn new(root: &TempDir) -> Self {
+            Self {
+                root: root.0.clone(),
+                session: None,
+                burst_length: 4,
+                lost: BTreeSet::new(),
+                burst_replies: 0,
+            }
+        }
+
+        fn path(&self, request: &FtpMessage) -> PathBuf {
+            let path = String::from_utf8_lossy(&request.data);
+            self.root.join(path.trim_start_matches('/'))
+        }
+
+        fn answer(&mut self, request: &FtpMessage) -> Vec<FtpMessage> {
+            let ack = |data: Vec<u8>| {
+                FtpMessage {
+                    seq_number: request.seq_number.wrapping_add(1),
+                    session: request.session,
+                    opcode: FtpOpcode::Ack,
+                    req_opcode: request.opcode,
+                    offset: request.offset,
+                    ..Default::default()
+                }
+                .with_data(data)
+            };
+            let nak = |nak: FtpNak| FtpMessage {
+                opcode: FtpOpcode::Nak,
+                ..ack(nak.to_data())
+            };
+            let not_found = |error: std::io::Error| match error.kind() {
+                std::io::ErrorKind::NotFound => nak(FtpNak::FileNotFound),
+                _ => nak(FtpNak::Fail),
+            };
+
+            let reply = match request.opcode {
+                FtpOpcode::ListDirectory => {
+                    let mut entries = match fs::read_dir(self.path(request)) {
+                        Ok(entries) => entries
+                            .map(|entry| entry.unwrap())
+                            .map(|entry| {
+                                let name = entry.file_name().to_string_lossy().into_owned();
+                                let metadata = entry.metadata().unwrap();
+                                if metadata.is_dir() {
+                                    format!("D{name}")
+                                } else {
+                                    format!("F{name}\t{}", metadata.len())
+                                }
+                            })
+                            .collect::<Vec<_>>(),
+                        Err(error) => return vec![not_found(error)],
+                    };
+                    entries.sort();
+
+                    let mut data = Vec::new();
+                    for entry in entries.iter().skip(request.offset as usize) {
+                        if data.len() + entry.len() + 1 > MAX_DATA_SIZE {
+                            break;
+                        }
+                        data.extend(entry.as_bytes());
+                        data.push(0);
+                    }
+
+                    if data.is_empty() {
+                        nak(FtpNak::Eof)
+                    } else {
+                        ack(data)
+                    }
+                }
+                FtpOpcode::OpenFileRO | FtpOpcode::CreateFile if self.session.is_some() => {
+                    nak(FtpNak::NoSessionsAvailable)
+                }
+                FtpOpcode::OpenFileRO => match fs::read(self.path(request)) {
+                    Ok(data) => {
+                        let size = data.len() as u32;
+                        self.session = Some(Session::Read(data));
+                        ack(size.to_le_bytes().to_vec())
+                    }
+                    Err(error) => not_found(error),
+                },
+                FtpOpcode::BurstReadFile => {
+                    let Some(Session::Read(data)) = &self.session else {
+                        return vec![nak(FtpNak::InvalidSession)];
+                    };
+                    if request.offset as usize >= data.len() {
+                        return vec![nak(FtpNak::Eof)];
+                    }
+
+                    let chunks = data[request.offset as usize..]
+                        .chunks(MAX_DATA_SIZE)
+                        .take(self.burst_length)
+                        .collect::<Vec<_>>();
+                    let last = chunks.len() - 1;
+
+                    let mut replies = Vec::new();
+                    for (index, chunk) in chunks.into_iter().enumerate() {
+                        self.burst_replies += 1;
+                        if self.lost.contains(&self.burst_replies) {
+                            continue;
+                        }
+
+                        let mut reply = ack(chunk.to_vec())
+                            .with_offset(request.offset + (index * MAX_DATA_SIZE) as u32);
+                        reply.seq_number = request.seq_number.wrapping_add(1 + index as u16);
+                        reply.burst_complete = index == last;
+                        replies.push(reply);
+                    }
+
+                    return replies;
+                }
+                FtpOpcode::CreateFile => match fs::File::create(self.path(request)) {
+                    Ok(_) => {
+                        self.session = Some(Session::Write(self.path(request)));
+                        ack(vec![])
+                    }
+                    Err(error) => not_found(error),
+                },
+                FtpOpcode::WriteFile => {
+                    let Some(Session::Write(path)) = &self.session else {
+                        return vec![nak(FtpNak::InvalidSession)];
+                    };
+
+                    let mut file = fs::OpenOptions::new().write(true).open(path).unwrap();
+                    file.seek(SeekFrom::Start(request.offset as u64)).unwrap();
+                    file.write_all(&request.data).unwrap();
+                    ack(vec![])
+                }
+                FtpOpcode::RemoveFile => match fs::remove_file(self.path(request)) {
+                    Ok(()) => ack(vec![]),
+                    Err(error) => not_found(error),
+                },
+                FtpOpcode::CalcFileCRC32 => match fs::read(self.path(request)) {
+                    Ok(data) => ack(crc32(&data).to_le_bytes().to_vec()),
+                    Err(error) => not_found(error),
+                },
+                FtpOpcode::TerminateSession | FtpOpcode::ResetSessions => {
+                    self.session = None;
+                    ack(vec![])
+                }
+                _ => nak(FtpNak::UnknownCommand),
+            };
+
+            vec![reply]
+        }
+
+        fn spawn(mut self) -> FtpClient {
+            let (client_sink, mut server_stream) = mpsc::channel::<Packet>(16);
+            let (mut server_sink, client_stream) = mpsc::channel::<Packet>(64);
+
+            tokio::spawn(async move {
+                let builder = PacketBuilder::new(1, 1);
+                while let Some(packet) = server_stream.next().await {
+                    let Some(request) = FtpMessage::from_packet(&packet, &ArduPilotMega) else {
+                        continue;
+                    };
+
+                    for reply in self.answer(&request) {
+                        let mut values = reply.fields();
+                        values.push(("target_system", FieldValue::U8(255)));
+                        values.push(("target_component", FieldValue::U8(190)));
+                        let packet = builder
+                            .build_fields(FILE_TRANSFER_PROTOCOL, &values)
+                            .unwrap();
+
+                        tokio::time::sleep(Duration::from_millis(5)).await;
+                        if server_sink.send(packet).await.is_err() {
+                            return;
+                        }
+                    }
+                }
+            });
+
+            let connection = Connection::from_split(
+                client_stream,
+                client_sink,
+                PacketBuilder::new(255, 190),
+                (1, 1),
+            );
+
+            FtpClient::new(connection)
+        }
+    }
+
+    /// File content, different at each offset of a burst
+    fn content(size: usize) -> Vec<u8> {
+        (0..size).map(|index| (index % 251) as u8).collect()
+    }
+
+    #[test]
+    fn test_ftp_message() {
+        let message = FtpMessage::new(FtpOpcode::OpenFileRO)
+            .with_session(3)
+            .with_offset(1000)
+            .with_data("/APM/LOGS/1.BIN");
+
+        let payload = message.to_payload();
+        assert_eq!(payload.len(), HEADER_SIZE + 15);
+        assert_eq!(FtpMessage::from_payload(&payload), message);
+
+        // Truncated trailing zeroes
+        let message = FtpMessage::new(FtpOpcode::ReadFile).with_data(vec![1, 0, 0]);
+        let payload = message.to_payload();
+        assert_eq!(
+            FtpMessage::from_payload(&payload[..HEADER_SIZE + 1]),
+            message
+        );
+
+        assert_eq!(
+            FtpNak::from_data(&FtpNak::FailErrno(13).to_data()),
+            FtpNak::FailErrno(13)
+        );
+        for value in 0..=255 {
+            assert_eq!(FtpOpcode::from(value).as_u8(), value);
+        }
+    }
+
+    #[test]
+    fn test_payload_field() {
+        // Truncated after the first byte of the FTP payload
+        let packet = PacketBuilder::new(1, 1).build(110, &[0, 1, 1, 42]).unwrap();
+
+        let Some(FieldValue::Array(values)) = packet.field("payload", &ArduPilotMega) else {
+            panic!("expected an array");
+        };
+        assert_eq!(values.len(), 251);
+        assert_eq!(values[0], FieldValue::U8(42));
+        assert_eq!(values[1], FieldValue::U8(0));
+    }
+
+    #[test]
+    fn test_crc32() {
+        assert_eq!(crc32(b"123456789"), 0x2DFD_2D88);
+        assert_eq!(crc32(&[]), 0);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_list_directory() {
+        let root = TempDir::new("list");
+        fs::create_dir(root.0.join("logs")).unwrap();
+        fs::write(root.0.join("params.parm"), content(1234)).unwrap();
+        // More entries than a single reply holds
+        for index in 0..30 {
+            fs::write(root.0.join("logs").join(format!("{index:08}.BIN")), []).unwrap();
+        }
+
+        let mut client = FtpServer::new(&root).spawn();
+
+        assert_eq!(
+            client.list_directory("/").await.unwrap(),
+            vec![
+                DirectoryEntry::Directory {
+                    name: "logs".to_string()
+                },
+                DirectoryEntry::File {
+                    name: "params.parm".to_string(),
+                    size: 1234
+                },
+            ]
+        );
+
+        let logs = client.list_directory("/logs").await.unwrap();
+        assert_eq!(logs.len(), 30);
+        assert_eq!(logs[29].name(), "00000029.BIN");
+
+        assert!(matches!(
+            client.list_directory("/missing").await,
+            Err(FtpError::Nak(FtpNak::FileNotFound))
+        ));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_read_file() {
+        let root = TempDir::new("read");
+        fs::write(root.0.join("00000001.BIN"), content(5000)).unwrap();
+
+        let mut server = FtpServer::new(&root);
+        // Replies of the first and the third bursts
+        server.lost = BTreeSet::from([2, 9, 10]);
+        let mut client = server.spawn();
+
+        assert_eq!(
+            client.read_file("/00000001.BIN").await.unwrap(),
+            content(5000)
+        );
+        // The session was terminated
+        assert_eq!(
+            client.read_file("00000001.BIN").await.unwrap(),
+            content(5000)
+        );
+
+        assert!(matches!(
+            client.read_file("/missing.BIN").await,
+            Err(FtpError::Nak(FtpNak::FileNotFound))
+        ));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_write_remove_and_crc32() {
+        let root = TempDir::new("write");
+        let mut client = FtpServer::new(&root).spawn();
+
+        client
+            .write_file("/mission.txt", &content(1000))
+            .await
+            .unwrap();
+        assert_eq!(fs::read(root.0.join("mission.txt")).unwrap(), content(1000));
+        assert_eq!(
+            client.crc32("/mission.txt").await.unwrap(),
+            crc32(&content(1000))
+        );
+
+        client.remove_file("/mission.txt").await.unwrap();
+        assert!(!root.0.join("mission.txt").exists());
+        assert!(matches!(
+            client.remove_file("/mission.txt").await,
+            Err(FtpError::Nak(FtpNak::FileNotFound))
+        ));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_path_too_long() {
+        let root = TempDir::new("path");
+        let mut client = FtpServer::new(&root).spawn();
+
+        // Not truncated into another path
+        let path = format!("/{}", "a".repeat(MAX_DATA_SIZE));
+        assert!(matches!(
+            client.write_file(&path, &content(10)).await,
+            Err(FtpError::PathTooLong { .. })
+        ));
+        assert!(matches!(
+            client.read_file(&path).await,
+            Err(FtpError::PathTooLong { .. })
+        ));
+        assert!(matches!(
+            client.list_directory(&path).await,
+            Err(FtpError::PathTooLong { .. })
+        ));
+        assert!(matches!(
+            client.remove_file(&path).await,
+            Err(FtpError::PathTooLong { .. })
+        ));
+        assert!(matches!(
+            client.crc32(&path).await,
+            Err(FtpError::PathTooLong { .. })
+        ));
+        assert_eq!(fs::read_dir(&root.0).unwrap().count(), 0);
+
+        let path = format!("/{}", "a".repeat(MAX_DATA_SIZE - 1));
+        client.write_file(&path, &content(10)).await.unwrap();
+        assert_eq!(client.crc32(&path).await.unwrap(), crc32(&content(10)));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_sessions() {
+        let root = TempDir::new("sessions");
+        fs::write(root.0.join("a.txt"), content(10)).unwrap();
+
+        let mut server = FtpServer::new(&root);
+        // Left by another client
+        server.session = Some(Session::Read(vec![]));
+        let mut client = server.spawn();
+
+        assert!(matches!(
+            client.read_file("/a.txt").await,
+            Err(FtpError::Nak(FtpNak::NoSessionsAvailable))
+        ));
+
+        client.reset_sessions().await.unwrap();
+        assert_eq!(client.read_file("/a.txt").await.unwrap(), content(10));
+    }
+}
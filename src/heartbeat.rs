@@ -0,0 +1,573 @@
+//! Presence of systems and components, from their HEARTBEAT.
+
+use std::{
+    collections::HashMap,
+    future::Future,
+    pin::Pin,
+    sync::Arc,
+    task::{ready, Context, Poll},
+    time::Duration,
+};
+
+use futures::{Sink, SinkExt, Stream, StreamExt};
+use log::{debug, trace};
+use tokio::{
+    sync::watch,
+    time::{Instant, MissedTickBehavior, Sleep},
+};
+
+use crate::{
+    builder::PacketBuilder,
+    dialect::{ArduPilotMega, Dialect},
+    field::FieldValue,
+    Packet,
+};
+
+/// The content of a HEARTBEAT
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Heartbeat {
+    pub custom_mode: u32,
+    /// MAV_TYPE
+    pub mav_type: u8,
+    /// MAV_AUTOPILOT
+    pub autopilot: u8,
+    /// MAV_MODE_FLAG bitmask
+    pub base_mode: u8,
+    /// MAV_STATE
+    pub system_status: u8,
+    pub mavlink_version: u8,
+}
+
+impl Heartbeat {
+    pub const MESSAGE_ID: u32 = 0;
+
+    /// Reads a HEARTBEAT packet, or `None` for any other message.
+    ///
+    /// HEARTBEAT is part of the minimal dialect, so it has the same layout in every dialect.
+    pub fn from_packet(packet: &Packet) -> Option<Self> {
+        if packet.message_id() != Self::MESSAGE_ID {
+            return None;
+        }
+
+        let read = |name| match packet.field(name, &ArduPilotMega) {
+            Some(FieldValue::U8(value)) => value,
+            _ => 0,
+        };
+
+        Some(Self {
+            custom_mode: match packet.field("custom_mode", &ArduPilotMega) {
+                Some(FieldValue::U32(value)) => value,
+                _ => 0,
+            },
+            mav_type: read("type"),
+            autopilot: read("autopilot"),
+            base_mode: read("base_mode"),
+            system_status: read("system_status"),
+            mavlink_version: read("mavlink_version"),
+        })
+    }
+
+    /// The payload, in wire order
+    pub fn to_payload(&self) -> [u8; 9] {
+        let mut payload = [0u8; 9];
+        let fields = ArduPilotMega
+            .fields(Self::MESSAGE_ID)
+            .expect("HEARTBEAT fields");
+
+        for field in fields {
+            let offset = field.offset as usize;
+            match field.name {
+                "custom_mode" => {
+                    payload[offset..offset + 4].copy_from_slice(&self.custom_mode.to_le_bytes())
+                }
+                "type" => payload[offset] = self.mav_type,
+                "autopilot" => payload[offset] = self.autopilot,
+                "base_mode" => payload[offset] = self.base_mode,
+                "system_status" => payload[offset] = self.system_status,
+                "mavlink_version" => payload[offset] = self.mavlink_version,
+                _ => (),
+            }
+        }
+
+        payload
+    }
+}
+
+/// A change in the presence of a component
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum HeartbeatEvent {
+    /// First heartbeat, or the first one after a disconnection
+    Connected {
+        system_id: u8,
+        component_id: u8,
+        heartbeat: Heartbeat,
+    },
+    /// No heartbeat within the timeout
+    Disconnected { system_id: u8, component_id: u8 },
+}
+
+/// What is known of a connected component
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ComponentState {
+    pub heartbeat: Heartbeat,
+    pub last_seen: Instant,
+}
+
+/// Tracks the components sending heartbeats, by (System ID, Component ID).
+///
+/// Either feed it packets with [`HeartbeatMonitor::update`] and check the timeouts with
+/// [`HeartbeatMonitor::expire`], or let [`HeartbeatMonitor::events`] do both on a stream.
+#[derive(Debug)]
+pub struct HeartbeatMonitor {
+    timeout: Duration,
+    components: HashMap<(u8, u8), ComponentState>,
+}
+
+impl HeartbeatMonitor {
+    /// MAVLink components are considered disconnected after missing 5 heartbeats of 1 Hz
+    pub const DEFAULT_TIMEOUT: Duration = Duration::from_secs(5);
+
+    pub fn new(timeout: Duration) -> Self {
+        Self {
+            timeout,
+            components: HashMap::new(),
+        }
+    }
+
+    pub fn get(&self, system_id: u8, component_id: u8) -> Option<&ComponentState> {
+        self.components.get(&(system_id, component_id))
+    }
+
+    /// The connected components, by (System ID, Component ID)
+    pub fn components(&self) -> &HashMap<(u8, u8), ComponentState> {
+        &self.components
+    }
+
+    /// Records a heartbeat, returning an event if the component just connected
+    pub fn update(&mut self, packet: &Packet) -> Option<HeartbeatEvent> {
+        let heartbeat = Heartbeat::from_packet(packet)?;
+        let (system_id, component_id) = (*packet.system_id(), *packet.component_id());
+
+        let state = ComponentState {
+            heartbeat,
+            last_seen: Instant::now(),
+        };
+        if self
+            .components
+            .insert((system_id, component_id), state)
+            .is_some()
+        {
+            return None;
+        }
+
+        debug!("Component {system_id}:{component_id} connected");
+        Some(HeartbeatEvent::Connected {
+            system_id,
+            component_id,
+            heartbeat,
+        })
+    }
+
+    /// Forgets the components whose heartbeat timed out, returning their events
+    pub fn expire(&mut self) -> Vec<HeartbeatEvent> {
+        let now = Instant::now();
+
+        let mut expired = self
+            .components
+            .iter()
+            .filter(|(_, state)| now.duration_since(state.last_seen) >= self.timeout)
+            .map(|(source, _)| *source)
+            .collect::<Vec<_>>();
+        expired.sort();
+
+        expired
+            .into_iter()
+            .map(|(system_id, component_id)| {
+                debug!("Component {system_id}:{component_id} disconnected");
+                self.components.remove(&(system_id, component_id));
+                HeartbeatEvent::Disconnected {
+                    system_id,
+                    component_id,
+                }
+            })
+            .collect()
+    }
+
+    /// When the next component times out
+    pub fn next_deadline(&self) -> Option<Instant> {
+        self.components
+            .values()
+            .map(|state| state.last_seen + self.timeout)
+            .min()
+    }
+
+    /// The events of the packets of a stream.
+    ///
+    /// After the stream ends, the remaining components time out before the events end.
+    pub fn events<S: Stream<Item = Packet> + Unpin>(self, stream: S) -> HeartbeatEvents<S> {
+        HeartbeatEvents {
+            stream: Some(stream),
+            monitor: self,
+            pending: Vec::new(),
+            sleep: Box::pin(tokio::time::sleep(Duration::ZERO)),
+        }
+    }
+}
+
+impl Default for HeartbeatMonitor {
+    fn default() -> Self {
+        Self::new(Self::DEFAULT_TIMEOUT)
+    }
+}
+
+/// See [`HeartbeatMonitor::events`]
+#[derive(Debug)]
+pub struct HeartbeatEvents<S> {
+    stream: Option<S>,
+    monitor: HeartbeatMonitor,
+    /// Expired components not yet yielded, in reverse order
+    pending: Vec<HeartbeatEvent>,
+    sleep: Pin<Box<Sleep>>,
+}
+
+impl<S> HeartbeatEvents<S> {
+    pub fn monitor(&self) -> &HeartbeatMonitor {
+        &self.monitor
+    }
+}
+
+impl<S: Stream<Item = Packet> + Unpin> Stream for HeartbeatEvents<S> {
+    type Item = HeartbeatEvent;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = &mut *self;
+
+        loop {
+            if let Some(event) = this.pending.pop() {
+                return Poll::Ready(Some(event));
+            }
+
+            this.pending = this.monitor.expire();
+            if !this.pending.is_empty() {
+                this.pending.reverse();
+                continue;
+            }
+
+            if let Some(stream) = &mut this.stream {
+                match stream.poll_next_unpin(cx) {
+                    Poll::Ready(Some(packet)) => {
+                        if let Some(event) = this.monitor.update(&packet) {
+                            return Poll::Ready(Some(event));
+                        }
+                        continue;
+                    }
+                    Poll::Ready(None) => {
+                        this.stream = None;
+                        continue;
+                    }
+                    Poll::Pending => (),
+                }
+            }
+
+            let Some(deadline) = this.monitor.next_deadline() else {
+                return if this.stream.is_none() {
+                    Poll::Ready(None)
+                } else {
+                    Poll::Pending
+                };
+            };
+
+            this.sleep.as_mut().reset(deadline);
+            ready!(this.sleep.as_mut().poll(cx));
+        }
+    }
+}
+
+/// A handle to change the heartbeat sent by a running [`HeartbeatEmitter`]
+#[derive(Debug, Clone)]
+pub struct HeartbeatControl {
+    sender: Arc<watch::Sender<Heartbeat>>,
+}
+
+impl HeartbeatControl {
+    pub fn get(&self) -> Heartbeat {
+        *self.sender.borrow()
+    }
+
+    pub fn set(&self, heartbeat: Heartbeat) {
+        self.sender.send_replace(heartbeat);
+    }
+
+    /// Changes the heartbeat in place, like `control.modify(|heartbeat| heartbeat.system_status = 4)`
+    pub fn modify(&self, modify: impl FnOnce(&mut Heartbeat)) {
+        self.sender.send_modify(modify);
+    }
+}
+
+/// Sends the heartbeat of a component to a [`Sink`], along with the rest of its traffic.
+///
+/// The heartbeats are built with the [`PacketBuilder`] of the component, so they share the
+/// [`Sequence`](crate::builder::Sequence) of everything else it builds.
+#[derive(Debug)]
+pub struct HeartbeatEmitter<Si, D: Dialect = ArduPilotMega> {
+    sink: Si,
+    builder: PacketBuilder<D>,
+    period: Duration,
+    control: HeartbeatControl,
+    receiver: watch::Receiver<Heartbeat>,
+}
+
+impl<Si, D> HeartbeatEmitter<Si, D>
+where
+    Si: Sink<Packet> + Unpin,
+    D: Dialect,
+{
+    /// MAVLink components send their heartbeat at 1 Hz
+    pub const DEFAULT_PERIOD: Duration = Duration::from_secs(1);
+
+    pub fn new(sink: Si, builder: PacketBuilder<D>, heartbeat: Heartbeat) -> Self {
+        let (sender, receiver) = watch::channel(heartbeat);
+
+        Self {
+            sink,
+            builder,
+            period: Self::DEFAULT_PERIOD,
+            control: HeartbeatControl {
+                sender: Arc::new(sender),
+            },
+            receiver,
+        }
+    }
+
+    pub fn with_period(mut self, period: Duration) -> Self {
+        self.period = period;
+        self
+    }
+
+    pub fn control(&self) -> HeartbeatControl {
+        self.control.clone()
+    }
+
+    /// Sends a heartbeat every period, and the packets of `traffic` as they come, until
+    /// `traffic` ends.
+    ///
+    /// To only send heartbeats, use a [`futures::stream::pending`] traffic.
+    pub async fn run<S: Stream<Item = Packet>>(mut self, traffic: S) -> Result<(), Si::Error> {
+        let mut traffic = std::pin::pin!(traffic);
+
+        let mut interval = tokio::time::interval(self.period);
+        interval.set_missed_tick_behavior(MissedTickBehavior::Delay);
+
+        loop {
+            tokio::select! {
+                _ = interval.tick() => {
+                    let heartbeat = *self.receiver.borrow_and_update();
+                    let packet = self
+                        .builder
+                        .build(Heartbeat::MESSAGE_ID, &heartbeat.to_payload())
+                        .expect("HEARTBEAT is part of every dialect");
+
+                    trace!("Sending heartbeat {}", packet.sequence());
+                    self.sink.send(packet).await?;
+                }
+                packet = traffic.next() => {
+                    let Some(packet) = packet else {
+                        break;
+                    };
+
+                    self.sink.send(packet).await?;
+                }
+            }
+        }
+
+        self.sink.flush().await
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test_util::{create_raw_packet, header};
+
+    const QUADROTOR: Heartbeat = Heartbeat {
+        custom_mode: 4,         // GUIDED
+        mav_type: 2,            // MAV_TYPE_QUADROTOR
+        autopilot: 3,           // MAV_AUTOPILOT_ARDUPILOTMEGA
+        base_mode: 0b1000_0001, // MAV_MODE_FLAG_SAFETY_ARMED | MAV_MODE_FLAG_CUSTOM_MODE_ENABLED
+        system_status: 4,       // MAV_STATE_ACTIVE
+        mavlink_version: 3,
+    };
+
+    #[test]
+    fn test_heartbeat_payload() {
+        let packet = create_raw_packet(
+            header(1, 1, 0),
+            Heartbeat::MESSAGE_ID,
+            &QUADROTOR.to_payload(),
+        );
+
+        assert_eq!(packet.payload(), &[4, 0, 0, 0, 2, 3, 0b1000_0001, 4, 3]);
+        assert_eq!(Heartbeat::from_packet(&packet), Some(QUADROTOR));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_connect_disconnect() {
+        let mut monitor = HeartbeatMonitor::new(Duration::from_secs(3));
+
+        assert_eq!(
+            monitor.update(&create_raw_packet(
+                header(1, 1, 0),
+                Heartbeat::MESSAGE_ID,
+                &QUADROTOR.to_payload()
+            )),
+            Some(HeartbeatEvent::Connected {
+                system_id: 1,
+                component_id: 1,
+                heartbeat: QUADROTOR,
+            })
+        );
+        assert_eq!(monitor.get(1, 1).unwrap().heartbeat, QUADROTOR);
+
+        // Already connected, but the state is updated
+        tokio::time::sleep(Duration::from_secs(2)).await;
+        let disarmed = Heartbeat {
+            base_mode: 1,
+            ..QUADROTOR
+        };
+        assert_eq!(
+            monitor.update(&create_raw_packet(
+                header(1, 1, 0),
+                Heartbeat::MESSAGE_ID,
+                &disarmed.to_payload()
+            )),
+            None
+        );
+        assert_eq!(monitor.get(1, 1).unwrap().heartbeat, disarmed);
+
+        tokio::time::sleep(Duration::from_secs(2)).await;
+        assert!(monitor.expire().is_empty());
+
+        tokio::time::sleep(Duration::from_secs(1)).await;
+        assert_eq!(
+            monitor.expire(),
+            vec![HeartbeatEvent::Disconnected {
+                system_id: 1,
+                component_id: 1,
+            }]
+        );
+        assert!(monitor.components().is_empty());
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_events() {
+        let start = Instant::now();
+        // A vehicle for 3 seconds and a camera for 10 seconds, at 1 Hz
+        let heartbeats = futures::stream::iter(0..10u64)
+            .then(move |second| async move {
+                tokio::time::sleep_until(start + Duration::from_secs(second)).await;
+
+                let mut packets = vec![create_raw_packet(
+                    header(2, 100, 0),
+                    Heartbeat::MESSAGE_ID,
+                    &QUADROTOR.to_payload(),
+                )];
+                if second < 3 {
+                    packets.push(create_raw_packet(
+                        header(1, 1, 0),
+                        Heartbeat::MESSAGE_ID,
+                        &QUADROTOR.to_payload(),
+                    ));
+                }
+                futures::stream::iter(packets)
+            })
+            .flatten();
+
+        let mut events = HeartbeatMonitor::new(Duration::from_millis(2500))
+            .events(Box::pin(heartbeats))
+            .map(|event| (event, start.elapsed().as_millis()));
+
+        assert!(matches!(
+            events.next().await,
+            Some((HeartbeatEvent::Connected { system_id: 2, .. }, 0))
+        ));
+        assert!(matches!(
+            events.next().await,
+            Some((HeartbeatEvent::Connected { system_id: 1, .. }, 0))
+        ));
+        assert_eq!(
+            events.next().await,
+            Some((
+                HeartbeatEvent::Disconnected {
+                    system_id: 1,
+                    component_id: 1
+                },
+                4500
+            ))
+        );
+        assert_eq!(
+            events.next().await,
+            Some((
+                HeartbeatEvent::Disconnected {
+                    system_id: 2,
+                    component_id: 100
+                },
+                11500
+            ))
+        );
+        assert_eq!(events.next().await, None);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_emitter() {
+        let start = Instant::now();
+        let sent = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let sink = futures::sink::unfold(sent.clone(), move |sent, packet: Packet| async move {
+            sent.lock()
+                .unwrap()
+                .push((start.elapsed().as_millis(), packet));
+            Ok::<_, std::io::Error>(sent)
+        });
+
+        let builder = PacketBuilder::new(1, 1);
+        let emitter = HeartbeatEmitter::new(Box::pin(sink), builder.clone(), QUADROTOR);
+        let control = emitter.control();
+
+        // ATTITUDE at 2 Hz from the same component, for 2.5 seconds
+        let traffic = futures::stream::iter(0..5u32).then(move |index| {
+            let builder = builder.clone();
+            let control = control.clone();
+            async move {
+                tokio::time::sleep_until(start + Duration::from_millis(250 + 500 * index as u64))
+                    .await;
+                if index == 2 {
+                    control.modify(|heartbeat| heartbeat.base_mode = 1);
+                }
+                builder.build(30, &[1; 28]).unwrap()
+            }
+        });
+
+        emitter.run(traffic).await.unwrap();
+
+        let sent = sent.lock().unwrap();
+        let heartbeats = sent
+            .iter()
+            .filter_map(|(elapsed, packet)| Some((*elapsed, Heartbeat::from_packet(packet)?)))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            heartbeats
+                .iter()
+                .map(|(elapsed, _)| *elapsed)
+                .collect::<Vec<_>>(),
+            vec![0, 1000, 2000]
+        );
+        assert_eq!(heartbeats[1].1, QUADROTOR);
+        assert_eq!(heartbeats[2].1.base_mode, 1);
+
+        // A single sequence for everything
+        let sequences = sent
+            .iter()
+            .map(|(_, packet)| *packet.sequence())
+            .collect::<Vec<_>>();
+        assert_eq!(sequences, (0..8).collect::<Vec<_>>());
+    }
+}
@@ -0,0 +1,211 @@
+//! JSON rendering of packets, decoding their payload with a [`Dialect`].
+//!
+//! A packet is rendered as:
+//!
+//! ```json
+//! {
+//!     "header": {
+//!         "version": 2,
+//!         "sequence": 0,
+//!         "system_id": 1,
+//!         "component_id": 1,
+//!         "message_id": 30,
+//!         "signed": false
+//!     },
+//!     "message": { "type": "ATTITUDE", "roll": 0.0, ... }
+//! }
+//! ```
+
+use mavlink::{MAVLinkV1MessageRaw, MAVLinkV2MessageRaw, MavHeader, MavlinkVersion, Message};
+use serde::{de::DeserializeOwned, Serialize};
+use serde_json::{json, Value};
+
+use crate::{dialect::Dialect, error::JsonError, Packet};
+
+impl Packet {
+    /// Renders the header and the decoded message as JSON
+    pub fn to_json<D>(&self) -> Result<Value, JsonError>
+    where
+        D: Dialect,
+        D::Message: Serialize,
+    {
+        let (version, signed) = match self {
+            Packet::V1(_) => (MavlinkVersion::V1, false),
+            Packet::V2(v2_packet) => (MavlinkVersion::V2, v2_packet.has_signature()),
+        };
+
+        let message = D::Message::parse(version, self.message_id(), self.payload())
+            .map_err(JsonError::Parse)?;
+
+        Ok(json!({
+            "header": {
+                "version": match version {
+                    MavlinkVersion::V1 => 1,
+                    MavlinkVersion::V2 => 2,
+                },
+                "sequence": self.sequence(),
+                "system_id": self.system_id(),
+                "component_id": self.component_id(),
+                "message_id": self.message_id(),
+                "signed": signed,
+            },
+            "message": serde_json::to_value(message)?,
+        }))
+    }
+
+    /// Frames the message described by `json`, in the format produced by [`Packet::to_json`].
+    ///
+    /// Only `header.system_id` and `header.component_id` are required: the version defaults to
+    /// 2 and the sequence to 0. When given, `header.message_id` must match the message. Packets
+    /// can't be signed this way.
+    pub fn from_json<D>(json: &Value) -> Result<Self, JsonError>
+    where
+        D: Dialect,
+        D::Message: DeserializeOwned,
+    {
+        let header = json
+            .get("header")
+            .ok_or(JsonError::InvalidHeader { field: "header" })?;
+
+        let header_field = |field: &'static str, default: Option<u8>| {
+            match header.get(field) {
+                Some(value) => value.as_u64().and_then(|value| u8::try_from(value).ok()),
+                None => default,
+            }
+            .ok_or(JsonError::InvalidHeader { field })
+        };
+
+        let version = header_field("version", Some(2))?;
+        let message_id = header
+            .get("message_id")
+            .map(|value| {
+                value
+                    .as_u64()
+                    .and_then(|value| u32::try_from(value).ok())
+                    .ok_or(JsonError::InvalidHeader {
+                        field: "message_id",
+                    })
+            })
+            .transpose()?;
+        let header = MavHeader {
+            system_id: header_field("system_id", None)?,
+            component_id: header_field("component_id", None)?,
+            sequence: header_field("sequence", Some(0))?,
+        };
+
+        let message = json
+            .get("message")
+            .ok_or(JsonError::InvalidHeader { field: "message" })?;
+        let message: D::Message = serde_json::from_value(message.clone())?;
+
+        if message_id.is_some_and(|message_id| message_id != message.message_id()) {
+            return Err(JsonError::InvalidHeader {
+                field: "message_id",
+            });
+        }
+
+        match version {
+            1 => {
+                if message.message_id() > u8::MAX as u32 {
+                    return Err(JsonError::InvalidHeader { field: "version" });
+                }
+
+                let mut raw_v1_message = MAVLinkV1MessageRaw::new();
+                raw_v1_message.serialize_message(header, &message);
+                Ok(Packet::from(raw_v1_message))
+            }
+            2 => {
+                let mut raw_v2_message = MAVLinkV2MessageRaw::new();
+                raw_v2_message.serialize_message(header, &message);
+                Ok(Packet::from(raw_v2_message))
+            }
+            _ => Err(JsonError::InvalidHeader { field: "version" }),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{
+        dialect::ArduPilotMega,
+        test_util::{create_v1_packet, create_v2_packet, header},
+    };
+
+    const HEADER: MavHeader = header(1, 2, 3);
+
+    #[test]
+    fn test_to_json() {
+        let packet = create_v2_packet(HEADER, 30);
+
+        let json = packet.to_json::<ArduPilotMega>().unwrap();
+
+        assert_eq!(json["header"]["version"], 2);
+        assert_eq!(json["header"]["sequence"], 3);
+        assert_eq!(json["header"]["system_id"], 1);
+        assert_eq!(json["header"]["component_id"], 2);
+        assert_eq!(json["header"]["message_id"], 30);
+        assert_eq!(json["header"]["signed"], false);
+        assert_eq!(json["message"]["type"], "ATTITUDE");
+    }
+
+    #[test]
+    fn test_json_roundtrip() {
+        for message_id in [0, 30, 76] {
+            for packet in [
+                create_v1_packet(HEADER, message_id),
+                create_v2_packet(HEADER, message_id),
+            ] {
+                let json = packet.to_json::<ArduPilotMega>().unwrap();
+                let decoded = Packet::from_json::<ArduPilotMega>(&json).unwrap();
+
+                assert_eq!(decoded, packet);
+            }
+        }
+    }
+
+    #[test]
+    fn test_from_json_defaults() {
+        let packet = create_v2_packet(HEADER, 0);
+        let mut json = packet.to_json::<ArduPilotMega>().unwrap();
+        json["header"] = json!({ "system_id": 1, "component_id": 2 });
+
+        let decoded = Packet::from_json::<ArduPilotMega>(&json).unwrap();
+
+        assert!(matches!(decoded, Packet::V2(_)));
+        assert_eq!(*decoded.sequence(), 0);
+        assert_eq!(decoded.payload(), packet.payload());
+    }
+
+    #[test]
+    fn test_from_json_invalid() {
+        let json = json!({
+            "header": { "component_id": 1 },
+            "message": { "type": "HEARTBEAT" },
+        });
+        assert!(matches!(
+            Packet::from_json::<ArduPilotMega>(&json),
+            Err(JsonError::InvalidHeader { field: "system_id" })
+        ));
+
+        let json = json!({
+            "header": { "system_id": 1, "component_id": 1 },
+            "message": { "type": "NOT_A_MESSAGE" },
+        });
+        assert!(matches!(
+            Packet::from_json::<ArduPilotMega>(&json),
+            Err(JsonError::Json(_))
+        ));
+
+        let mut json = create_v2_packet(HEADER, 30)
+            .to_json::<ArduPilotMega>()
+            .unwrap();
+        json["header"]["message_id"] = json!(0);
+        assert!(matches!(
+            Packet::from_json::<ArduPilotMega>(&json),
+            Err(JsonError::InvalidHeader {
+                field: "message_id"
+            })
+        ));
+    }
+}
@@ -1,13 +1,56 @@
+#[cfg(feature = "async")]
+pub mod builder;
+#[cfg(feature = "async")]
+pub mod client;
 pub mod codec;
+#[cfg(feature = "async")]
+pub mod command;
+#[cfg(feature = "async")]
+pub mod dedup;
+pub mod dialect;
+pub mod display;
 pub mod error;
+pub mod event;
+pub mod field;
+pub mod filter;
+#[cfg(feature = "async")]
+pub mod ftp;
+#[cfg(feature = "async")]
+pub mod heartbeat;
+#[cfg(feature = "json")]
+pub mod json;
+#[cfg(feature = "async")]
+pub mod link;
+#[cfg(feature = "async")]
+pub mod mission;
+#[cfg(feature = "async")]
+pub mod param;
+pub mod pcap;
+#[cfg(feature = "async")]
+pub mod rate_limit;
+#[cfg(feature = "async")]
+pub mod replay;
+#[cfg(feature = "async")]
+pub mod router;
 pub mod rust_mavlink_compatibility;
+#[cfg(feature = "serde")]
+pub mod serialization;
+pub mod target;
+#[cfg(test)]
+mod test_util;
+pub mod tlog;
 pub mod v1;
 pub mod v2;
 
+use std::borrow::Cow;
+
 use bytes::Bytes;
 
+use codec::get_extra_crc;
+use dialect::Dialect;
+use error::ValidationError;
 use v1::{V1Packet, V1_STX};
-use v2::{V2Packet, V2_STX};
+use v2::{V2Packet, MAVLINK_SUPPORTED_IFLAGS, V2_STX};
 
 #[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
 #[repr(u8)]
@@ -49,6 +92,16 @@ impl Packet {
         }
     }
 
+    /// The payload zero-extended to the full length of its message.
+    ///
+    /// MAVLink V1 payloads are never truncated, so only V2 payloads might be extended.
+    pub fn payload_extended<D: Dialect>(&self, dialect: &D) -> Cow<'_, [u8]> {
+        match self {
+            Packet::V1(v1_packet) => Cow::Borrowed(v1_packet.payload()),
+            Packet::V2(v2_packet) => v2_packet.payload_extended(dialect),
+        }
+    }
+
     #[inline(always)]
     pub fn checksum(&self) -> u16 {
         match self {
@@ -122,6 +175,97 @@ impl Packet {
     }
 }
 
+impl TryFrom<Bytes> for Packet {
+    type Error = ValidationError;
+
+    /// Validates the framing and the CRC of a single, complete packet
+    fn try_from(bytes: Bytes) -> Result<Self, Self::Error> {
+        let Some(&stx) = bytes.first() else {
+            return Err(ValidationError::InvalidSize {
+                expected: V1Packet::STX_SIZE + V1Packet::HEADER_SIZE + V1Packet::CHECKSUM_SIZE,
+                actual: 0,
+            });
+        };
+
+        let packet = match stx {
+            V1_STX => {
+                let min_size = V1Packet::STX_SIZE + V1Packet::HEADER_SIZE + V1Packet::CHECKSUM_SIZE;
+                if bytes.len() < min_size {
+                    return Err(ValidationError::InvalidSize {
+                        expected: min_size,
+                        actual: bytes.len(),
+                    });
+                }
+
+                Packet::V1(V1Packet::new(bytes))
+            }
+            V2_STX => {
+                let min_size = V2Packet::STX_SIZE + V2Packet::HEADER_SIZE + V2Packet::CHECKSUM_SIZE;
+                if bytes.len() < min_size {
+                    return Err(ValidationError::InvalidSize {
+                        expected: min_size,
+                        actual: bytes.len(),
+                    });
+                }
+
+                let incompat_flags = *v2::incompat_flags(&bytes);
+                if incompat_flags & !MAVLINK_SUPPORTED_IFLAGS != 0 {
+                    return Err(ValidationError::InvalidFlags { incompat_flags });
+                }
+
+                Packet::V2(V2Packet::new(bytes))
+            }
+            stx => return Err(ValidationError::InvalidStx { stx }),
+        };
+
+        let packet_size = packet.packet_size();
+        if packet.bytes().len() != packet_size {
+            return Err(ValidationError::InvalidSize {
+                expected: packet_size,
+                actual: packet.bytes().len(),
+            });
+        }
+
+        let msgid = packet.message_id();
+        let Some(extra_crc) = get_extra_crc(msgid) else {
+            return Err(ValidationError::UnknownMessageID { msgid });
+        };
+
+        let expected_crc = packet.checksum();
+        let calculated_crc = mavlink::calculate_crc(packet.checksum_data(), extra_crc);
+        if calculated_crc != expected_crc {
+            return Err(ValidationError::InvalidCRC {
+                expected_crc,
+                calculated_crc,
+            });
+        }
+
+        Ok(packet)
+    }
+}
+
+impl TryFrom<Bytes> for V1Packet {
+    type Error = ValidationError;
+
+    fn try_from(bytes: Bytes) -> Result<Self, Self::Error> {
+        match Packet::try_from(bytes)? {
+            Packet::V1(v1_packet) => Ok(v1_packet),
+            Packet::V2(_) => Err(ValidationError::InvalidVersion { version: 2 }),
+        }
+    }
+}
+
+impl TryFrom<Bytes> for V2Packet {
+    type Error = ValidationError;
+
+    fn try_from(bytes: Bytes) -> Result<Self, Self::Error> {
+        match Packet::try_from(bytes)? {
+            Packet::V1(_) => Err(ValidationError::InvalidVersion { version: 1 }),
+            Packet::V2(v2_packet) => Ok(v2_packet),
+        }
+    }
+}
+
 /// Creates a `MavlinkCodec` with compile-time configuration.
 ///
 /// # Parameters
@@ -132,6 +276,8 @@ impl Packet {
 /// - `drop_invalid_compid`: Whether to drop messages with zeroed Component ID
 /// - `skip_crc_validation`: Whether to skip the CRC validation
 /// - `drop_incompatible`: Whether to drop messages with unknown Incompatibility Flags
+/// - `validate_payload_length` (optional, defaults to `false`): Whether to drop messages with a
+///   payload length out of the dialect bounds
 ///
 /// # Example
 ///
@@ -149,6 +295,19 @@ impl Packet {
 ///
 /// // Which is equivallent to:
 /// let codec = MavlinkCodec::<true, true, false, false, false, false>::default();
+///
+/// let codec = mavlink_codec! {
+///     accept_v1: true,
+///     accept_v2: true,
+///     drop_invalid_sysid: false,
+///     drop_invalid_compid: false,
+///     skip_crc_validation: false,
+///     drop_incompatible: false,
+///     validate_payload_length: true,
+/// };
+///
+/// // Which is equivallent to:
+/// let codec = MavlinkCodec::<true, true, false, false, false, false, true>::default();
 /// ```
 #[macro_export]
 macro_rules! mavlink_codec {
@@ -165,6 +324,8 @@ macro_rules! mavlink_codec {
         skip_crc_validation: $skip_crc_validation:expr,
         /// Whether to drop messages with unknown Incompatibility Flags
         drop_incompatible: $drop_incompatible:expr,
+        /// Whether to drop messages with a payload length out of the dialect bounds
+        $(validate_payload_length: $validate_payload_length:expr,)?
     ) => {
         $crate::codec::MavlinkCodec::<
             { $accept_v1 },
@@ -173,8 +334,7 @@ macro_rules! mavlink_codec {
             { $drop_invalid_compid },
             { $skip_crc_validation },
             { $drop_incompatible },
-        > {
-            state: $crate::codec::CodecState::default(),
-        }
+            { false $(|| $validate_payload_length)? },
+        >::default()
     };
 }
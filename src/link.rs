@@ -0,0 +1,455 @@
+//! Aggregation of redundant links, like dual radios plus LTE, into a single one.
+//!
+//! The [`LinkAggregator`] merges the packets received from every link without duplicates, and
+//! measures the quality of each link: its loss, from the sequence gaps of each source, and its
+//! latency relative to the fastest link, from the arrival times of the copies of a packet.
+
+use std::{
+    collections::HashMap,
+    io,
+    pin::Pin,
+    sync::{Arc, Mutex},
+    task::{ready, Context, Poll},
+    time::Duration,
+};
+
+use futures::{stream::BoxStream, Sink, SinkExt, Stream, StreamExt};
+use log::trace;
+use tokio::time::Instant;
+
+use crate::{dedup::Deduplicator, Packet};
+
+type BoxSink = Pin<Box<dyn Sink<Packet, Error = io::Error> + Send>>;
+
+/// Which links the outgoing packets are sent on
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum TransmitPolicy {
+    /// The link with the lowest loss, then the lowest latency
+    #[default]
+    BestLink,
+    AllLinks,
+}
+
+/// Quality of a single link
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct LinkStatistics {
+    pub received: u64,
+    /// Packets missing from the sequence of their source
+    pub lost: u64,
+    /// Packets received on this link before any other
+    pub first_arrivals: u64,
+    /// Moving average of the delay behind the fastest link
+    pub relative_latency: Duration,
+    /// Last sequence by (System ID, Component ID)
+    last_sequences: HashMap<(u8, u8), u8>,
+}
+
+impl LinkStatistics {
+    /// Weight of the new samples in [`LinkStatistics::relative_latency`]
+    const LATENCY_SMOOTHING: f64 = 0.1;
+    /// How far behind the last sequence a packet is considered late rather than from a source
+    /// that restarted
+    const REORDER_WINDOW: u8 = 16;
+
+    pub fn loss_ratio(&self) -> f64 {
+        let total = self.received + self.lost;
+        if total == 0 {
+            return 0.0;
+        }
+
+        self.lost as f64 / total as f64
+    }
+
+    fn update_sequence(&mut self, packet: &Packet) {
+        let source = (*packet.system_id(), *packet.component_id());
+        let sequence = *packet.sequence();
+
+        self.received += 1;
+
+        let Some(last_sequence) = self.last_sequences.insert(source, sequence) else {
+            return;
+        };
+
+        // Anything between the expected and the received sequence was lost, modulo 256
+        let gap = sequence.wrapping_sub(last_sequence.wrapping_add(1));
+        if gap < 128 {
+            self.lost += gap as u64;
+            return;
+        }
+
+        // A backward jump
+        let behind = last_sequence.wrapping_sub(sequence);
+        if behind == 0 {
+            trace!("Duplicate sequence {sequence} from {source:?}");
+        } else if behind <= Self::REORDER_WINDOW {
+            // Late, so it was counted as lost, and the last sequence stays ahead
+            self.lost = self.lost.saturating_sub(1);
+            self.last_sequences.insert(source, last_sequence);
+        } else {
+            trace!("Sequence of {source:?} reset from {last_sequence} to {sequence}");
+        }
+    }
+
+    fn update_latency(&mut self, delay: Duration) {
+        self.relative_latency = self.relative_latency.mul_f64(1.0 - Self::LATENCY_SMOOTHING)
+            + delay.mul_f64(Self::LATENCY_SMOOTHING);
+    }
+}
+
+/// A handle to the statistics of a [`LinkAggregator`], usable while it runs
+#[derive(Debug, Clone, Default)]
+pub struct LinkStats {
+    links: Arc<Mutex<Vec<LinkStatistics>>>,
+}
+
+impl LinkStats {
+    pub fn links(&self) -> Vec<LinkStatistics> {
+        self.links.lock().unwrap().clone()
+    }
+
+    /// The link with the lowest loss, then the lowest latency
+    pub fn best_link(&self) -> Option<usize> {
+        self.links
+            .lock()
+            .unwrap()
+            .iter()
+            .enumerate()
+            .min_by(|(_, a), (_, b)| {
+                a.loss_ratio()
+                    .total_cmp(&b.loss_ratio())
+                    .then(a.relative_latency.cmp(&b.relative_latency))
+            })
+            .map(|(id, _)| id)
+    }
+}
+
+struct Link {
+    stream: Option<BoxStream<'static, Packet>>,
+    sink: BoxSink,
+}
+
+/// Merges redundant links, see the [module documentation](self).
+///
+/// As a [`Stream`], it yields the first copy of each packet received from any link. As a
+/// [`Sink`], it sends the packets according to its [`TransmitPolicy`].
+pub struct LinkAggregator {
+    links: Vec<Link>,
+    deduplicator: Deduplicator,
+    policy: TransmitPolicy,
+    stats: LinkStats,
+    /// Link polled first, rotated for fairness
+    next_link: usize,
+    /// Link chosen when the sink was polled ready
+    transmit_link: Option<usize>,
+}
+
+impl std::fmt::Debug for LinkAggregator {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("LinkAggregator")
+            .field("links", &self.links.len())
+            .field("policy", &self.policy)
+            .field("stats", &self.stats)
+            .finish_non_exhaustive()
+    }
+}
+
+impl LinkAggregator {
+    /// `dedup_window` should cover the latency difference between the links, see
+    /// [`Deduplicator`]
+    pub fn new(dedup_window: Duration) -> Self {
+        Self {
+            links: Vec::new(),
+            deduplicator: Deduplicator::new(dedup_window),
+            policy: TransmitPolicy::default(),
+            stats: LinkStats::default(),
+            next_link: 0,
+            transmit_link: None,
+        }
+    }
+
+    pub fn with_policy(mut self, policy: TransmitPolicy) -> Self {
+        self.policy = policy;
+        self
+    }
+
+    /// Adds a link, returning its ID in the statistics
+    pub fn add_link<St, Si>(&mut self, stream: St, sink: Si) -> usize
+    where
+        St: Stream<Item = Packet> + Send + 'static,
+        Si: Sink<Packet> + Send + 'static,
+        Si::Error: Into<Box<dyn std::error::Error + Send + Sync>>,
+    {
+        self.links.push(Link {
+            stream: Some(stream.boxed()),
+            sink: Box::pin(sink.sink_map_err(io::Error::other)),
+        });
+        self.stats
+            .links
+            .lock()
+            .unwrap()
+            .push(LinkStatistics::default());
+
+        self.links.len() - 1
+    }
+
+    pub fn stats(&self) -> LinkStats {
+        self.stats.clone()
+    }
+
+    /// Returns the packet if no other link received it first
+    fn receive(&mut self, link: usize, packet: Packet) -> Option<Packet> {
+        let mut links = self.stats.links.lock().unwrap();
+        let statistics = &mut links[link];
+        statistics.update_sequence(&packet);
+
+        match self.deduplicator.seen_at(&packet) {
+            Some(first_seen) => {
+                statistics.update_latency(Instant::now().duration_since(first_seen));
+                None
+            }
+            None => {
+                statistics.first_arrivals += 1;
+                statistics.update_latency(Duration::ZERO);
+                Some(packet)
+            }
+        }
+    }
+}
+
+impl Stream for LinkAggregator {
+    type Item = Packet;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = &mut *self;
+        let links_count = this.links.len();
+
+        loop {
+            let mut received = false;
+
+            for offset in 0..links_count {
+                let link = (this.next_link + offset) % links_count;
+                let Some(stream) = &mut this.links[link].stream else {
+                    continue;
+                };
+
+                match stream.poll_next_unpin(cx) {
+                    Poll::Ready(Some(packet)) => {
+                        received = true;
+                        this.next_link = (link + 1) % links_count;
+                        if let Some(packet) = this.receive(link, packet) {
+                            return Poll::Ready(Some(packet));
+                        }
+                        break;
+                    }
+                    Poll::Ready(None) => this.links[link].stream = None,
+                    Poll::Pending => (),
+                }
+            }
+
+            if this.links.iter().all(|link| link.stream.is_none()) {
+                return Poll::Ready(None);
+            }
+
+            if !received {
+                return Poll::Pending;
+            }
+        }
+    }
+}
+
+impl Sink<Packet> for LinkAggregator {
+    type Error = io::Error;
+
+    fn poll_ready(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        let this = &mut *self;
+
+        match this.policy {
+            TransmitPolicy::BestLink => {
+                let link = match this.transmit_link {
+                    Some(link) => link,
+                    None => {
+                        let link = this.stats.best_link().ok_or_else(|| {
+                            io::Error::new(io::ErrorKind::NotConnected, "no links")
+                        })?;
+                        this.transmit_link = Some(link);
+                        link
+                    }
+                };
+
+                this.links[link].sink.poll_ready_unpin(cx)
+            }
+            TransmitPolicy::AllLinks => {
+                for link in &mut this.links {
+                    ready!(link.sink.poll_ready_unpin(cx))?;
+                }
+
+                Poll::Ready(Ok(()))
+            }
+        }
+    }
+
+    fn start_send(mut self: Pin<&mut Self>, packet: Packet) -> Result<(), Self::Error> {
+        let this = &mut *self;
+
+        match this.policy {
+            TransmitPolicy::BestLink => {
+                let link = this.transmit_link.take().ok_or_else(|| {
+                    io::Error::other("start_send called without poll_ready being ready")
+                })?;
+
+                this.links[link].sink.start_send_unpin(packet)
+            }
+            TransmitPolicy::AllLinks => this
+                .links
+                .iter_mut()
+                .try_for_each(|link| link.sink.start_send_unpin(packet.clone())),
+        }
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        for link in &mut self.links {
+            ready!(link.sink.poll_flush_unpin(cx))?;
+        }
+
+        Poll::Ready(Ok(()))
+    }
+
+    fn poll_close(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        for link in &mut self.links {
+            ready!(link.sink.poll_close_unpin(cx))?;
+        }
+
+        Poll::Ready(Ok(()))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test_util::{create_v2_packet, header};
+    use rand::{prelude::StdRng, Rng, SeedableRng};
+
+    const PACKETS: usize = 500;
+    const PERIOD: Duration = Duration::from_millis(20);
+
+    #[test]
+    fn test_reordered_sequences() {
+        let mut statistics = LinkStatistics::default();
+        for sequence in [0, 1, 3, 2, 4, 4, 5, 7] {
+            statistics.update_sequence(&create_v2_packet(header(1, 1, sequence), 30));
+        }
+        // Only 6 is missing: 2 came late and 4 twice
+        assert_eq!(statistics.received, 8);
+        assert_eq!(statistics.lost, 1);
+
+        // Wrapping around
+        for sequence in (8..=255).chain([1, 0, 2]) {
+            statistics.update_sequence(&create_v2_packet(header(1, 1, sequence), 30));
+        }
+        assert_eq!(statistics.lost, 1);
+
+        // The source restarted
+        for sequence in (3..=100).chain([0, 1, 2]) {
+            statistics.update_sequence(&create_v2_packet(header(1, 1, sequence), 30));
+        }
+        assert_eq!(statistics.lost, 1);
+    }
+
+    /// A link receiving the same packets every [`PERIOD`], with some delay and random losses
+    fn lossy_link(
+        seed: u64,
+        loss_ratio: f64,
+        delay: Duration,
+    ) -> impl Stream<Item = Packet> + Send + 'static {
+        let start = Instant::now();
+        let mut rng = StdRng::seed_from_u64(seed);
+        let received = (0..PACKETS)
+            .filter(|_| !rng.gen_bool(loss_ratio))
+            .collect::<Vec<_>>();
+
+        futures::stream::iter(received).then(move |index| async move {
+            tokio::time::sleep_until(start + PERIOD * index as u32 + delay).await;
+            create_v2_packet(header(1, 1, index as u8), 30)
+        })
+    }
+
+    type Sent = Arc<Mutex<Vec<Packet>>>;
+
+    fn recording_sink() -> (impl Sink<Packet, Error = io::Error> + Send + 'static, Sent) {
+        let sent = Sent::default();
+
+        let sink = futures::sink::unfold(sent.clone(), |sent, packet| async move {
+            sent.lock().unwrap().push(packet);
+            Ok::<_, io::Error>(sent)
+        });
+
+        (sink, sent)
+    }
+
+    /// A lossless but slow radio, and a fast but lossy LTE link
+    fn create_aggregator(policy: TransmitPolicy) -> (LinkAggregator, Sent, Sent) {
+        let mut aggregator = LinkAggregator::new(Duration::from_millis(500)).with_policy(policy);
+
+        let (radio_sink, radio_sent) = recording_sink();
+        aggregator.add_link(lossy_link(1, 0.0, Duration::from_millis(50)), radio_sink);
+        let (lte_sink, lte_sent) = recording_sink();
+        aggregator.add_link(lossy_link(2, 0.3, Duration::from_millis(10)), lte_sink);
+
+        (aggregator, radio_sent, lte_sent)
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_merge_and_statistics() {
+        let (mut aggregator, _, _) = create_aggregator(TransmitPolicy::BestLink);
+        let stats = aggregator.stats();
+
+        let mut sequences = Vec::new();
+        while let Some(packet) = aggregator.next().await {
+            sequences.push(*packet.sequence());
+        }
+
+        // Nothing lost, nothing repeated, although the copies from the radio come late
+        let mut expected = (0..PACKETS).map(|index| index as u8).collect::<Vec<_>>();
+        expected.sort();
+        sequences.sort();
+        assert_eq!(sequences, expected);
+
+        let [radio, lte] = stats.links().try_into().unwrap();
+        assert_eq!(radio.received, PACKETS as u64);
+        assert_eq!(radio.lost, 0);
+        assert!((0.25..0.35).contains(&lte.loss_ratio()), "{lte:?}");
+        assert!(lte.first_arrivals > 0);
+        assert_eq!(lte.first_arrivals + radio.first_arrivals, PACKETS as u64);
+
+        // The radio lags 40 ms behind the LTE link whenever both received a packet
+        assert!(
+            (Duration::from_millis(10)..=Duration::from_millis(40))
+                .contains(&radio.relative_latency),
+            "{radio:?}"
+        );
+        assert_eq!(lte.relative_latency, Duration::ZERO);
+
+        assert_eq!(stats.best_link(), Some(0));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_transmit_policies() {
+        let (mut aggregator, radio_sent, lte_sent) = create_aggregator(TransmitPolicy::BestLink);
+        while aggregator.next().await.is_some() {}
+
+        aggregator
+            .send(create_v2_packet(header(1, 1, 0), 30))
+            .await
+            .unwrap();
+        assert_eq!(radio_sent.lock().unwrap().len(), 1);
+        assert!(lte_sent.lock().unwrap().is_empty());
+
+        let (mut aggregator, radio_sent, lte_sent) = create_aggregator(TransmitPolicy::AllLinks);
+        aggregator
+            .send(create_v2_packet(header(1, 1, 0), 30))
+            .await
+            .unwrap();
+        assert_eq!(radio_sent.lock().unwrap().len(), 1);
+        assert_eq!(lte_sent.lock().unwrap().len(), 1);
+    }
+}
@@ -0,0 +1,730 @@
+//! The mission protocol: transfers of missions, geofences and rally points, with MISSION_COUNT,
+//! MISSION_REQUEST_INT, MISSION_ITEM_INT and MISSION_ACK.
+
+use std::time::Duration;
+
+use log::debug;
+use thiserror::Error;
+use tokio::time::Instant;
+
+use crate::{
+    client::{ClientError, Connection},
+    dialect::{ArduPilotMega, Dialect},
+    field::FieldValue,
+    Packet,
+};
+
+const MISSION_REQUEST: u32 = 40;
+const MISSION_REQUEST_LIST: u32 = 43;
+const MISSION_COUNT: u32 = 44;
+const MISSION_CLEAR_ALL: u32 = 45;
+const MISSION_ACK: u32 = 47;
+const MISSION_REQUEST_INT: u32 = 51;
+const MISSION_ITEM_INT: u32 = 73;
+
+/// Why a mission transfer failed
+#[derive(Error, Debug)]
+#[non_exhaustive]
+pub enum MissionError {
+    #[error("mission transfer rejected: {0:?}")]
+    Rejected(MissionResult),
+
+    #[error("a plan holds at most {} items, got {count}", u16::MAX)]
+    TooManyItems { count: usize },
+
+    #[error(transparent)]
+    Client(#[from] ClientError),
+}
+
+/// MAV_MISSION_TYPE, the kind of plan transferred
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub enum MissionType {
+    #[default]
+    Mission,
+    Fence,
+    Rally,
+}
+
+impl TryFrom<u8> for MissionType {
+    type Error = u8;
+
+    fn try_from(value: u8) -> Result<Self, Self::Error> {
+        match value {
+            0 => Ok(MissionType::Mission),
+            1 => Ok(MissionType::Fence),
+            2 => Ok(MissionType::Rally),
+            value => Err(value),
+        }
+    }
+}
+
+impl MissionType {
+    pub fn as_u8(&self) -> u8 {
+        match self {
+            MissionType::Mission => 0,
+            MissionType::Fence => 1,
+            MissionType::Rally => 2,
+        }
+    }
+}
+
+/// MAV_MISSION_RESULT, the outcome of a transfer
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MissionResult {
+    Accepted,
+    Error,
+    UnsupportedFrame,
+    Unsupported,
+    NoSpace,
+    Invalid,
+    /// An invalid param1 to param7, by number
+    InvalidParam(u8),
+    InvalidSequence,
+    Denied,
+    OperationCancelled,
+    Unknown(u8),
+}
+
+impl From<u8> for MissionResult {
+    fn from(value: u8) -> Self {
+        match value {
+            0 => MissionResult::Accepted,
+            1 => MissionResult::Error,
+            2 => MissionResult::UnsupportedFrame,
+            3 => MissionResult::Unsupported,
+            4 => MissionResult::NoSpace,
+            5 => MissionResult::Invalid,
+            6..=12 => MissionResult::InvalidParam(value - 5),
+            13 => MissionResult::InvalidSequence,
+            14 => MissionResult::Denied,
+            15 => MissionResult::OperationCancelled,
+            value => MissionResult::Unknown(value),
+        }
+    }
+}
+
+impl MissionResult {
+    pub fn as_u8(&self) -> u8 {
+        match *self {
+            MissionResult::Accepted => 0,
+            MissionResult::Error => 1,
+            MissionResult::UnsupportedFrame => 2,
+            MissionResult::Unsupported => 3,
+            MissionResult::NoSpace => 4,
+            MissionResult::Invalid => 5,
+            MissionResult::InvalidParam(param) => param + 5,
+            MissionResult::InvalidSequence => 13,
+            MissionResult::Denied => 14,
+            MissionResult::OperationCancelled => 15,
+            MissionResult::Unknown(value) => value,
+        }
+    }
+}
+
+/// An item of a plan, as transferred with MISSION_ITEM_INT
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct MissionItem {
+    /// MAV_CMD
+    pub command: u16,
+    /// MAV_FRAME
+    pub frame: u8,
+    pub current: bool,
+    pub autocontinue: bool,
+    pub params: [f32; 4],
+    /// Latitude in degE7, or local x in meters * 1e4
+    pub x: i32,
+    /// Longitude in degE7, or local y in meters * 1e4
+    pub y: i32,
+    pub z: f32,
+}
+
+impl MissionItem {
+    /// Reads a MISSION_ITEM_INT, returning the item with its sequence
+    pub fn from_packet<D: Dialect>(packet: &Packet, dialect: &D) -> Option<(u16, Self)> {
+        if packet.message_id() != MISSION_ITEM_INT {
+            return None;
+        }
+
+        let integer = |name| {
+            packet
+                .field(name, dialect)
+                .and_then(|value| value.as_i64())
+                .unwrap_or_default()
+        };
+        let float = |name| match packet.field(name, dialect) {
+            Some(FieldValue::F32(value)) => value,
+            _ => 0.0,
+        };
+
+        Some((
+            integer("seq") as u16,
+            Self {
+                command: integer("command") as u16,
+                frame: integer("frame") as u8,
+                current: integer("current") != 0,
+                autocontinue: integer("autocontinue") != 0,
+                params: [
+                    float("param1"),
+                    float("param2"),
+                    float("param3"),
+                    float("param4"),
+                ],
+                x: integer("x") as i32,
+                y: integer("y") as i32,
+                z: float("z"),
+            },
+        ))
+    }
+
+    /// The fields of the MISSION_ITEM_INT of this item
+    pub fn fields(&self, seq: u16, mission_type: MissionType) -> Vec<(&'static str, FieldValue)> {
+        let [param1, param2, param3, param4] = self.params;
+
+        vec![
+            ("param1", FieldValue::F32(param1)),
+            ("param2", FieldValue::F32(param2)),
+            ("param3", FieldValue::F32(param3)),
+            ("param4", FieldValue::F32(param4)),
+            ("x", FieldValue::I32(self.x)),
+            ("y", FieldValue::I32(self.y)),
+            ("z", FieldValue::F32(self.z)),
+            ("seq", FieldValue::U16(seq)),
+            ("command", FieldValue::U16(self.command)),
+            ("frame", FieldValue::U8(self.frame)),
+            ("current", FieldValue::U8(self.current as u8)),
+            ("autocontinue", FieldValue::U8(self.autocontinue as u8)),
+            ("mission_type", FieldValue::U8(mission_type.as_u8())),
+        ]
+    }
+}
+
+/// Uploads, downloads and clears the missions, geofences and rally points of a component.
+///
+/// Each step of a transfer is sent again when its answer times out, up to the retries.
+#[derive(Debug)]
+pub struct MissionClient<D: Dialect = ArduPilotMega> {
+    connection: Connection<D>,
+    timeout: Duration,
+    retries: u32,
+}
+
+impl<D: Dialect> MissionClient<D> {
+    pub const DEFAULT_TIMEOUT: Duration = Duration::from_millis(1500);
+    pub const DEFAULT_RETRIES: u32 = 4;
+
+    pub fn new(connection: Connection<D>) -> Self {
+        Self {
+            connection,
+            timeout: Self::DEFAULT_TIMEOUT,
+            retries: Self::DEFAULT_RETRIES,
+        }
+    }
+
+    /// How long to wait for the next step of a transfer before sending again
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// How many times to send again each step of a transfer
+    pub fn with_retries(mut self, retries: u32) -> Self {
+        self.retries = retries;
+        self
+    }
+
+    pub fn connection(&self) -> &Connection<D> {
+        &self.connection
+    }
+
+    pub fn into_connection(self) -> Connection<D> {
+        self.connection
+    }
+
+    /// Replaces the plan of a type with `items`.
+    ///
+    /// The component drives the transfer, requesting the items one by one, and acknowledges
+    /// the whole plan. More items than MISSION_COUNT can announce are a
+    /// [`MissionError::TooManyItems`], before anything is sent.
+    pub async fn upload(
+        &mut self,
+        mission_type: MissionType,
+        items: &[MissionItem],
+    ) -> Result<(), MissionError> {
+        let Ok(count) = u16::try_from(items.len()) else {
+            return Err(MissionError::TooManyItems { count: items.len() });
+        };
+
+        let mut message = (
+            MISSION_COUNT,
+            vec![
+                ("count", FieldValue::U16(count)),
+                ("mission_type", FieldValue::U8(mission_type.as_u8())),
+            ],
+        );
+
+        loop {
+            let (message_id, values) = message;
+
+            // The next request, or the final acknowledgement
+            let request = self
+                .request(
+                    message_id,
+                    &values,
+                    mission_type,
+                    |packet, dialect| match packet.message_id() {
+                        MISSION_REQUEST_INT | MISSION_REQUEST => {
+                            let seq = packet.field("seq", dialect)?.as_i64()? as u16;
+                            Some(Some(seq))
+                        }
+                        MISSION_ACK => Some(None),
+                        _ => None,
+                    },
+                )
+                .await?;
+
+            let Some(seq) = request else {
+                debug!("Uploaded {} {mission_type:?} items", items.len());
+                return Ok(());
+            };
+            let Some(item) = items.get(seq as usize) else {
+                return Err(MissionError::Rejected(MissionResult::InvalidSequence));
+            };
+
+            message = (MISSION_ITEM_INT, item.fields(seq, mission_type));
+        }
+    }
+
+    /// Downloads the plan of a type
+    pub async fn download(
+        &mut self,
+        mission_type: MissionType,
+    ) -> Result<Vec<MissionItem>, MissionError> {
+        let mission_type_value = ("mission_type", FieldValue::U8(mission_type.as_u8()));
+
+        let count = self
+            .request(
+                MISSION_REQUEST_LIST,
+                std::slice::from_ref(&mission_type_value),
+                mission_type,
+                |packet, dialect| match packet.message_id() {
+                    MISSION_COUNT => packet.field("count", dialect)?.as_i64(),
+                    _ => None,
+                },
+            )
+            .await?;
+
+        let mut items = Vec::with_capacity(count as usize);
+        for seq in 0..count as u16 {
+            let item = self
+                .request(
+                    MISSION_REQUEST_INT,
+                    &[("seq", FieldValue::U16(seq)), mission_type_value.clone()],
+                    mission_type,
+                    |packet, dialect| match MissionItem::from_packet(packet, dialect) {
+                        Some((item_seq, item)) if item_seq == seq => Some(item),
+                        _ => None,
+                    },
+                )
+                .await?;
+
+            items.push(item);
+        }
+
+        self.connection
+            .send(
+                MISSION_ACK,
+                &[
+                    ("type", FieldValue::U8(MissionResult::Accepted.as_u8())),
+                    mission_type_value,
+                ],
+            )
+            .await?;
+
+        debug!("Downloaded {} {mission_type:?} items", items.len());
+        Ok(items)
+    }
+
+    /// Removes the plan of a type
+    pub async fn clear(&mut self, mission_type: MissionType) -> Result<(), MissionError> {
+        self.request(
+            MISSION_CLEAR_ALL,
+            &[("mission_type", FieldValue::U8(mission_type.as_u8()))],
+            mission_type,
+            |packet, _| (packet.message_id() == MISSION_ACK).then_some(()),
+        )
+        .await
+    }
+
+    /// Sends a message until an answer about the same plan type matches.
+    ///
+    /// A MISSION_ACK with an error ends the transfer, as a [`MissionError::Rejected`].
+    async fn request<T>(
+        &mut self,
+        message_id: u32,
+        values: &[(&str, FieldValue)],
+        mission_type: MissionType,
+        matches: impl Fn(&Packet, &D) -> Option<T>,
+    ) -> Result<T, MissionError> {
+        let mut attempts = 0;
+
+        while attempts <= self.retries {
+            self.connection.send(message_id, values).await?;
+            attempts += 1;
+
+            let deadline = Instant::now() + self.timeout;
+            while let Some(packet) = self.connection.receive(deadline).await? {
+                let dialect = self.connection.builder().dialect();
+                let read = |name| packet.field(name, dialect).and_then(|value| value.as_i64());
+
+                if read("mission_type") != Some(mission_type.as_u8() as i64) {
+                    continue;
+                }
+
+                if packet.message_id() == MISSION_ACK {
+                    let result = MissionResult::from(read("type").unwrap_or_default() as u8);
+                    if result != MissionResult::Accepted {
+                        return Err(MissionError::Rejected(result));
+                    }
+                }
+
+                if let Some(answer) = matches(&packet, dialect) {
+                    return Ok(answer);
+                }
+            }
+        }
+
+        Err(ClientError::Timeout { attempts }.into())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::builder::PacketBuilder;
+    use futures::{channel::mpsc, SinkExt, StreamExt};
+    use std::{
+        collections::{BTreeSet, HashMap},
+        sync::{Arc, Mutex},
+    };
+
+    type Plans = Arc<Mutex<HashMap<u8, Vec<MissionItem>>>>;
+
+    /// A vehicle storing the plans, following the mission protocol
+    struct FakeVehicle {
+        plans: Plans,
+        /// The plan being uploaded, with its expected count
+        upload: Option<(u8, u16, Vec<MissionItem>)>,
+        /// Maximum number of rally points
+        rally_capacity: u16,
+        /// The answers lost, by their number
+        lost: BTreeSet<usize>,
+        answers: usize,
+        builder: PacketBuilder,
+    }
+
+    impl FakeVehicle {
+        fn new() -> Self {
+            Self {
+                plans: Plans::default(),
+                upload: None,
+                rally_capacity: 2,
+                lost: BTreeSet::new(),
+                answers: 0,
+                builder: PacketBuilder::new(1, 1),
+            }
+        }
+
+        fn build(
+            &self,
+            message_id: u32,
+            mission_type: u8,
+            values: &[(&str, FieldValue)],
+        ) -> Packet {
+            let mut values = values.to_vec();
+            values.push(("target_system", FieldValue::U8(255)));
+            values.push(("target_component", FieldValue::U8(190)));
+            values.push(("mission_type", FieldValue::U8(mission_type)));
+
+            self.builder.build_fields(message_id, &values).unwrap()
+        }
+
+        fn ack(&self, mission_type: u8, result: MissionResult) -> Packet {
+            self.build(
+                MISSION_ACK,
+                mission_type,
+                &[("type", FieldValue::U8(result.as_u8()))],
+            )
+        }
+
+        fn request(&self, mission_type: u8, seq: u16) -> Packet {
+            self.build(
+                MISSION_REQUEST_INT,
+                mission_type,
+                &[("seq", FieldValue::U16(seq))],
+            )
+        }
+
+        fn answer(&mut self, packet: &Packet) -> Option<Packet> {
+            let read = |name| {
+                packet
+                    .field(name, &ArduPilotMega)
+                    .and_then(|value| value.as_i64())
+                    .unwrap_or_default()
+            };
+            let mission_type = read("mission_type") as u8;
+
+            let answer = match packet.message_id() {
+                MISSION_COUNT => {
+                    let count = read("count") as u16;
+                    if mission_type == MissionType::Rally.as_u8() && count > self.rally_capacity {
+                        self.ack(mission_type, MissionResult::NoSpace)
+                    } else if count == 0 {
+                        self.plans.lock().unwrap().insert(mission_type, vec![]);
+                        self.ack(mission_type, MissionResult::Accepted)
+                    } else {
+                        self.upload = Some((mission_type, count, Vec::new()));
+                        self.request(mission_type, 0)
+                    }
+                }
+                MISSION_ITEM_INT => {
+                    let (seq, item) = MissionItem::from_packet(packet, &ArduPilotMega)?;
+                    // The last item again, from a lost acknowledgement
+                    let Some((upload_type, count, items)) = self.upload.as_mut() else {
+                        return Some(self.ack(mission_type, MissionResult::Accepted));
+                    };
+
+                    // A duplicate, from a lost request
+                    if seq as usize == items.len() {
+                        items.push(item);
+                    }
+
+                    if items.len() < *count as usize {
+                        let seq = items.len() as u16;
+                        self.request(mission_type, seq)
+                    } else {
+                        let upload_type = *upload_type;
+                        let (_, _, items) = self.upload.take()?;
+                        self.plans.lock().unwrap().insert(upload_type, items);
+                        self.ack(upload_type, MissionResult::Accepted)
+                    }
+                }
+                MISSION_REQUEST_LIST => {
+                    let count = self
+                        .plans
+                        .lock()
+                        .unwrap()
+                        .get(&mission_type)
+                        .map_or(0, |items| items.len());
+                    self.build(
+                        MISSION_COUNT,
+                        mission_type,
+                        &[("count", FieldValue::U16(count as u16))],
+                    )
+                }
+                MISSION_REQUEST_INT => {
+                    let seq = read("seq") as u16;
+                    let item = *self
+                        .plans
+                        .lock()
+                        .unwrap()
+                        .get(&mission_type)?
+                        .get(seq as usize)?;
+                    let mission_type = MissionType::try_from(mission_type).ok()?;
+
+                    self.builder
+                        .build_fields(
+                            MISSION_ITEM_INT,
+                            &[
+                                item.fields(seq, mission_type),
+                                vec![
+                                    ("target_system", FieldValue::U8(255)),
+                                    ("target_component", FieldValue::U8(190)),
+                                ],
+                            ]
+                            .concat(),
+                        )
+                        .unwrap()
+                }
+                MISSION_CLEAR_ALL => {
+                    self.plans.lock().unwrap().remove(&mission_type);
+                    self.ack(mission_type, MissionResult::Accepted)
+                }
+                _ => return None,
+            };
+
+            self.answers += 1;
+            if self.lost.contains(&self.answers) {
+                return None;
+            }
+
+            Some(answer)
+        }
+
+        fn spawn(mut self) -> (MissionClient, Plans) {
+            let (client_sink, mut vehicle_stream) = mpsc::channel::<Packet>(16);
+            let (mut vehicle_sink, client_stream) = mpsc::channel::<Packet>(16);
+            let plans = self.plans.clone();
+
+            tokio::spawn(async move {
+                while let Some(packet) = vehicle_stream.next().await {
+                    let Some(answer) = self.answer(&packet) else {
+                        continue;
+                    };
+
+                    tokio::time::sleep(Duration::from_millis(10)).await;
+                    if vehicle_sink.send(answer).await.is_err() {
+                        return;
+                    }
+                }
+            });
+
+            let connection = Connection::from_split(
+                client_stream,
+                client_sink,
+                PacketBuilder::new(255, 190),
+                (1, 1),
+            );
+
+            (MissionClient::new(connection), plans)
+        }
+    }
+
+    fn waypoints(count: i32) -> Vec<MissionItem> {
+        (0..count)
+            .map(|index| MissionItem {
+                command: 16, // MAV_CMD_NAV_WAYPOINT
+                frame: 6,    // MAV_FRAME_GLOBAL_RELATIVE_ALT_INT
+                current: index == 0,
+                autocontinue: true,
+                params: [0.0, 2.0, 0.0, 90.0],
+                x: 473_977_420 + index * 1000,
+                y: 85_455_940 - index * 1000,
+                z: 10.0 + index as f32,
+            })
+            .collect()
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_upload_and_download() {
+        let (mut client, plans) = FakeVehicle::new().spawn();
+
+        client
+            .upload(MissionType::Mission, &waypoints(3))
+            .await
+            .unwrap();
+        client
+            .upload(MissionType::Fence, &waypoints(1))
+            .await
+            .unwrap();
+
+        assert_eq!(plans.lock().unwrap().get(&0).map(Vec::len), Some(3));
+        assert_eq!(plans.lock().unwrap().get(&1).map(Vec::len), Some(1));
+
+        assert_eq!(
+            client.download(MissionType::Mission).await.unwrap(),
+            waypoints(3)
+        );
+        assert_eq!(
+            client.download(MissionType::Fence).await.unwrap(),
+            waypoints(1)
+        );
+        assert!(client
+            .download(MissionType::Rally)
+            .await
+            .unwrap()
+            .is_empty());
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_lossy_transfers() {
+        let mut vehicle = FakeVehicle::new();
+        // The first request, the acknowledgement of the upload, and an item of the download
+        vehicle.lost = BTreeSet::from([1, 5, 8]);
+        let (mut client, _) = vehicle.spawn();
+
+        client
+            .upload(MissionType::Mission, &waypoints(3))
+            .await
+            .unwrap();
+
+        assert_eq!(
+            client.download(MissionType::Mission).await.unwrap(),
+            waypoints(3)
+        );
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_rejected() {
+        let (mut client, plans) = FakeVehicle::new().spawn();
+
+        assert!(matches!(
+            client.upload(MissionType::Rally, &waypoints(3)).await,
+            Err(MissionError::Rejected(MissionResult::NoSpace))
+        ));
+        assert!(plans.lock().unwrap().is_empty());
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_too_many_items() {
+        let (mut client, plans) = FakeVehicle::new().spawn();
+
+        let start = Instant::now();
+        assert!(matches!(
+            client
+                .upload(MissionType::Mission, &waypoints(65_536))
+                .await,
+            Err(MissionError::TooManyItems { count: 65_536 })
+        ));
+        // Refused without waiting for the vehicle
+        assert_eq!(start.elapsed(), Duration::ZERO);
+        assert!(plans.lock().unwrap().is_empty());
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_clear() {
+        let (mut client, plans) = FakeVehicle::new().spawn();
+
+        client
+            .upload(MissionType::Mission, &waypoints(2))
+            .await
+            .unwrap();
+        client
+            .upload(MissionType::Rally, &waypoints(2))
+            .await
+            .unwrap();
+        client.clear(MissionType::Mission).await.unwrap();
+
+        assert!(client
+            .download(MissionType::Mission)
+            .await
+            .unwrap()
+            .is_empty());
+        assert_eq!(plans.lock().unwrap().get(&2).map(Vec::len), Some(2));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_timeout() {
+        let mut vehicle = FakeVehicle::new();
+        vehicle.lost = (1..=3).collect();
+        let (client, _) = vehicle.spawn();
+        let mut client = client.with_retries(2);
+
+        let start = Instant::now();
+        assert!(matches!(
+            client.download(MissionType::Mission).await,
+            Err(MissionError::Client(ClientError::Timeout { attempts: 3 }))
+        ));
+        assert_eq!(
+            start.elapsed(),
+            MissionClient::<ArduPilotMega>::DEFAULT_TIMEOUT * 3
+        );
+    }
+
+    #[test]
+    fn test_mission_result() {
+        for value in 0..=16 {
+            assert_eq!(MissionResult::from(value).as_u8(), value);
+        }
+        assert_eq!(MissionResult::from(8), MissionResult::InvalidParam(3));
+    }
+}
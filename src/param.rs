@@ -0,0 +1,739 @@
+//! The parameter protocol: PARAM_REQUEST_LIST, PARAM_REQUEST_READ and PARAM_SET, answered with
+//! PARAM_VALUE.
+
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    time::Duration,
+};
+
+use log::debug;
+use thiserror::Error;
+use tokio::time::Instant;
+
+use crate::{
+    client::{ClientError, Connection},
+    dialect::{ArduPilotMega, Dialect},
+    field::FieldValue,
+    Packet,
+};
+
+const PARAM_REQUEST_READ: u32 = 20;
+const PARAM_REQUEST_LIST: u32 = 21;
+const PARAM_VALUE: u32 = 22;
+const PARAM_SET: u32 = 23;
+
+/// Why a parameter client failed
+#[derive(Error, Debug)]
+#[non_exhaustive]
+pub enum ParamError {
+    #[error("parameter {name} is {value} instead of {requested}")]
+    NotSet {
+        name: String,
+        requested: ParamValue,
+        value: ParamValue,
+    },
+
+    #[error(transparent)]
+    Client(#[from] ClientError),
+}
+
+/// How the values of integer parameters are carried by the float `param_value` field.
+///
+/// Components advertise it in the capabilities of their AUTOPILOT_VERSION.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ParamEncoding {
+    /// The bytes of the integer, as in a C union, like PX4 does
+    /// (MAV_PROTOCOL_CAPABILITY_PARAM_ENCODE_BYTEWISE)
+    Bytewise,
+    /// The integer converted to a float, like ArduPilot does
+    /// (MAV_PROTOCOL_CAPABILITY_PARAM_ENCODE_C_CAST)
+    CCast,
+}
+
+/// The value of a parameter, typed by its MAV_PARAM_TYPE
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ParamValue {
+    U8(u8),
+    I8(i8),
+    U16(u16),
+    I16(i16),
+    U32(u32),
+    I32(i32),
+    F32(f32),
+    /// The 64-bit types, which don't fit in `param_value`, as received
+    Unsupported {
+        param_type: u8,
+        value: f32,
+    },
+}
+
+impl ParamValue {
+    /// Decodes the `param_value` field, of MAV_PARAM_TYPE `param_type`
+    pub fn decode(value: f32, param_type: u8, encoding: ParamEncoding) -> Self {
+        let [b0, b1, b2, b3] = value.to_le_bytes();
+
+        match (param_type, encoding) {
+            (1, ParamEncoding::Bytewise) => ParamValue::U8(b0),
+            (2, ParamEncoding::Bytewise) => ParamValue::I8(b0 as i8),
+            (3, ParamEncoding::Bytewise) => ParamValue::U16(u16::from_le_bytes([b0, b1])),
+            (4, ParamEncoding::Bytewise) => ParamValue::I16(i16::from_le_bytes([b0, b1])),
+            (5, ParamEncoding::Bytewise) => ParamValue::U32(u32::from_le_bytes([b0, b1, b2, b3])),
+            (6, ParamEncoding::Bytewise) => ParamValue::I32(i32::from_le_bytes([b0, b1, b2, b3])),
+            (1, ParamEncoding::CCast) => ParamValue::U8(value as u8),
+            (2, ParamEncoding::CCast) => ParamValue::I8(value as i8),
+            (3, ParamEncoding::CCast) => ParamValue::U16(value as u16),
+            (4, ParamEncoding::CCast) => ParamValue::I16(value as i16),
+            (5, ParamEncoding::CCast) => ParamValue::U32(value as u32),
+            (6, ParamEncoding::CCast) => ParamValue::I32(value as i32),
+            (9, _) => ParamValue::F32(value),
+            (param_type, _) => ParamValue::Unsupported { param_type, value },
+        }
+    }
+
+    /// The `param_value` and `param_type` fields
+    pub fn encode(&self, encoding: ParamEncoding) -> (f32, u8) {
+        let bytewise = |bytes: &[u8]| {
+            let mut value = [0u8; 4];
+            value[..bytes.len()].copy_from_slice(bytes);
+            f32::from_le_bytes(value)
+        };
+
+        let value = match (*self, encoding) {
+            (ParamValue::U8(value), ParamEncoding::Bytewise) => bytewise(&[value]),
+            (ParamValue::I8(value), ParamEncoding::Bytewise) => bytewise(&[value as u8]),
+            (ParamValue::U16(value), ParamEncoding::Bytewise) => bytewise(&value.to_le_bytes()),
+            (ParamValue::I16(value), ParamEncoding::Bytewise) => bytewise(&value.to_le_bytes()),
+            (ParamValue::U32(value), ParamEncoding::Bytewise) => bytewise(&value.to_le_bytes()),
+            (ParamValue::I32(value), ParamEncoding::Bytewise) => bytewise(&value.to_le_bytes()),
+            (ParamValue::U8(value), ParamEncoding::CCast) => value as f32,
+            (ParamValue::I8(value), ParamEncoding::CCast) => value as f32,
+            (ParamValue::U16(value), ParamEncoding::CCast) => value as f32,
+            (ParamValue::I16(value), ParamEncoding::CCast) => value as f32,
+            (ParamValue::U32(value), ParamEncoding::CCast) => value as f32,
+            (ParamValue::I32(value), ParamEncoding::CCast) => value as f32,
+            (ParamValue::F32(value), _) | (ParamValue::Unsupported { value, .. }, _) => value,
+        };
+
+        (value, self.param_type())
+    }
+
+    /// MAV_PARAM_TYPE
+    pub fn param_type(&self) -> u8 {
+        match self {
+            ParamValue::U8(_) => 1,
+            ParamValue::I8(_) => 2,
+            ParamValue::U16(_) => 3,
+            ParamValue::I16(_) => 4,
+            ParamValue::U32(_) => 5,
+            ParamValue::I32(_) => 6,
+            ParamValue::F32(_) => 9,
+            ParamValue::Unsupported { param_type, .. } => *param_type,
+        }
+    }
+
+    pub fn as_f64(&self) -> f64 {
+        match *self {
+            ParamValue::U8(value) => value as f64,
+            ParamValue::I8(value) => value as f64,
+            ParamValue::U16(value) => value as f64,
+            ParamValue::I16(value) => value as f64,
+            ParamValue::U32(value) => value as f64,
+            ParamValue::I32(value) => value as f64,
+            ParamValue::F32(value) | ParamValue::Unsupported { value, .. } => value as f64,
+        }
+    }
+}
+
+impl std::fmt::Display for ParamValue {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ParamValue::F32(value) | ParamValue::Unsupported { value, .. } => value.fmt(f),
+            value => (value.as_f64() as i64).fmt(f),
+        }
+    }
+}
+
+/// A parameter, as last reported by its component
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Param {
+    pub index: u16,
+    pub value: ParamValue,
+}
+
+/// Reads and writes the parameters of a component, keeping the values it reports in a cache.
+///
+/// Every PARAM_VALUE received updates the cache, including the ones the component broadcasts
+/// when its parameters change.
+#[derive(Debug)]
+pub struct ParamClient<D: Dialect = ArduPilotMega> {
+    connection: Connection<D>,
+    timeout: Duration,
+    retries: u32,
+    encoding: ParamEncoding,
+    params: BTreeMap<String, Param>,
+}
+
+impl<D: Dialect> ParamClient<D> {
+    pub const DEFAULT_TIMEOUT: Duration = Duration::from_secs(1);
+    pub const DEFAULT_RETRIES: u32 = 2;
+
+    /// A client of a component using `encoding` for its integer parameters
+    pub fn new(connection: Connection<D>, encoding: ParamEncoding) -> Self {
+        Self {
+            connection,
+            timeout: Self::DEFAULT_TIMEOUT,
+            retries: Self::DEFAULT_RETRIES,
+            encoding,
+            params: BTreeMap::new(),
+        }
+    }
+
+    /// How long to wait for a response, or for the next parameter of a list, before requesting
+    /// again
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// How many times to send again unanswered requests
+    pub fn with_retries(mut self, retries: u32) -> Self {
+        self.retries = retries;
+        self
+    }
+
+    pub fn connection(&self) -> &Connection<D> {
+        &self.connection
+    }
+
+    pub fn into_connection(self) -> Connection<D> {
+        self.connection
+    }
+
+    /// The cached parameters, by name
+    pub fn params(&self) -> &BTreeMap<String, Param> {
+        &self.params
+    }
+
+    /// The cached value of a parameter
+    pub fn cached(&self, name: &str) -> Option<ParamValue> {
+        self.params.get(name).map(|param| param.value)
+    }
+
+    /// Downloads all the parameters.
+    ///
+    /// The parameters lost from the list sent by the component are requested again one by one.
+    pub async fn download_all(&mut self) -> Result<&BTreeMap<String, Param>, ParamError> {
+        let mut received = BTreeSet::new();
+        let mut count = None;
+        let mut attempts = 0;
+
+        // Until the component starts listing its parameters
+        let count = loop {
+            if attempts > self.retries {
+                return Err(ClientError::Timeout { attempts }.into());
+            }
+            self.connection.send(PARAM_REQUEST_LIST, &[]).await?;
+            attempts += 1;
+
+            let mut deadline = Instant::now() + self.timeout;
+            while let Some(packet) = self.connection.receive(deadline).await? {
+                let Some((_, param, param_count)) = self.update(&packet) else {
+                    continue;
+                };
+
+                count = Some(param_count);
+                // Not the index of a listed parameter, like the u16::MAX of some PARAM_SET answers
+                if param.index < param_count {
+                    received.insert(param.index);
+                }
+                if received.len() >= param_count as usize {
+                    break;
+                }
+                deadline = Instant::now() + self.timeout;
+            }
+
+            if let Some(count) = count {
+                break count;
+            }
+        };
+
+        let missing = (0..count)
+            .filter(|index| !received.contains(index))
+            .collect::<Vec<_>>();
+        if !missing.is_empty() {
+            debug!("Requesting {} missing parameters", missing.len());
+        }
+
+        for index in missing {
+            self.request(
+                PARAM_REQUEST_READ,
+                &[("param_index", FieldValue::I16(index as i16))],
+                |_, param| param.index == index,
+            )
+            .await?;
+        }
+
+        Ok(&self.params)
+    }
+
+    /// Reads a parameter from the component
+    pub async fn get(&mut self, name: &str) -> Result<ParamValue, ParamError> {
+        let (_, param) = self
+            .request(
+                PARAM_REQUEST_READ,
+                &[
+                    ("param_index", FieldValue::I16(-1)),
+                    ("param_id", FieldValue::Text(name.to_string())),
+                ],
+                |param_id, _| param_id == name,
+            )
+            .await?;
+
+        Ok(param.value)
+    }
+
+    /// Sets a parameter, verifying the value reported back by the component.
+    ///
+    /// Components report their current value when they reject a new one, which is a
+    /// [`ParamError::NotSet`]. The values are compared as encoded, since integers above 2^24
+    /// can't be carried exactly with [`ParamEncoding::CCast`]: the component sets the nearest
+    /// float, which is what ends up cached.
+    pub async fn set(&mut self, name: &str, value: ParamValue) -> Result<(), ParamError> {
+        let (param_value, param_type) = value.encode(self.encoding);
+
+        let (_, param) = self
+            .request(
+                PARAM_SET,
+                &[
+                    ("param_value", FieldValue::F32(param_value)),
+                    ("param_id", FieldValue::Text(name.to_string())),
+                    ("param_type", FieldValue::U8(param_type)),
+                ],
+                |param_id, _| param_id == name,
+            )
+            .await?;
+
+        let (reported_value, reported_type) = param.value.encode(self.encoding);
+        if (reported_value.to_bits(), reported_type) != (param_value.to_bits(), param_type) {
+            return Err(ParamError::NotSet {
+                name: name.to_string(),
+                requested: value,
+                value: param.value,
+            });
+        }
+
+        Ok(())
+    }
+
+    /// Sends a request until a PARAM_VALUE matches
+    async fn request(
+        &mut self,
+        message_id: u32,
+        values: &[(&str, FieldValue)],
+        matches: impl Fn(&str, &Param) -> bool,
+    ) -> Result<(String, Param), ParamError> {
+        let mut attempts = 0;
+
+        while attempts <= self.retries {
+            self.connection.send(message_id, values).await?;
+            attempts += 1;
+
+            let deadline = Instant::now() + self.timeout;
+            while let Some(packet) = self.connection.receive(deadline).await? {
+                if let Some((param_id, param, _)) = self.update(&packet) {
+                    if matches(&param_id, &param) {
+                        return Ok((param_id, param));
+                    }
+                }
+            }
+        }
+
+        Err(ClientError::Timeout { attempts }.into())
+    }
+
+    /// Caches a PARAM_VALUE, returning it with the `param_count`
+    fn update(&mut self, packet: &Packet) -> Option<(String, Param, u16)> {
+        if packet.message_id() != PARAM_VALUE {
+            return None;
+        }
+
+        let dialect = self.connection.builder().dialect();
+        let read = |name| {
+            packet
+                .field(name, dialect)
+                .and_then(|value| value.as_i64())
+                .unwrap_or_default()
+        };
+
+        let Some(FieldValue::Text(param_id)) = packet.field("param_id", dialect) else {
+            return None;
+        };
+        let Some(FieldValue::F32(param_value)) = packet.field("param_value", dialect) else {
+            return None;
+        };
+        let mut index = read("param_index") as u16;
+
+        // The answers to PARAM_SET may have no index
+        if index == u16::MAX {
+            index = self
+                .params
+                .get(&param_id)
+                .map_or(index, |param| param.index);
+        }
+
+        let param = Param {
+            index,
+            value: ParamValue::decode(param_value, read("param_type") as u8, self.encoding),
+        };
+        self.params.insert(param_id.clone(), param);
+
+        Some((param_id, param, read("param_count") as u16))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::builder::PacketBuilder;
+    use futures::{channel::mpsc, SinkExt, StreamExt};
+    use std::sync::{Arc, Mutex};
+
+    type Requests = Arc<Mutex<Vec<Packet>>>;
+
+    /// A component serving its parameters
+    struct ParamServer {
+        params: Vec<(&'static str, ParamValue)>,
+        encoding: ParamEncoding,
+        read_only: &'static [&'static str],
+        /// The indices lost the first time they are sent
+        lost: BTreeSet<u16>,
+        /// Sent with no index before the list, like the answers to a PARAM_SET of another client
+        unindexed: Vec<(&'static str, ParamValue)>,
+        builder: PacketBuilder,
+    }
+
+    impl ParamServer {
+        fn new(params: Vec<(&'static str, ParamValue)>) -> Self {
+            Self {
+                params,
+                encoding: ParamEncoding::Bytewise,
+                read_only: &[],
+                lost: BTreeSet::new(),
+                unindexed: vec![],
+                builder: PacketBuilder::new(1, 1),
+            }
+        }
+
+        fn param_value(&mut self, index: u16) -> Option<Packet> {
+            if self.lost.remove(&index) {
+                return None;
+            }
+
+            let (name, value) = self.params[index as usize];
+            Some(self.build_param_value(name, value, index))
+        }
+
+        fn build_param_value(&self, name: &str, value: ParamValue, index: u16) -> Packet {
+            let (param_value, param_type) = value.encode(self.encoding);
+            self.builder
+                .build_fields(
+                    PARAM_VALUE,
+                    &[
+                        ("param_value", FieldValue::F32(param_value)),
+                        ("param_count", FieldValue::U16(self.params.len() as u16)),
+                        ("param_index", FieldValue::U16(index)),
+                        ("param_id", FieldValue::Text(name.to_string())),
+                        ("param_type", FieldValue::U8(param_type)),
+                    ],
+                )
+                .unwrap()
+        }
+
+        fn index_of(&self, packet: &Packet) -> Option<u16> {
+            let Some(FieldValue::Text(param_id)) = packet.field("param_id", &ArduPilotMega) else {
+                return None;
+            };
+
+            self.params
+                .iter()
+                .position(|(name, _)| *name == param_id)
+                .map(|index| index as u16)
+        }
+
+        fn answer(&mut self, packet: &Packet) -> Vec<Packet> {
+            match packet.message_id() {
+                PARAM_REQUEST_LIST => {
+                    let unindexed = self
+                        .unindexed
+                        .iter()
+                        .map(|(name, value)| self.build_param_value(name, *value, u16::MAX))
+                        .collect::<Vec<_>>();
+
+                    unindexed
+                        .into_iter()
+                        .chain(
+                            (0..self.params.len() as u16)
+                                .filter_map(|index| self.param_value(index)),
+                        )
+                        .collect()
+                }
+                PARAM_REQUEST_READ => {
+                    let index = match packet.field("param_index", &ArduPilotMega) {
+                        Some(FieldValue::I16(-1)) => self.index_of(packet),
+                        Some(FieldValue::I16(index)) => Some(index as u16),
+                        _ => None,
+                    };
+
+                    index
+                        .and_then(|index| self.param_value(index))
+                        .into_iter()
+                        .collect()
+                }
+                PARAM_SET => {
+                    let Some(index) = self.index_of(packet) else {
+                        return vec![];
+                    };
+
+                    let (name, value) = &mut self.params[index as usize];
+                    if !self.read_only.contains(name) {
+                        let read = |name| packet.field(name, &ArduPilotMega).unwrap();
+                        let (FieldValue::F32(param_value), FieldValue::U8(param_type)) =
+                            (read("param_value"), read("param_type"))
+                        else {
+                            return vec![];
+                        };
+                        *value = ParamValue::decode(param_value, param_type, self.encoding);
+                    }
+
+                    self.param_value(index).into_iter().collect()
+                }
+                _ => vec![],
+            }
+        }
+
+        fn spawn(mut self) -> (ParamClient, Requests) {
+            let encoding = self.encoding;
+            let (client_sink, mut server_stream) = mpsc::channel::<Packet>(16);
+            let (mut server_sink, client_stream) = mpsc::channel::<Packet>(256);
+            let requests = Requests::default();
+
+            let server_requests = requests.clone();
+            tokio::spawn(async move {
+                while let Some(packet) = server_stream.next().await {
+                    server_requests.lock().unwrap().push(packet.clone());
+
+                    for answer in self.answer(&packet) {
+                        tokio::time::sleep(Duration::from_millis(5)).await;
+                        if server_sink.send(answer).await.is_err() {
+                            return;
+                        }
+                    }
+                }
+            });
+
+            let connection = Connection::from_split(
+                client_stream,
+                client_sink,
+                PacketBuilder::new(255, 190),
+                (1, 1),
+            );
+
+            (ParamClient::new(connection, encoding), requests)
+        }
+    }
+
+    fn params() -> Vec<(&'static str, ParamValue)> {
+        vec![
+            ("SYSID_THISMAV", ParamValue::U8(1)),
+            ("TRIM_OFFSET", ParamValue::I8(-3)),
+            ("SERIAL0_BAUD", ParamValue::U16(57)),
+            ("MIS_TOTAL", ParamValue::I16(-1200)),
+            ("STAT_RUNTIME", ParamValue::U32(3_000_000_000)),
+            ("BRD_SER_NUM", ParamValue::I32(-123_456_789)),
+            ("ANGLE_MAX", ParamValue::F32(4500.5)),
+            // A bytewise encoded integer that is a signaling NaN as a float
+            ("RC_OPTIONS", ParamValue::I32(0x7F80_0001)),
+        ]
+    }
+
+    #[test]
+    fn test_param_encoding() {
+        for (_, value) in params() {
+            let (param_value, param_type) = value.encode(ParamEncoding::Bytewise);
+            assert_eq!(
+                ParamValue::decode(param_value, param_type, ParamEncoding::Bytewise),
+                value
+            );
+        }
+
+        assert_eq!(
+            ParamValue::U16(57).encode(ParamEncoding::Bytewise),
+            (f32::from_le_bytes([57, 0, 0, 0]), 3)
+        );
+        assert_eq!(
+            ParamValue::I16(-1200).encode(ParamEncoding::CCast),
+            (-1200.0, 4)
+        );
+        assert_eq!(
+            ParamValue::decode(-1200.0, 4, ParamEncoding::CCast),
+            ParamValue::I16(-1200)
+        );
+        assert_eq!(
+            ParamValue::decode(1.5, 10, ParamEncoding::CCast),
+            ParamValue::Unsupported {
+                param_type: 10,
+                value: 1.5
+            }
+        );
+        assert_eq!(ParamValue::I8(-3).to_string(), "-3");
+        assert_eq!(ParamValue::F32(4500.5).to_string(), "4500.5");
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_download_all() {
+        let (mut client, requests) = ParamServer::new(params()).spawn();
+
+        let downloaded = client.download_all().await.unwrap();
+        assert_eq!(downloaded.len(), 8);
+        for (index, (name, value)) in params().into_iter().enumerate() {
+            assert_eq!(
+                downloaded.get(name),
+                Some(&Param {
+                    index: index as u16,
+                    value
+                })
+            );
+        }
+        assert_eq!(requests.lock().unwrap().len(), 1);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_download_unindexed() {
+        let mut server = ParamServer::new(params());
+        server.unindexed = vec![("SR0_POSITION", ParamValue::U8(4))];
+        let (mut client, requests) = server.spawn();
+
+        // Not taken for one of the listed parameters
+        let downloaded = client.download_all().await.unwrap();
+        assert_eq!(downloaded.len(), 9);
+        assert_eq!(
+            client.cached("RC_OPTIONS"),
+            Some(ParamValue::I32(0x7F80_0001))
+        );
+        assert_eq!(requests.lock().unwrap().len(), 1);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_download_c_cast() {
+        let mut server = ParamServer::new(params());
+        server.encoding = ParamEncoding::CCast;
+        let (mut client, _) = server.spawn();
+
+        let downloaded = client.download_all().await.unwrap();
+        assert_eq!(downloaded["MIS_TOTAL"].value, ParamValue::I16(-1200));
+        assert_eq!(downloaded["SERIAL0_BAUD"].value, ParamValue::U16(57));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_download_missing() {
+        let mut server = ParamServer::new(params());
+        // Lost from the list
+        server.lost = BTreeSet::from([2, 6]);
+        let (mut client, requests) = server.spawn();
+
+        assert_eq!(client.download_all().await.unwrap().len(), 8);
+        assert_eq!(client.cached("SERIAL0_BAUD"), Some(ParamValue::U16(57)));
+        assert_eq!(client.cached("ANGLE_MAX"), Some(ParamValue::F32(4500.5)));
+
+        let requests = requests
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|packet| {
+                (
+                    packet.message_id(),
+                    packet
+                        .field("param_index", &ArduPilotMega)
+                        .and_then(|value| value.as_i64()),
+                )
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            requests,
+            vec![
+                (PARAM_REQUEST_LIST, None),
+                (PARAM_REQUEST_READ, Some(2)),
+                (PARAM_REQUEST_READ, Some(6)),
+            ]
+        );
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_get_and_set() {
+        let mut server = ParamServer::new(params());
+        server.read_only = &["STAT_RUNTIME"];
+        let (mut client, _) = server.spawn();
+
+        assert_eq!(
+            client.get("BRD_SER_NUM").await.unwrap(),
+            ParamValue::I32(-123_456_789)
+        );
+        assert_eq!(
+            client.params().get("BRD_SER_NUM").map(|param| param.index),
+            Some(5)
+        );
+
+        client
+            .set("RC_OPTIONS", ParamValue::I32(0x7F80_0002))
+            .await
+            .unwrap();
+        assert_eq!(
+            client.cached("RC_OPTIONS"),
+            Some(ParamValue::I32(0x7F80_0002))
+        );
+        assert_eq!(
+            client.get("RC_OPTIONS").await.unwrap(),
+            ParamValue::I32(0x7F80_0002)
+        );
+
+        assert!(matches!(
+            client.set("STAT_RUNTIME", ParamValue::U32(0)).await,
+            Err(ParamError::NotSet {
+                requested: ParamValue::U32(0),
+                value: ParamValue::U32(3_000_000_000),
+                ..
+            })
+        ));
+
+        assert!(matches!(
+            client.get("UNKNOWN").await,
+            Err(ParamError::Client(ClientError::Timeout { attempts: 3 }))
+        ));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_set_c_cast() {
+        let mut server = ParamServer::new(params());
+        server.encoding = ParamEncoding::CCast;
+        let (mut client, _) = server.spawn();
+
+        // 2^24 + 1 is rounded to the nearest float on the way
+        client
+            .set("STAT_RUNTIME", ParamValue::U32(16_777_217))
+            .await
+            .unwrap();
+        assert_eq!(
+            client.cached("STAT_RUNTIME"),
+            Some(ParamValue::U32(16_777_216))
+        );
+
+        client
+            .set("BRD_SER_NUM", ParamValue::I32(-123_456_789))
+            .await
+            .unwrap();
+        assert_eq!(
+            client.cached("BRD_SER_NUM"),
+            Some(ParamValue::I32(-123_456_792))
+        );
+    }
+}
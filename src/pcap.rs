@@ -0,0 +1,999 @@
+//! pcap/pcapng import and export of MAVLink-over-UDP traffic.
+//!
+//! [`PcapReader`] pulls the UDP payloads out of pcap or pcapng captures and decodes them, while
+//! [`PcapWriter`] produces classic pcap files that Wireshark's MAVLink dissector can open.
+
+use std::{
+    collections::VecDeque,
+    io::{self, Read, Write},
+    net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use bytes::BytesMut;
+use log::trace;
+use tokio_util::codec::Decoder;
+
+use crate::{codec::MavlinkCodec, error::DecoderError, Packet};
+
+pub const PCAP_MAGIC_MICROS: u32 = 0xA1B2_C3D4;
+pub const PCAP_MAGIC_NANOS: u32 = 0xA1B2_3C4D;
+pub const PCAPNG_SECTION_HEADER_BLOCK: u32 = 0x0A0D_0D0A;
+pub const PCAPNG_BYTE_ORDER_MAGIC: u32 = 0x1A2B_3C4D;
+
+const PCAPNG_INTERFACE_DESCRIPTION_BLOCK: u32 = 0x0000_0001;
+const PCAPNG_SIMPLE_PACKET_BLOCK: u32 = 0x0000_0003;
+const PCAPNG_ENHANCED_PACKET_BLOCK: u32 = 0x0000_0006;
+const PCAPNG_OPTION_END: u16 = 0;
+const PCAPNG_OPTION_IF_TSRESOL: u16 = 9;
+
+const ETHERTYPE_IPV4: u16 = 0x0800;
+const ETHERTYPE_IPV6: u16 = 0x86DD;
+const ETHERTYPE_VLAN: u16 = 0x8100;
+const ETHERTYPE_QINQ: u16 = 0x88A8;
+
+const IP_PROTOCOL_UDP: u8 = 17;
+
+const ETHERNET_HEADER_SIZE: usize = 14;
+const IPV4_HEADER_SIZE: usize = 20;
+const IPV6_HEADER_SIZE: usize = 40;
+const UDP_HEADER_SIZE: usize = 8;
+
+/// Largest captured frame or pcapng block read, well above any UDP datagram, so corrupt lengths
+/// are rejected before allocating
+const MAX_BLOCK_SIZE: usize = 256 * 1024;
+
+/// A capture of MAVLink packets sent over UDP: `(timestamp, source, destination, packet)`
+pub type PcapRecord = (SystemTime, SocketAddr, SocketAddr, Packet);
+
+/// A packet decoded out of a capture, or the error its datagram failed with:
+/// `(timestamp, source, destination, decoded packet)`
+pub type PcapDecoded = (
+    SystemTime,
+    SocketAddr,
+    SocketAddr,
+    Result<Packet, DecoderError>,
+);
+
+/// Link-layer header types supported by [`PcapReader`]
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[repr(u32)]
+pub enum LinkType {
+    Ethernet = 1,
+    Raw = 101,
+    LinuxSll = 113,
+    Ipv4 = 228,
+    Ipv6 = 229,
+    LinuxSll2 = 276,
+}
+
+impl TryFrom<u32> for LinkType {
+    type Error = io::Error;
+
+    fn try_from(value: u32) -> Result<Self, Self::Error> {
+        Ok(match value {
+            1 => Self::Ethernet,
+            101 => Self::Raw,
+            113 => Self::LinuxSll,
+            228 => Self::Ipv4,
+            229 => Self::Ipv6,
+            276 => Self::LinuxSll2,
+            _ => {
+                return Err(io::Error::new(
+                    io::ErrorKind::Unsupported,
+                    format!("Unsupported link type: {value}"),
+                ))
+            }
+        })
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+enum Endianness {
+    Little,
+    Big,
+}
+
+impl Endianness {
+    #[inline(always)]
+    fn u16(&self, bytes: &[u8]) -> u16 {
+        let bytes = [bytes[0], bytes[1]];
+        match self {
+            Endianness::Little => u16::from_le_bytes(bytes),
+            Endianness::Big => u16::from_be_bytes(bytes),
+        }
+    }
+
+    #[inline(always)]
+    fn u32(&self, bytes: &[u8]) -> u32 {
+        let bytes = [bytes[0], bytes[1], bytes[2], bytes[3]];
+        match self {
+            Endianness::Little => u32::from_le_bytes(bytes),
+            Endianness::Big => u32::from_be_bytes(bytes),
+        }
+    }
+}
+
+/// A captured link-layer frame: `(timestamp, link type, data)`
+type Frame = (SystemTime, Option<LinkType>, Vec<u8>);
+
+#[derive(Debug, Copy, Clone)]
+struct Interface {
+    link_type: Option<LinkType>,
+    /// Timestamp resolution, from the `if_tsresol` option
+    units_per_second: u64,
+}
+
+#[derive(Debug)]
+enum Format {
+    Pcap {
+        endianness: Endianness,
+        link_type: LinkType,
+        nanos: bool,
+    },
+    PcapNg {
+        endianness: Endianness,
+        interfaces: Vec<Interface>,
+    },
+}
+
+/// Reads MAVLink packets out of the UDP datagrams of a pcap or pcapng capture.
+///
+/// Supported link types are listed by [`LinkType`]. Packets from interfaces of unsupported link
+/// types, non-UDP traffic and IP fragments are skipped.
+///
+/// Each datagram is decoded by a fresh clone of the codec, so a truncated packet doesn't corrupt
+/// the next datagrams, and decode errors are returned along with the packets.
+#[derive(Debug)]
+pub struct PcapReader<R, D = MavlinkCodec<true, true, false, false, false, false>> {
+    reader: R,
+    format: Format,
+    codec: D,
+    pending: VecDeque<PcapDecoded>,
+}
+
+impl<R: Read> PcapReader<R> {
+    pub fn new(reader: R) -> io::Result<Self> {
+        Self::with_codec(reader, MavlinkCodec::default())
+    }
+}
+
+impl<R: Read, D> PcapReader<R, D> {
+    /// Decodes the datagrams with `codec`, to select the accepted versions or skip the CRC
+    /// validation
+    pub fn with_codec(mut reader: R, codec: D) -> io::Result<Self> {
+        let mut magic = [0u8; 4];
+        reader.read_exact(&mut magic)?;
+
+        let format = if u32::from_le_bytes(magic) == PCAPNG_SECTION_HEADER_BLOCK {
+            Format::PcapNg {
+                endianness: read_section_header_block(&mut reader)?,
+                interfaces: Vec::new(),
+            }
+        } else {
+            let (endianness, magic) = match (u32::from_le_bytes(magic), u32::from_be_bytes(magic)) {
+                (magic @ (PCAP_MAGIC_MICROS | PCAP_MAGIC_NANOS), _) => (Endianness::Little, magic),
+                (_, magic @ (PCAP_MAGIC_MICROS | PCAP_MAGIC_NANOS)) => (Endianness::Big, magic),
+                _ => {
+                    return Err(io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        "Not a pcap or pcapng file",
+                    ))
+                }
+            };
+
+            let mut header = [0u8; 20];
+            reader.read_exact(&mut header)?;
+
+            Format::Pcap {
+                endianness,
+                link_type: LinkType::try_from(endianness.u32(&header[16..20]))?,
+                nanos: magic == PCAP_MAGIC_NANOS,
+            }
+        };
+
+        Ok(Self {
+            reader,
+            format,
+            codec,
+            pending: VecDeque::new(),
+        })
+    }
+
+    pub fn into_inner(self) -> R {
+        self.reader
+    }
+
+    /// Reads the next captured frame, returning its timestamp, link type and data
+    fn read_frame(&mut self) -> io::Result<Option<Frame>> {
+        match &mut self.format {
+            Format::Pcap {
+                endianness,
+                link_type,
+                nanos,
+            } => {
+                let mut header = [0u8; 16];
+                if !read_exact_or_eof(&mut self.reader, &mut header)? {
+                    return Ok(None);
+                }
+
+                let seconds = endianness.u32(&header[0..4]) as u64;
+                let fraction = endianness.u32(&header[4..8]) as u64;
+                let captured_length = check_length(endianness.u32(&header[8..12]))?;
+
+                let mut data = vec![0u8; captured_length];
+                self.reader.read_exact(&mut data)?;
+
+                let fraction = if *nanos {
+                    Duration::from_nanos(fraction)
+                } else {
+                    Duration::from_micros(fraction)
+                };
+                let timestamp = UNIX_EPOCH + Duration::from_secs(seconds) + fraction;
+
+                Ok(Some((timestamp, Some(*link_type), data)))
+            }
+            Format::PcapNg {
+                endianness,
+                interfaces,
+            } => loop {
+                let mut header = [0u8; 8];
+                if !read_exact_or_eof(&mut self.reader, &mut header)? {
+                    return Ok(None);
+                }
+
+                if u32::from_le_bytes([header[0], header[1], header[2], header[3]])
+                    == PCAPNG_SECTION_HEADER_BLOCK
+                {
+                    // A new section, which might have a different endianness
+                    let mut magic = [0u8; 4];
+                    self.reader.read_exact(&mut magic)?;
+                    *endianness = section_endianness(&magic)?;
+                    interfaces.clear();
+
+                    let block_length = check_length(endianness.u32(&header[4..8]))?;
+                    let mut body = vec![0u8; block_length.saturating_sub(12)];
+                    self.reader.read_exact(&mut body)?;
+                    continue;
+                }
+
+                let block_type = endianness.u32(&header[0..4]);
+                let block_length = check_length(endianness.u32(&header[4..8]))?;
+                if block_length < 12 || !block_length.is_multiple_of(4) {
+                    return Err(io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        format!("Invalid pcapng block length: {block_length}"),
+                    ));
+                }
+
+                // Body plus the trailing block length
+                let mut body = vec![0u8; block_length - header.len()];
+                self.reader.read_exact(&mut body)?;
+                let body = &body[..body.len() - 4];
+
+                match block_type {
+                    PCAPNG_INTERFACE_DESCRIPTION_BLOCK if body.len() >= 8 => {
+                        let link_type = LinkType::try_from(endianness.u16(&body[0..2]) as u32)
+                            .inspect_err(|error| trace!("Ignoring interface: {error}"))
+                            .ok();
+                        let units_per_second = interface_resolution(*endianness, &body[8..]);
+
+                        interfaces.push(Interface {
+                            link_type,
+                            units_per_second,
+                        });
+                    }
+                    PCAPNG_ENHANCED_PACKET_BLOCK if body.len() >= 20 => {
+                        let interface_id = endianness.u32(&body[0..4]) as usize;
+                        let Some(interface) = interfaces.get(interface_id) else {
+                            trace!("Unknown interface {interface_id}");
+                            continue;
+                        };
+
+                        let units = ((endianness.u32(&body[4..8]) as u64) << 32)
+                            | endianness.u32(&body[8..12]) as u64;
+                        let captured_length = endianness.u32(&body[12..16]) as usize;
+                        let data = body
+                            .get(20..20 + captured_length)
+                            .ok_or_else(|| {
+                                io::Error::new(
+                                    io::ErrorKind::InvalidData,
+                                    "Truncated enhanced packet block",
+                                )
+                            })?
+                            .to_vec();
+
+                        let seconds = units / interface.units_per_second;
+                        let nanos = (units % interface.units_per_second) as u128 * 1_000_000_000
+                            / interface.units_per_second as u128;
+                        let timestamp = UNIX_EPOCH + Duration::new(seconds, nanos as u32);
+
+                        return Ok(Some((timestamp, interface.link_type, data)));
+                    }
+                    PCAPNG_SIMPLE_PACKET_BLOCK if body.len() >= 4 => {
+                        let Some(interface) = interfaces.first() else {
+                            trace!("Simple packet block without interface");
+                            continue;
+                        };
+
+                        let original_length = endianness.u32(&body[0..4]) as usize;
+                        let data = body[4..].iter().take(original_length).copied().collect();
+
+                        return Ok(Some((UNIX_EPOCH, interface.link_type, data)));
+                    }
+                    _ => {
+                        trace!("Skipping pcapng block type {block_type:#x}");
+                    }
+                }
+            },
+        }
+    }
+}
+
+impl<R, D> Iterator for PcapReader<R, D>
+where
+    R: Read,
+    D: Decoder<Item = Result<Packet, DecoderError>, Error = io::Error> + Clone,
+{
+    type Item = io::Result<PcapDecoded>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some(record) = self.pending.pop_front() {
+                return Some(Ok(record));
+            }
+
+            let (timestamp, link_type, frame) = match self.read_frame() {
+                Ok(Some(frame)) => frame,
+                Ok(None) => return None,
+                Err(error) => return Some(Err(error)),
+            };
+
+            let Some(link_type) = link_type else {
+                continue;
+            };
+
+            let Some((source, destination, payload)) = udp_datagram(link_type, &frame) else {
+                continue;
+            };
+
+            let mut codec = self.codec.clone();
+            let mut buf = BytesMut::from(payload);
+            loop {
+                match codec.decode(&mut buf) {
+                    Ok(Some(decoded)) => {
+                        self.pending
+                            .push_back((timestamp, source, destination, decoded));
+                    }
+                    Ok(None) => break,
+                    Err(error) => return Some(Err(error)),
+                }
+            }
+        }
+    }
+}
+
+/// Writes MAVLink packets as UDP datagrams into a classic pcap capture, with Ethernet framing.
+#[derive(Debug)]
+pub struct PcapWriter<W: Write> {
+    writer: W,
+}
+
+impl<W: Write> PcapWriter<W> {
+    pub const SNAPLEN: u32 = 65535;
+
+    /// Creates the writer, writing the pcap global header right away
+    pub fn new(mut writer: W) -> io::Result<Self> {
+        let mut header = Vec::with_capacity(24);
+        header.extend_from_slice(&PCAP_MAGIC_MICROS.to_le_bytes());
+        header.extend_from_slice(&2u16.to_le_bytes()); // Version major
+        header.extend_from_slice(&4u16.to_le_bytes()); // Version minor
+        header.extend_from_slice(&0i32.to_le_bytes()); // Timezone offset
+        header.extend_from_slice(&0u32.to_le_bytes()); // Timestamp accuracy
+        header.extend_from_slice(&Self::SNAPLEN.to_le_bytes());
+        header.extend_from_slice(&(LinkType::Ethernet as u32).to_le_bytes());
+
+        writer.write_all(&header)?;
+
+        Ok(Self { writer })
+    }
+
+    /// Writes `packet` as the payload of a UDP datagram sent from `source` to `destination`.
+    ///
+    /// Both addresses must be of the same IP version.
+    pub fn write(
+        &mut self,
+        timestamp: SystemTime,
+        source: SocketAddr,
+        destination: SocketAddr,
+        packet: &Packet,
+    ) -> io::Result<()> {
+        let frame = ethernet_frame(source, destination, packet.as_slice())?;
+
+        let since_epoch = timestamp.duration_since(UNIX_EPOCH).unwrap_or_default();
+        let mut header = Vec::with_capacity(16);
+        header.extend_from_slice(&(since_epoch.as_secs() as u32).to_le_bytes());
+        header.extend_from_slice(&since_epoch.subsec_micros().to_le_bytes());
+        header.extend_from_slice(&(frame.len() as u32).to_le_bytes());
+        header.extend_from_slice(&(frame.len() as u32).to_le_bytes());
+
+        self.writer.write_all(&header)?;
+        self.writer.write_all(&frame)
+    }
+
+    pub fn write_record(&mut self, record: &PcapRecord) -> io::Result<()> {
+        let (timestamp, source, destination, packet) = record;
+        self.write(*timestamp, *source, *destination, packet)
+    }
+
+    pub fn flush(&mut self) -> io::Result<()> {
+        self.writer.flush()
+    }
+
+    pub fn into_inner(self) -> W {
+        self.writer
+    }
+}
+
+fn read_exact_or_eof<R: Read>(reader: &mut R, buf: &mut [u8]) -> io::Result<bool> {
+    let mut read = 0;
+    while read < buf.len() {
+        match reader.read(&mut buf[read..]) {
+            Ok(0) if read == 0 => return Ok(false),
+            Ok(0) => return Err(io::ErrorKind::UnexpectedEof.into()),
+            Ok(n) => read += n,
+            Err(error) if error.kind() == io::ErrorKind::Interrupted => (),
+            Err(error) => return Err(error),
+        }
+    }
+
+    Ok(true)
+}
+
+/// Rejects a length read from the file above [`MAX_BLOCK_SIZE`]
+fn check_length(length: u32) -> io::Result<usize> {
+    let length = length as usize;
+    if length > MAX_BLOCK_SIZE {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("Length of {length} bytes exceeds the maximum of {MAX_BLOCK_SIZE}"),
+        ));
+    }
+
+    Ok(length)
+}
+
+fn section_endianness(magic: &[u8; 4]) -> io::Result<Endianness> {
+    if u32::from_le_bytes(*magic) == PCAPNG_BYTE_ORDER_MAGIC {
+        Ok(Endianness::Little)
+    } else if u32::from_be_bytes(*magic) == PCAPNG_BYTE_ORDER_MAGIC {
+        Ok(Endianness::Big)
+    } else {
+        Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "Invalid pcapng byte-order magic",
+        ))
+    }
+}
+
+/// Reads the rest of a Section Header Block, after its block type
+fn read_section_header_block<R: Read>(reader: &mut R) -> io::Result<Endianness> {
+    let mut header = [0u8; 8];
+    reader.read_exact(&mut header)?;
+
+    let mut magic = [0u8; 4];
+    magic.copy_from_slice(&header[4..8]);
+    let endianness = section_endianness(&magic)?;
+
+    let block_length = check_length(endianness.u32(&header[0..4]))?;
+    let mut body = vec![0u8; block_length.saturating_sub(12)];
+    reader.read_exact(&mut body)?;
+
+    Ok(endianness)
+}
+
+/// Parses the Interface Description Block options, looking for `if_tsresol`
+fn interface_resolution(endianness: Endianness, mut options: &[u8]) -> u64 {
+    let mut units_per_second = 1_000_000;
+
+    while options.len() >= 4 {
+        let code = endianness.u16(&options[0..2]);
+        let length = endianness.u16(&options[2..4]) as usize;
+        let padded_length = length.div_ceil(4) * 4;
+
+        if code == PCAPNG_OPTION_END {
+            break;
+        }
+
+        if code == PCAPNG_OPTION_IF_TSRESOL && length == 1 && options.len() > 4 {
+            let value = options[4];
+            let exponent = (value & 0x7F) as u32;
+            let base: u64 = if value & 0x80 == 0 { 10 } else { 2 };
+            units_per_second = base.checked_pow(exponent).unwrap_or(u64::MAX);
+        }
+
+        options = options.get(4 + padded_length..).unwrap_or_default();
+    }
+
+    units_per_second
+}
+
+/// Extracts the source, destination and payload of the UDP datagram carried by `frame`
+fn udp_datagram(link_type: LinkType, frame: &[u8]) -> Option<(SocketAddr, SocketAddr, &[u8])> {
+    let (ethertype, network) = match link_type {
+        LinkType::Ethernet => {
+            let mut ethertype = u16::from_be_bytes([*frame.get(12)?, *frame.get(13)?]);
+            let mut offset = ETHERNET_HEADER_SIZE;
+            while ethertype == ETHERTYPE_VLAN || ethertype == ETHERTYPE_QINQ {
+                ethertype = u16::from_be_bytes([*frame.get(offset + 2)?, *frame.get(offset + 3)?]);
+                offset += 4;
+            }
+
+            (Some(ethertype), frame.get(offset..)?)
+        }
+        LinkType::LinuxSll => (
+            Some(u16::from_be_bytes([*frame.get(14)?, *frame.get(15)?])),
+            frame.get(16..)?,
+        ),
+        LinkType::LinuxSll2 => (
+            Some(u16::from_be_bytes([*frame.first()?, *frame.get(1)?])),
+            frame.get(20..)?,
+        ),
+        LinkType::Raw => (None, frame),
+        LinkType::Ipv4 => (Some(ETHERTYPE_IPV4), frame),
+        LinkType::Ipv6 => (Some(ETHERTYPE_IPV6), frame),
+    };
+
+    let version = network.first()? >> 4;
+    let (source, destination, udp) = match (ethertype, version) {
+        (Some(ETHERTYPE_IPV4) | None, 4) => ipv4_udp(network)?,
+        (Some(ETHERTYPE_IPV6) | None, 6) => ipv6_udp(network)?,
+        _ => return None,
+    };
+
+    let source_port = u16::from_be_bytes([*udp.first()?, *udp.get(1)?]);
+    let destination_port = u16::from_be_bytes([*udp.get(2)?, *udp.get(3)?]);
+    let length = u16::from_be_bytes([*udp.get(4)?, *udp.get(5)?]) as usize;
+    // Truncated captures still carry useful packets
+    let payload = udp.get(UDP_HEADER_SIZE..length.min(udp.len()))?;
+
+    Some((
+        SocketAddr::new(source, source_port),
+        SocketAddr::new(destination, destination_port),
+        payload,
+    ))
+}
+
+fn ipv4_udp(packet: &[u8]) -> Option<(IpAddr, IpAddr, &[u8])> {
+    let header_length = ((packet.first()? & 0x0F) as usize) * 4;
+    if header_length < IPV4_HEADER_SIZE {
+        return None;
+    }
+
+    let total_length = u16::from_be_bytes([*packet.get(2)?, *packet.get(3)?]) as usize;
+    let flags_fragment = u16::from_be_bytes([*packet.get(6)?, *packet.get(7)?]);
+    let protocol = *packet.get(9)?;
+
+    let more_fragments = flags_fragment & 0x2000 != 0;
+    let fragment_offset = flags_fragment & 0x1FFF;
+    if protocol != IP_PROTOCOL_UDP || more_fragments || fragment_offset != 0 {
+        return None;
+    }
+
+    let source: [u8; 4] = packet.get(12..16)?.try_into().ok()?;
+    let destination: [u8; 4] = packet.get(16..20)?.try_into().ok()?;
+    let end = total_length.clamp(header_length, packet.len());
+
+    Some((
+        IpAddr::V4(Ipv4Addr::from(source)),
+        IpAddr::V4(Ipv4Addr::from(destination)),
+        packet.get(header_length..end)?,
+    ))
+}
+
+fn ipv6_udp(packet: &[u8]) -> Option<(IpAddr, IpAddr, &[u8])> {
+    const HOP_BY_HOP: u8 = 0;
+    const ROUTING: u8 = 43;
+    const DESTINATION_OPTIONS: u8 = 60;
+
+    let mut next_header = *packet.get(6)?;
+    let source: [u8; 16] = packet.get(8..24)?.try_into().ok()?;
+    let destination: [u8; 16] = packet.get(24..40)?.try_into().ok()?;
+
+    let mut offset = IPV6_HEADER_SIZE;
+    while matches!(next_header, HOP_BY_HOP | ROUTING | DESTINATION_OPTIONS) {
+        next_header = *packet.get(offset)?;
+        offset += (*packet.get(offset + 1)? as usize + 1) * 8;
+    }
+
+    if next_header != IP_PROTOCOL_UDP {
+        return None;
+    }
+
+    Some((
+        IpAddr::V6(Ipv6Addr::from(source)),
+        IpAddr::V6(Ipv6Addr::from(destination)),
+        packet.get(offset..)?,
+    ))
+}
+
+/// Builds an Ethernet frame carrying `payload` in a UDP datagram from `source` to `destination`
+fn ethernet_frame(
+    source: SocketAddr,
+    destination: SocketAddr,
+    payload: &[u8],
+) -> io::Result<Vec<u8>> {
+    let udp_length = UDP_HEADER_SIZE + payload.len();
+    if IPV6_HEADER_SIZE + udp_length > u16::MAX as usize {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "Datagram too large",
+        ));
+    }
+
+    let mut frame = Vec::with_capacity(ETHERNET_HEADER_SIZE + IPV6_HEADER_SIZE + udp_length);
+
+    // Locally administered MAC addresses
+    frame.extend_from_slice(&[0x02, 0, 0, 0, 0, 0x02]);
+    frame.extend_from_slice(&[0x02, 0, 0, 0, 0, 0x01]);
+
+    let mut udp = Vec::with_capacity(udp_length);
+    udp.extend_from_slice(&source.port().to_be_bytes());
+    udp.extend_from_slice(&destination.port().to_be_bytes());
+    udp.extend_from_slice(&(udp_length as u16).to_be_bytes());
+    udp.extend_from_slice(&[0, 0]); // Checksum
+    udp.extend_from_slice(payload);
+
+    match (source.ip(), destination.ip()) {
+        (IpAddr::V4(source), IpAddr::V4(destination)) => {
+            frame.extend_from_slice(&ETHERTYPE_IPV4.to_be_bytes());
+
+            let mut header = [0u8; IPV4_HEADER_SIZE];
+            header[0] = 0x45; // Version 4, 5 words of header
+            header[2..4].copy_from_slice(&((IPV4_HEADER_SIZE + udp_length) as u16).to_be_bytes());
+            header[6] = 0x40; // Don't fragment
+            header[8] = 64; // TTL
+            header[9] = IP_PROTOCOL_UDP;
+            header[12..16].copy_from_slice(&source.octets());
+            header[16..20].copy_from_slice(&destination.octets());
+            let checksum = internet_checksum(&[&header]);
+            header[10..12].copy_from_slice(&checksum.to_be_bytes());
+
+            // The UDP checksum is optional for IPv4
+            frame.extend_from_slice(&header);
+        }
+        (IpAddr::V6(source), IpAddr::V6(destination)) => {
+            frame.extend_from_slice(&ETHERTYPE_IPV6.to_be_bytes());
+
+            let mut header = [0u8; IPV6_HEADER_SIZE];
+            header[0] = 0x60; // Version 6
+            header[4..6].copy_from_slice(&(udp_length as u16).to_be_bytes());
+            header[6] = IP_PROTOCOL_UDP;
+            header[7] = 64; // Hop limit
+            header[8..24].copy_from_slice(&source.octets());
+            header[24..40].copy_from_slice(&destination.octets());
+
+            // The UDP checksum is mandatory for IPv6
+            let mut pseudo_header = [0u8; 40];
+            pseudo_header[0..32].copy_from_slice(&header[8..40]);
+            pseudo_header[32..36].copy_from_slice(&(udp_length as u32).to_be_bytes());
+            pseudo_header[39] = IP_PROTOCOL_UDP;
+            let checksum = match internet_checksum(&[&pseudo_header, &udp]) {
+                0 => 0xFFFF,
+                checksum => checksum,
+            };
+            udp[6..8].copy_from_slice(&checksum.to_be_bytes());
+
+            frame.extend_from_slice(&header);
+        }
+        _ => {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "Source and destination must be of the same IP version",
+            ))
+        }
+    }
+
+    frame.extend_from_slice(&udp);
+
+    Ok(frame)
+}
+
+/// RFC 1071 checksum over the concatenation of `chunks`, each one of even length but the last
+fn internet_checksum(chunks: &[&[u8]]) -> u16 {
+    let mut sum = 0u32;
+    for chunk in chunks {
+        for word in chunk.chunks(2) {
+            let word = match word {
+                [high, low] => u16::from_be_bytes([*high, *low]),
+                [high] => u16::from_be_bytes([*high, 0]),
+                _ => unreachable!(),
+            };
+            sum += word as u32;
+        }
+    }
+
+    while sum > 0xFFFF {
+        sum = (sum & 0xFFFF) + (sum >> 16);
+    }
+
+    !(sum as u16)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test_util::{create_v1_packet, create_v2_packet, header};
+
+    fn create_records(source: SocketAddr, destination: SocketAddr) -> Vec<PcapRecord> {
+        (0..6u8)
+            .map(|sequence| {
+                let timestamp = UNIX_EPOCH
+                    + Duration::from_secs(1_700_000_000)
+                    + Duration::from_micros(sequence as u64 * 1_500);
+                // Heartbeat messages
+                let packet = if sequence % 2 == 0 {
+                    create_v2_packet(header(1, 1, sequence), 0)
+                } else {
+                    create_v1_packet(header(1, 1, sequence), 0)
+                };
+
+                (timestamp, source, destination, packet)
+            })
+            .collect()
+    }
+
+    fn read_pcap(data: &[u8]) -> Vec<PcapRecord> {
+        PcapReader::new(data)
+            .unwrap()
+            .map(|record| {
+                let (timestamp, source, destination, packet) = record.unwrap();
+                (timestamp, source, destination, packet.unwrap())
+            })
+            .collect()
+    }
+
+    fn write_pcap(records: &[PcapRecord]) -> Vec<u8> {
+        let mut writer = PcapWriter::new(Vec::new()).unwrap();
+        for record in records {
+            writer.write_record(record).unwrap();
+        }
+
+        writer.into_inner()
+    }
+
+    #[test]
+    fn test_pcap_ipv4_write_read() {
+        let records = create_records(
+            "192.168.2.2:14550".parse().unwrap(),
+            "192.168.2.1:14555".parse().unwrap(),
+        );
+        let pcap = write_pcap(&records);
+
+        assert_eq!(read_pcap(&pcap), records);
+    }
+
+    #[test]
+    fn test_pcap_ipv6_write_read() {
+        let records = create_records(
+            "[fe80::1]:14550".parse().unwrap(),
+            "[fe80::2]:14555".parse().unwrap(),
+        );
+        let pcap = write_pcap(&records);
+
+        assert_eq!(read_pcap(&pcap), records);
+    }
+
+    #[test]
+    fn test_mixed_ip_versions() {
+        let mut writer = PcapWriter::new(Vec::new()).unwrap();
+
+        assert!(writer
+            .write(
+                UNIX_EPOCH,
+                "192.168.2.2:14550".parse().unwrap(),
+                "[fe80::2]:14555".parse().unwrap(),
+                &create_v2_packet(header(1, 1, 0), 0),
+            )
+            .is_err());
+    }
+
+    #[test]
+    fn test_ipv4_header_checksum() {
+        let frame = ethernet_frame(
+            "10.0.0.1:1".parse().unwrap(),
+            "10.0.0.2:2".parse().unwrap(),
+            &[],
+        )
+        .unwrap();
+
+        let ip_header = &frame[ETHERNET_HEADER_SIZE..ETHERNET_HEADER_SIZE + IPV4_HEADER_SIZE];
+        assert_eq!(internet_checksum(&[ip_header]), 0);
+    }
+
+    #[test]
+    fn test_ipv4_short_header() {
+        let mut frame = ethernet_frame(
+            "10.0.0.1:1".parse().unwrap(),
+            "10.0.0.2:2".parse().unwrap(),
+            create_v2_packet(header(1, 1, 0), 0).as_slice(),
+        )
+        .unwrap();
+        assert!(ipv4_udp(&frame[ETHERNET_HEADER_SIZE..]).is_some());
+
+        frame[ETHERNET_HEADER_SIZE] = 0x44; // IHL of 4, 16 bytes
+        assert_eq!(ipv4_udp(&frame[ETHERNET_HEADER_SIZE..]), None);
+    }
+
+    #[test]
+    fn test_multiple_packets_per_datagram() {
+        let source: SocketAddr = "10.0.0.1:14550".parse().unwrap();
+        let destination: SocketAddr = "10.0.0.2:14550".parse().unwrap();
+
+        let first = create_v2_packet(header(1, 1, 0), 0);
+        let second = create_v1_packet(header(1, 1, 1), 0);
+        let mut payload = first.as_slice().to_vec();
+        payload.extend_from_slice(&[0, 1, 2]); // Trash between packets
+        payload.extend_from_slice(second.as_slice());
+
+        let frame = ethernet_frame(source, destination, &payload).unwrap();
+        let mut pcap = write_pcap(&[]);
+        pcap.extend_from_slice(&1u32.to_le_bytes());
+        pcap.extend_from_slice(&0u32.to_le_bytes());
+        pcap.extend_from_slice(&(frame.len() as u32).to_le_bytes());
+        pcap.extend_from_slice(&(frame.len() as u32).to_le_bytes());
+        pcap.extend_from_slice(&frame);
+
+        let decoded = read_pcap(&pcap)
+            .into_iter()
+            .map(|record| record.3)
+            .collect::<Vec<_>>();
+
+        assert_eq!(decoded, vec![first, second]);
+    }
+
+    #[test]
+    fn test_decode_errors() {
+        let mut payload = create_v2_packet(header(1, 1, 0), 0).as_slice().to_vec();
+        *payload.last_mut().unwrap() ^= 0xFF;
+
+        let frame = ethernet_frame(
+            "10.0.0.1:14550".parse().unwrap(),
+            "10.0.0.2:14550".parse().unwrap(),
+            &payload,
+        )
+        .unwrap();
+        let mut pcap = write_pcap(&[]);
+        pcap.extend_from_slice(&[0; 8]);
+        pcap.extend_from_slice(&(frame.len() as u32).to_le_bytes());
+        pcap.extend_from_slice(&(frame.len() as u32).to_le_bytes());
+        pcap.extend_from_slice(&frame);
+
+        let decoded = PcapReader::new(&pcap[..])
+            .unwrap()
+            .map(|record| record.unwrap().3)
+            .collect::<Vec<_>>();
+        assert!(matches!(
+            decoded[..],
+            [Err(DecoderError::InvalidCRC { .. })]
+        ));
+
+        let codec = MavlinkCodec::<true, true, false, false, true, false>::default();
+        let decoded = PcapReader::with_codec(&pcap[..], codec)
+            .unwrap()
+            .map(|record| record.unwrap().3.unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(decoded.len(), 1);
+        assert_eq!(decoded[0].as_slice(), &payload[..]);
+    }
+
+    fn pcapng_block(block_type: u32, body: &[u8]) -> Vec<u8> {
+        let padded_length = body.len().div_ceil(4) * 4;
+        let block_length = (12 + padded_length) as u32;
+
+        let mut block = Vec::new();
+        block.extend_from_slice(&block_type.to_le_bytes());
+        block.extend_from_slice(&block_length.to_le_bytes());
+        block.extend_from_slice(body);
+        block.resize(8 + padded_length, 0);
+        block.extend_from_slice(&block_length.to_le_bytes());
+        block
+    }
+
+    #[test]
+    fn test_pcapng_linux_sll() {
+        let source: SocketAddr = "127.0.0.1:5760".parse().unwrap();
+        let destination: SocketAddr = "127.0.0.1:14550".parse().unwrap();
+        let packet = create_v2_packet(header(1, 1, 7), 0);
+
+        // Reuse the IP layer of an Ethernet frame, replacing its header by a Linux cooked one
+        let ethernet = ethernet_frame(source, destination, packet.as_slice()).unwrap();
+        let mut frame = vec![0, 0, 0, 1, 0, 6, 0, 0, 0, 0, 0, 0, 0, 0];
+        frame.extend_from_slice(&ETHERTYPE_IPV4.to_be_bytes());
+        frame.extend_from_slice(&ethernet[ETHERNET_HEADER_SIZE..]);
+
+        let mut section = Vec::new();
+        section.extend_from_slice(&PCAPNG_BYTE_ORDER_MAGIC.to_le_bytes());
+        section.extend_from_slice(&1u16.to_le_bytes());
+        section.extend_from_slice(&0u16.to_le_bytes());
+        section.extend_from_slice(&(-1i64).to_le_bytes());
+
+        let mut interface = Vec::new();
+        interface.extend_from_slice(&(LinkType::LinuxSll as u16).to_le_bytes());
+        interface.extend_from_slice(&0u16.to_le_bytes());
+        interface.extend_from_slice(&0u32.to_le_bytes());
+        interface.extend_from_slice(&PCAPNG_OPTION_IF_TSRESOL.to_le_bytes());
+        interface.extend_from_slice(&1u16.to_le_bytes());
+        interface.extend_from_slice(&[9, 0, 0, 0]); // Nanoseconds
+        interface.extend_from_slice(&[0, 0, 0, 0]); // End of options
+
+        let units = 1_700_000_000_123_456_789u64;
+        let mut enhanced_packet = Vec::new();
+        enhanced_packet.extend_from_slice(&0u32.to_le_bytes());
+        enhanced_packet.extend_from_slice(&((units >> 32) as u32).to_le_bytes());
+        enhanced_packet.extend_from_slice(&(units as u32).to_le_bytes());
+        enhanced_packet.extend_from_slice(&(frame.len() as u32).to_le_bytes());
+        enhanced_packet.extend_from_slice(&(frame.len() as u32).to_le_bytes());
+        enhanced_packet.extend_from_slice(&frame);
+
+        let mut pcapng = pcapng_block(PCAPNG_SECTION_HEADER_BLOCK, &section);
+        pcapng.extend(pcapng_block(PCAPNG_INTERFACE_DESCRIPTION_BLOCK, &interface));
+        pcapng.extend(pcapng_block(0x0000_0005, &[0; 8])); // Interface statistics, skipped
+        pcapng.extend(pcapng_block(PCAPNG_ENHANCED_PACKET_BLOCK, &enhanced_packet));
+
+        let decoded = read_pcap(&pcapng);
+
+        assert_eq!(decoded.len(), 1);
+        assert_eq!(decoded[0].0, UNIX_EPOCH + Duration::from_nanos(units));
+        assert_eq!(decoded[0].1, source);
+        assert_eq!(decoded[0].2, destination);
+        assert_eq!(decoded[0].3, packet);
+    }
+
+    #[test]
+    fn test_not_a_capture() {
+        assert!(PcapReader::new(&[0u8; 24][..]).is_err());
+    }
+
+    #[test]
+    fn test_oversized_lengths() {
+        let mut pcap = Vec::new();
+        pcap.extend_from_slice(&PCAP_MAGIC_MICROS.to_le_bytes());
+        pcap.extend_from_slice(&[0; 16]);
+        pcap.extend_from_slice(&(LinkType::Ethernet as u32).to_le_bytes());
+        pcap.extend_from_slice(&[0; 8]);
+        pcap.extend_from_slice(&u32::MAX.to_le_bytes());
+        pcap.extend_from_slice(&u32::MAX.to_le_bytes());
+
+        let error = PcapReader::new(&pcap[..])
+            .unwrap()
+            .next()
+            .unwrap()
+            .unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+
+        let section = [
+            &PCAPNG_BYTE_ORDER_MAGIC.to_le_bytes()[..],
+            &[1, 0, 0, 0],
+            &[0xFF; 8],
+        ]
+        .concat();
+        let mut pcapng = pcapng_block(PCAPNG_SECTION_HEADER_BLOCK, &section);
+        pcapng.extend_from_slice(&PCAPNG_ENHANCED_PACKET_BLOCK.to_le_bytes());
+        pcapng.extend_from_slice(&0xFFFF_FFFCu32.to_le_bytes());
+
+        let error = PcapReader::new(&pcapng[..])
+            .unwrap()
+            .next()
+            .unwrap()
+            .unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+
+        let mut section_header = PCAPNG_SECTION_HEADER_BLOCK.to_le_bytes().to_vec();
+        section_header.extend_from_slice(&0xFFFF_FFFCu32.to_le_bytes());
+        section_header.extend_from_slice(&PCAPNG_BYTE_ORDER_MAGIC.to_le_bytes());
+
+        let error = PcapReader::new(&section_header[..]).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+    }
+}
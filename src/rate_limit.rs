@@ -0,0 +1,502 @@
+//! Rate limiting per (System ID, Component ID, Message ID), for low-bandwidth links.
+//!
+//! The limits are checked on header fields only. A packet over its limit is either dropped, or
+//! kept to be sent when its slot opens, replaced by any newer packet of the same source and
+//! message in the meantime.
+
+use std::{
+    collections::{BTreeSet, HashMap},
+    future::Future,
+    pin::Pin,
+    task::{ready, Context, Poll},
+    time::Duration,
+};
+
+use futures::{Sink, SinkExt, Stream, StreamExt};
+use log::trace;
+use tokio::time::{Instant, Sleep};
+
+use crate::Packet;
+
+/// What happens to the packets over their rate limit
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum RateLimitMode {
+    #[default]
+    Drop,
+    /// Keeps the latest packet, to be sent as soon as the rate allows it
+    KeepLatest,
+}
+
+/// The maximum rates, by Message ID or by source and Message ID
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct RateLimits {
+    default: Option<Duration>,
+    messages: HashMap<u32, Duration>,
+    sources: HashMap<(u8, u8, u32), Duration>,
+}
+
+impl RateLimits {
+    /// Limits from a table of (Message ID, maximum rate in Hz)
+    ///
+    /// # Panics
+    ///
+    /// Panics if any rate is not a positive number.
+    pub fn from_table(table: impl IntoIterator<Item = (u32, f64)>) -> Self {
+        table
+            .into_iter()
+            .fold(Self::default(), |limits, (message_id, rate)| {
+                limits.with_message_rate(message_id, rate)
+            })
+    }
+
+    /// # Panics
+    ///
+    /// Panics if `rate` is not a positive number.
+    pub fn with_message_rate(mut self, message_id: u32, rate: f64) -> Self {
+        self.messages.insert(message_id, interval(rate));
+        self
+    }
+
+    /// Overrides the message rate for a single source
+    ///
+    /// # Panics
+    ///
+    /// Panics if `rate` is not a positive number.
+    pub fn with_source_rate(
+        mut self,
+        system_id: u8,
+        component_id: u8,
+        message_id: u32,
+        rate: f64,
+    ) -> Self {
+        self.sources
+            .insert((system_id, component_id, message_id), interval(rate));
+        self
+    }
+
+    /// The rate of the messages without a rate of their own, unlimited by default
+    ///
+    /// # Panics
+    ///
+    /// Panics if `rate` is not a positive number.
+    pub fn with_default_rate(mut self, rate: f64) -> Self {
+        self.default = Some(interval(rate));
+        self
+    }
+
+    /// Minimum time between two packets like this one, or `None` if they are not limited
+    pub fn interval(&self, packet: &Packet) -> Option<Duration> {
+        let message_id = packet.message_id();
+
+        self.sources
+            .get(&(*packet.system_id(), *packet.component_id(), message_id))
+            .or_else(|| self.messages.get(&message_id))
+            .or(self.default.as_ref())
+            .copied()
+    }
+}
+
+fn interval(rate: f64) -> Duration {
+    assert!(rate > 0.0, "rate must be positive, got {rate}");
+
+    Duration::from_secs_f64(1.0 / rate)
+}
+
+type SlotKey = (u8, u8, u32);
+
+#[derive(Debug)]
+struct Slot {
+    next_allowed: Instant,
+    pending: Option<Packet>,
+}
+
+impl Slot {
+    fn is_idle(&self, now: Instant) -> bool {
+        self.pending.is_none() && now >= self.next_allowed
+    }
+}
+
+/// Slots kept before the first pruning of the idle ones
+const MIN_PRUNE_THRESHOLD: usize = 64;
+
+/// Decides which packets go through, shared by [`RateLimitedStream`] and [`RateLimitedSink`]
+#[derive(Debug)]
+pub struct RateLimiter {
+    limits: RateLimits,
+    mode: RateLimitMode,
+    /// By (System ID, Component ID, Message ID)
+    slots: HashMap<SlotKey, Slot>,
+    /// The slots with a kept packet, by when they open
+    due: BTreeSet<(Instant, SlotKey)>,
+    /// Number of slots above which the idle ones are pruned
+    prune_threshold: usize,
+}
+
+impl RateLimiter {
+    pub fn new(limits: RateLimits, mode: RateLimitMode) -> Self {
+        Self {
+            limits,
+            mode,
+            slots: HashMap::new(),
+            due: BTreeSet::new(),
+            prune_threshold: MIN_PRUNE_THRESHOLD,
+        }
+    }
+
+    /// Returns the packet if it can go through now, otherwise drops or keeps it
+    pub fn admit(&mut self, packet: Packet, now: Instant) -> Option<Packet> {
+        let Some(interval) = self.limits.interval(&packet) else {
+            return Some(packet);
+        };
+
+        self.prune(now);
+
+        let key = (
+            *packet.system_id(),
+            *packet.component_id(),
+            packet.message_id(),
+        );
+        let slot = self.slots.entry(key).or_insert(Slot {
+            next_allowed: now,
+            pending: None,
+        });
+
+        if now >= slot.next_allowed {
+            // Anything kept is older than this packet
+            if slot.pending.take().is_some() {
+                self.due.remove(&(slot.next_allowed, key));
+            }
+            slot.next_allowed = now + interval;
+            return Some(packet);
+        }
+
+        match self.mode {
+            RateLimitMode::Drop => trace!("Dropping message {} over its rate", key.2),
+            RateLimitMode::KeepLatest => {
+                if slot.pending.replace(packet).is_none() {
+                    self.due.insert((slot.next_allowed, key));
+                }
+            }
+        }
+
+        None
+    }
+
+    /// A kept packet whose slot is open
+    pub fn take_due(&mut self, now: Instant) -> Option<Packet> {
+        let &(next_allowed, key) = self.due.first()?;
+        if now < next_allowed {
+            return None;
+        }
+        self.due.pop_first();
+
+        let slot = self.slots.get_mut(&key)?;
+        let packet = slot.pending.take()?;
+
+        let interval = self.limits.interval(&packet).unwrap_or_default();
+        slot.next_allowed = now + interval;
+
+        Some(packet)
+    }
+
+    /// When the next kept packet is due
+    pub fn next_deadline(&self) -> Option<Instant> {
+        self.due.first().map(|&(next_allowed, _)| next_allowed)
+    }
+
+    /// Forgets the slots that are open with nothing kept, once their number has doubled since the
+    /// last pruning, so that sources seen once do not accumulate
+    fn prune(&mut self, now: Instant) {
+        if self.slots.len() < self.prune_threshold {
+            return;
+        }
+
+        self.slots.retain(|_, slot| !slot.is_idle(now));
+        self.prune_threshold = (2 * self.slots.len()).max(MIN_PRUNE_THRESHOLD);
+    }
+}
+
+/// Rate limits a packet stream, see the [module documentation](self)
+#[derive(Debug)]
+pub struct RateLimitedStream<S> {
+    stream: S,
+    limiter: RateLimiter,
+    sleep: Pin<Box<Sleep>>,
+    finished: bool,
+}
+
+impl<S: Stream<Item = Packet> + Unpin> RateLimitedStream<S> {
+    pub fn new(stream: S, limits: RateLimits, mode: RateLimitMode) -> Self {
+        Self {
+            stream,
+            limiter: RateLimiter::new(limits, mode),
+            sleep: Box::pin(tokio::time::sleep(Duration::ZERO)),
+            finished: false,
+        }
+    }
+
+    pub fn into_inner(self) -> S {
+        self.stream
+    }
+}
+
+impl<S: Stream<Item = Packet> + Unpin> Stream for RateLimitedStream<S> {
+    type Item = Packet;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = &mut *self;
+
+        loop {
+            let now = Instant::now();
+            if let Some(packet) = this.limiter.take_due(now) {
+                return Poll::Ready(Some(packet));
+            }
+
+            if !this.finished {
+                match this.stream.poll_next_unpin(cx) {
+                    Poll::Ready(Some(packet)) => {
+                        if let Some(packet) = this.limiter.admit(packet, now) {
+                            return Poll::Ready(Some(packet));
+                        }
+                        continue;
+                    }
+                    Poll::Ready(None) => {
+                        this.finished = true;
+                        continue;
+                    }
+                    Poll::Pending => (),
+                }
+            }
+
+            // The kept packets are still sent after the end of the stream
+            let Some(deadline) = this.limiter.next_deadline() else {
+                return if this.finished {
+                    Poll::Ready(None)
+                } else {
+                    Poll::Pending
+                };
+            };
+
+            this.sleep.as_mut().reset(deadline);
+            ready!(this.sleep.as_mut().poll(cx));
+        }
+    }
+}
+
+/// Rate limits the packets sent to a sink, see the [module documentation](self).
+///
+/// Flushing waits for the kept packets to be sent.
+#[derive(Debug)]
+pub struct RateLimitedSink<Si> {
+    sink: Si,
+    limiter: RateLimiter,
+    sleep: Pin<Box<Sleep>>,
+}
+
+impl<Si: Sink<Packet> + Unpin> RateLimitedSink<Si> {
+    pub fn new(sink: Si, limits: RateLimits, mode: RateLimitMode) -> Self {
+        Self {
+            sink,
+            limiter: RateLimiter::new(limits, mode),
+            sleep: Box::pin(tokio::time::sleep(Duration::ZERO)),
+        }
+    }
+
+    pub fn into_inner(self) -> Si {
+        self.sink
+    }
+
+    fn poll_send_due(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Si::Error>> {
+        loop {
+            ready!(self.sink.poll_ready_unpin(cx))?;
+
+            let Some(packet) = self.limiter.take_due(Instant::now()) else {
+                return Poll::Ready(Ok(()));
+            };
+            self.sink.start_send_unpin(packet)?;
+        }
+    }
+}
+
+impl<Si: Sink<Packet> + Unpin> Sink<Packet> for RateLimitedSink<Si> {
+    type Error = Si::Error;
+
+    fn poll_ready(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.poll_send_due(cx)
+    }
+
+    fn start_send(mut self: Pin<&mut Self>, packet: Packet) -> Result<(), Self::Error> {
+        match self.limiter.admit(packet, Instant::now()) {
+            Some(packet) => self.sink.start_send_unpin(packet),
+            None => Ok(()),
+        }
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        let this = &mut *self;
+
+        loop {
+            ready!(this.poll_send_due(cx))?;
+            ready!(this.sink.poll_flush_unpin(cx))?;
+
+            let Some(deadline) = this.limiter.next_deadline() else {
+                return Poll::Ready(Ok(()));
+            };
+
+            this.sleep.as_mut().reset(deadline);
+            ready!(this.sleep.as_mut().poll(cx));
+        }
+    }
+
+    fn poll_close(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        ready!(self.as_mut().poll_flush(cx))?;
+
+        self.sink.poll_close_unpin(cx)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::{Arc, Mutex};
+
+    use super::*;
+    use crate::test_util::{create_v2_packet, header};
+
+    /// ATTITUDE at 200 Hz and HEARTBEAT at 1 Hz, for one second
+    fn telemetry() -> impl Stream<Item = Packet> + Unpin {
+        Box::pin(futures::stream::unfold(0u32, |tick| async move {
+            if tick >= 200 {
+                return None;
+            }
+            if tick > 0 {
+                tokio::time::sleep(Duration::from_millis(5)).await;
+            }
+
+            let packet = match tick % 200 {
+                0 => create_v2_packet(header(1, 1, tick as u8), 0),
+                _ => create_v2_packet(header(1, 1, tick as u8), 30),
+            };
+            Some((packet, tick + 1))
+        }))
+    }
+
+    fn limits() -> RateLimits {
+        RateLimits::from_table([(30, 10.0)])
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_drop() {
+        let packets = RateLimitedStream::new(telemetry(), limits(), RateLimitMode::Drop)
+            .collect::<Vec<_>>()
+            .await;
+
+        let attitudes = packets
+            .iter()
+            .filter(|packet| packet.message_id() == 30)
+            .map(|packet| *packet.sequence())
+            .collect::<Vec<_>>();
+        assert_eq!(attitudes, vec![1, 21, 41, 61, 81, 101, 121, 141, 161, 181]);
+
+        // Unlimited messages go through
+        assert_eq!(packets[0].message_id(), 0);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_keep_latest() {
+        let start = Instant::now();
+        let mut stream = RateLimitedStream::new(telemetry(), limits(), RateLimitMode::KeepLatest);
+
+        let mut attitudes = Vec::new();
+        while let Some(packet) = stream.next().await {
+            if packet.message_id() == 30 {
+                attitudes.push((*packet.sequence(), start.elapsed().as_millis()));
+            }
+        }
+
+        // The packets kept are the latest ones when their slot opens, even after the stream ends
+        assert_eq!(attitudes.len(), 11);
+        assert_eq!(attitudes[0], (1, 5));
+        assert_eq!(attitudes[1], (20, 105));
+        assert_eq!(attitudes[10], (199, 1005));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_source_rate() {
+        let limits = limits().with_source_rate(2, 1, 30, 1.0);
+        let mut limiter = RateLimiter::new(limits, RateLimitMode::Drop);
+        let now = Instant::now();
+
+        assert!(limiter
+            .admit(create_v2_packet(header(2, 1, 0), 30), now)
+            .is_some());
+        assert!(limiter
+            .admit(
+                create_v2_packet(header(2, 1, 1), 30),
+                now + Duration::from_millis(500)
+            )
+            .is_none());
+        // System 1 has a rate of its own
+        assert!(limiter
+            .admit(
+                create_v2_packet(header(1, 1, 0), 30),
+                now + Duration::from_millis(500)
+            )
+            .is_some());
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_idle_slots_pruned() {
+        let mut limiter = RateLimiter::new(limits(), RateLimitMode::KeepLatest);
+        let mut now = Instant::now();
+
+        for system_id in 0..=u8::MAX {
+            assert!(limiter
+                .admit(create_v2_packet(header(system_id, 1, 0), 30), now)
+                .is_some());
+            now += Duration::from_millis(200);
+        }
+        assert!(limiter.slots.len() <= MIN_PRUNE_THRESHOLD);
+
+        // Slots with a kept packet stay until it is sent
+        assert!(limiter
+            .admit(create_v2_packet(header(1, 1, 1), 30), now)
+            .is_some());
+        assert!(limiter
+            .admit(create_v2_packet(header(1, 1, 2), 30), now)
+            .is_none());
+        for system_id in 2..=u8::MAX {
+            assert!(limiter
+                .admit(create_v2_packet(header(system_id, 1, 0), 30), now)
+                .is_some());
+        }
+        assert_eq!(
+            limiter.next_deadline(),
+            Some(now + Duration::from_millis(100))
+        );
+        assert!(limiter.take_due(now).is_none());
+
+        let packet = limiter.take_due(now + Duration::from_millis(100)).unwrap();
+        assert_eq!(*packet.sequence(), 2);
+        assert_eq!(limiter.next_deadline(), None);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_sink() {
+        let sent = Arc::new(Mutex::new(Vec::new()));
+        let sink = futures::sink::unfold(sent.clone(), |sent, packet: Packet| async move {
+            sent.lock().unwrap().push(*packet.sequence());
+            Ok::<_, std::io::Error>(sent)
+        });
+        let mut sink = RateLimitedSink::new(Box::pin(sink), limits(), RateLimitMode::KeepLatest);
+
+        for sequence in 0..5 {
+            sink.feed(create_v2_packet(header(1, 1, sequence), 30))
+                .await
+                .unwrap();
+        }
+        // Waits for the kept packet
+        sink.flush().await.unwrap();
+
+        assert_eq!(*sent.lock().unwrap(), vec![0, 4]);
+    }
+}
@@ -0,0 +1,654 @@
+//! Time-accurate replay of tlog records into any packet [`Sink`].
+
+use std::{
+    collections::{HashSet, VecDeque},
+    io::{self, Read, Seek},
+    sync::Arc,
+    time::{Duration, SystemTime},
+};
+
+use futures::{Sink, SinkExt};
+use log::trace;
+use thiserror::Error;
+use tokio::{sync::watch, time::Instant};
+
+use crate::{tlog::TlogReader, Packet};
+
+/// Records read from the source on each trip to a blocking thread
+const READ_AHEAD: usize = 64;
+
+/// Selects which packets are replayed, by System ID and Message ID.
+///
+/// An empty filter lets every packet through.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ReplayFilter {
+    system_ids: Option<HashSet<u8>>,
+    message_ids: Option<HashSet<u32>>,
+}
+
+impl ReplayFilter {
+    pub fn with_system_ids(mut self, system_ids: impl IntoIterator<Item = u8>) -> Self {
+        self.system_ids = Some(system_ids.into_iter().collect());
+        self
+    }
+
+    pub fn with_message_ids(mut self, message_ids: impl IntoIterator<Item = u32>) -> Self {
+        self.message_ids = Some(message_ids.into_iter().collect());
+        self
+    }
+
+    #[inline(always)]
+    pub fn matches(&self, packet: &Packet) -> bool {
+        let system_id_matches = self
+            .system_ids
+            .as_ref()
+            .is_none_or(|system_ids| system_ids.contains(packet.system_id()));
+        let message_id_matches = self
+            .message_ids
+            .as_ref()
+            .is_none_or(|message_ids| message_ids.contains(&packet.message_id()));
+
+        system_id_matches && message_id_matches
+    }
+}
+
+/// A replay speed that isn't within [`ReplayControl::MIN_SPEED`]..=[`ReplayControl::MAX_SPEED`]
+#[derive(Error, Debug, Clone, Copy, PartialEq)]
+#[error("invalid replay speed: {speed}")]
+pub struct InvalidSpeed {
+    pub speed: f64,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct ControlState {
+    paused: bool,
+    speed: f64,
+    seek_generation: u64,
+    seek_position: Duration,
+}
+
+/// A handle to control a running [`TlogReplayer`] from anywhere.
+#[derive(Debug, Clone)]
+pub struct ReplayControl {
+    sender: Arc<watch::Sender<ControlState>>,
+}
+
+impl ReplayControl {
+    pub const MIN_SPEED: f64 = 1e-6;
+    pub const MAX_SPEED: f64 = 1e6;
+
+    pub fn pause(&self) {
+        self.sender.send_modify(|state| state.paused = true);
+    }
+
+    pub fn resume(&self) {
+        self.sender.send_modify(|state| state.paused = false);
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.sender.borrow().paused
+    }
+
+    /// Changes the speed multiplier of the replay, leaving it unchanged if `speed` is out of
+    /// range or NaN
+    pub fn set_speed(&self, speed: f64) -> Result<(), InvalidSpeed> {
+        if !(Self::MIN_SPEED..=Self::MAX_SPEED).contains(&speed) {
+            return Err(InvalidSpeed { speed });
+        }
+
+        self.sender.send_modify(|state| state.speed = speed);
+
+        Ok(())
+    }
+
+    /// Moves the replay to `position`, relative to the first record of the log
+    pub fn seek(&self, position: Duration) {
+        self.sender.send_modify(|state| {
+            state.seek_generation += 1;
+            state.seek_position = position;
+        });
+    }
+}
+
+/// Where a [`TlogReplayer`] lazily reads its records from.
+///
+/// Reads may block, the replayer does them on [`tokio::task::spawn_blocking`] threads.
+pub trait ReplaySource {
+    /// The next record, or `None` at the end of the log
+    fn next_record(&mut self) -> io::Result<Option<(SystemTime, Packet)>>;
+
+    /// Goes back to the first record, to seek backwards
+    fn rewind(&mut self) -> io::Result<()>;
+}
+
+impl<R: Read + Seek> ReplaySource for TlogReader<R> {
+    fn next_record(&mut self) -> io::Result<Option<(SystemTime, Packet)>> {
+        self.next().transpose()
+    }
+
+    fn rewind(&mut self) -> io::Result<()> {
+        TlogReader::rewind(self)
+    }
+}
+
+/// Records from an iterator, rewound by starting over from a clone of it.
+#[derive(Debug, Clone)]
+pub struct IterSource<I> {
+    start: I,
+    current: I,
+}
+
+impl<I: Clone> IterSource<I> {
+    pub fn new(records: I) -> Self {
+        Self {
+            start: records.clone(),
+            current: records,
+        }
+    }
+}
+
+impl<I> ReplaySource for IterSource<I>
+where
+    I: Iterator<Item = (SystemTime, Packet)> + Clone,
+{
+    fn next_record(&mut self) -> io::Result<Option<(SystemTime, Packet)>> {
+        Ok(self.current.next())
+    }
+
+    fn rewind(&mut self) -> io::Result<()> {
+        self.current = self.start.clone();
+        Ok(())
+    }
+}
+
+/// Replays tlog records into a [`Sink`], preserving the original inter-packet timing.
+///
+/// Records are read from their [`ReplaySource`] as the replay goes, a few at a time and never all
+/// at once.
+///
+/// The replay is driven by [`tokio::time`], so it can be made deterministic in tests with
+/// [`tokio::time::pause`].
+#[derive(Debug)]
+pub struct TlogReplayer<S> {
+    source: S,
+    filter: ReplayFilter,
+    control: ReplayControl,
+    receiver: watch::Receiver<ControlState>,
+}
+
+impl<I> TlogReplayer<IterSource<I>>
+where
+    I: Iterator<Item = (SystemTime, Packet)> + Clone,
+{
+    pub fn new(records: impl IntoIterator<IntoIter = I>) -> Self {
+        Self::from_source(IterSource::new(records.into_iter()))
+    }
+}
+
+impl<R: Read + Seek> TlogReplayer<TlogReader<R>> {
+    /// Seeking backwards rewinds `reader` and reads it again up to the new position.
+    pub fn from_reader(reader: TlogReader<R>) -> Self {
+        Self::from_source(reader)
+    }
+}
+
+impl<S: ReplaySource> TlogReplayer<S> {
+    pub fn from_source(source: S) -> Self {
+        let (sender, receiver) = watch::channel(ControlState {
+            paused: false,
+            speed: 1.0,
+            seek_generation: 0,
+            seek_position: Duration::ZERO,
+        });
+
+        Self {
+            source,
+            filter: ReplayFilter::default(),
+            control: ReplayControl {
+                sender: Arc::new(sender),
+            },
+            receiver,
+        }
+    }
+
+    /// See [`ReplayControl::set_speed`]
+    pub fn with_speed(self, speed: f64) -> Result<Self, InvalidSpeed> {
+        self.control.set_speed(speed)?;
+        Ok(self)
+    }
+
+    pub fn with_filter(mut self, filter: ReplayFilter) -> Self {
+        self.filter = filter;
+        self
+    }
+
+    pub fn control(&self) -> ReplayControl {
+        self.control.clone()
+    }
+
+    /// Replays all the records into `sink`, returning the number of packets sent
+    pub async fn replay<Si>(self, sink: Si) -> io::Result<usize>
+    where
+        S: Send + 'static,
+        Si: Sink<Packet>,
+        Si::Error: Into<Box<dyn std::error::Error + Send + Sync>>,
+    {
+        let Self {
+            source,
+            filter,
+            control,
+            mut receiver,
+        } = self;
+        let mut source = BlockingReader::new(source);
+        // Only the handles given away can control the replay from now on
+        drop(control);
+
+        let mut sink = std::pin::pin!(sink.sink_map_err(io::Error::other));
+
+        let mut state = receiver.borrow_and_update().clone();
+        let mut controlled = true;
+
+        let mut next = source.next_record().await?;
+        let start = next.as_ref().map(|(timestamp, _)| *timestamp);
+
+        let mut sent = 0;
+        let mut anchor_instant = Instant::now();
+        let mut anchor_position = Duration::ZERO;
+
+        while let Some((timestamp, packet)) = &next {
+            let position = offset_of(start, timestamp);
+            let deadline = anchor_instant
+                + position
+                    .saturating_sub(anchor_position)
+                    .div_f64(state.speed);
+
+            tokio::select! {
+                _ = tokio::time::sleep_until(deadline), if !state.paused => {
+                    if filter.matches(packet) {
+                        sink.send(packet.clone()).await?;
+                        sent += 1;
+                    }
+
+                    next = source.next_record().await?;
+                }
+                changed = receiver.changed(), if controlled => {
+                    let now = Instant::now();
+
+                    if changed.is_err() {
+                        trace!("All replay controls dropped, replaying until the end");
+                        controlled = false;
+                        if state.paused {
+                            state.paused = false;
+                            anchor_instant = now;
+                        }
+                        continue;
+                    }
+
+                    let new_state = receiver.borrow_and_update().clone();
+
+                    // Re-anchor the timeline at the current log position
+                    if !state.paused {
+                        anchor_position = (anchor_position
+                            + (now - anchor_instant).mul_f64(state.speed))
+                        .min(position);
+                    }
+                    anchor_instant = now;
+
+                    if new_state.seek_generation != state.seek_generation {
+                        trace!("Seeking to {:?}", new_state.seek_position);
+                        anchor_position = new_state.seek_position;
+
+                        if new_state.seek_position < position {
+                            source.rewind().await?;
+                            next = source.next_record().await?;
+                        }
+                        while let Some((timestamp, _)) = &next {
+                            if offset_of(start, timestamp) >= new_state.seek_position {
+                                break;
+                            }
+                            next = source.next_record().await?;
+                        }
+                    }
+
+                    state = new_state;
+                }
+            }
+        }
+
+        sink.flush().await?;
+
+        Ok(sent)
+    }
+}
+
+/// Reads a [`ReplaySource`] ahead, on blocking threads
+struct BlockingReader<S> {
+    /// Only `None` while on a blocking thread
+    source: Option<S>,
+    records: VecDeque<(SystemTime, Packet)>,
+    /// Met after the buffered records
+    error: Option<io::Error>,
+    eof: bool,
+}
+
+impl<S: ReplaySource + Send + 'static> BlockingReader<S> {
+    fn new(source: S) -> Self {
+        Self {
+            source: Some(source),
+            records: VecDeque::new(),
+            error: None,
+            eof: false,
+        }
+    }
+
+    async fn next_record(&mut self) -> io::Result<Option<(SystemTime, Packet)>> {
+        if self.records.is_empty() {
+            if let Some(error) = self.error.take() {
+                return Err(error);
+            }
+            if !self.eof {
+                self.read_ahead(false).await?;
+            }
+        }
+
+        match self.records.pop_front() {
+            Some(record) => Ok(Some(record)),
+            None => self.error.take().map_or(Ok(None), Err),
+        }
+    }
+
+    async fn rewind(&mut self) -> io::Result<()> {
+        self.records.clear();
+        self.error = None;
+        self.eof = false;
+
+        self.read_ahead(true).await
+    }
+
+    async fn read_ahead(&mut self, rewind: bool) -> io::Result<()> {
+        let Some(mut source) = self.source.take() else {
+            return Err(io::Error::other("the replay source was lost"));
+        };
+
+        let (source, records, result) = tokio::task::spawn_blocking(move || {
+            let mut records = VecDeque::with_capacity(READ_AHEAD);
+            if rewind {
+                if let Err(error) = source.rewind() {
+                    return (source, records, Err(error));
+                }
+            }
+
+            while records.len() < READ_AHEAD {
+                match source.next_record() {
+                    Ok(Some(record)) => records.push_back(record),
+                    Ok(None) => return (source, records, Ok(false)),
+                    Err(error) => return (source, records, Err(error)),
+                }
+            }
+
+            (source, records, Ok(true))
+        })
+        .await
+        .map_err(io::Error::other)?;
+
+        self.source = Some(source);
+        self.records.extend(records);
+        match result {
+            Ok(more) => self.eof = !more,
+            Err(error) => self.error = Some(error),
+        }
+
+        Ok(())
+    }
+}
+
+#[inline(always)]
+fn offset_of(start: Option<SystemTime>, timestamp: &SystemTime) -> Duration {
+    let Some(start) = start else {
+        return Duration::ZERO;
+    };
+
+    timestamp.duration_since(start).unwrap_or_default()
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::Mutex;
+
+    use super::*;
+    use crate::test_util::{create_v2_packet, header};
+
+    fn create_record(offset_ms: u64, system_id: u8, message_id: u32) -> (SystemTime, Packet) {
+        let timestamp = SystemTime::UNIX_EPOCH
+            + Duration::from_secs(1_700_000_000)
+            + Duration::from_millis(offset_ms);
+
+        (
+            timestamp,
+            create_v2_packet(header(system_id, 1, 0), message_id),
+        )
+    }
+
+    type Arrivals = Arc<Mutex<Vec<(Duration, Packet)>>>;
+
+    fn recording_sink() -> (impl Sink<Packet, Error = io::Error>, Arrivals) {
+        let start = Instant::now();
+        let arrivals = Arrivals::default();
+
+        let sink = futures::sink::unfold(arrivals.clone(), move |arrivals, packet| async move {
+            arrivals.lock().unwrap().push((start.elapsed(), packet));
+            Ok::<_, io::Error>(arrivals)
+        });
+
+        (sink, arrivals)
+    }
+
+    fn arrival_times(arrivals: &Arrivals) -> Vec<u128> {
+        arrivals
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|(elapsed, _)| elapsed.as_millis())
+            .collect()
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_original_timing() {
+        let records = vec![
+            create_record(0, 1, 0),
+            create_record(100, 1, 0),
+            create_record(350, 1, 0),
+            create_record(1000, 1, 0),
+        ];
+        let (sink, arrivals) = recording_sink();
+
+        let sent = TlogReplayer::new(records.clone())
+            .replay(sink)
+            .await
+            .unwrap();
+
+        assert_eq!(sent, 4);
+        assert_eq!(arrival_times(&arrivals), vec![0, 100, 350, 1000]);
+        assert!(arrivals
+            .lock()
+            .unwrap()
+            .iter()
+            .zip(records)
+            .all(|((_, received), (_, expected))| *received == expected));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_speed() {
+        let records = vec![
+            create_record(0, 1, 0),
+            create_record(100, 1, 0),
+            create_record(400, 1, 0),
+        ];
+        let (sink, arrivals) = recording_sink();
+
+        TlogReplayer::new(records)
+            .with_speed(4.0)
+            .unwrap()
+            .replay(sink)
+            .await
+            .unwrap();
+
+        assert_eq!(arrival_times(&arrivals), vec![0, 25, 100]);
+    }
+
+    #[test]
+    fn test_invalid_speed() {
+        let replayer = TlogReplayer::new(vec![create_record(0, 1, 0)]);
+        let control = replayer.control();
+
+        for speed in [0.0, -1.0, f64::NAN, f64::INFINITY, 1e-9, 1e9] {
+            assert!(control.set_speed(speed).is_err(), "{speed}");
+        }
+        assert_eq!(control.set_speed(0.5), Ok(()));
+        assert_eq!(control.sender.borrow().speed, 0.5);
+
+        assert!(matches!(
+            replayer.with_speed(f64::NAN),
+            Err(InvalidSpeed { speed }) if speed.is_nan()
+        ));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_filter() {
+        let records = vec![
+            create_record(0, 1, 0),
+            create_record(100, 2, 0),
+            create_record(200, 1, 30),
+            create_record(300, 1, 0),
+        ];
+        let (sink, arrivals) = recording_sink();
+
+        let sent = TlogReplayer::new(records)
+            .with_filter(
+                ReplayFilter::default()
+                    .with_system_ids([1])
+                    .with_message_ids([0]),
+            )
+            .replay(sink)
+            .await
+            .unwrap();
+
+        assert_eq!(sent, 2);
+        assert_eq!(arrival_times(&arrivals), vec![0, 300]);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_pause_resume() {
+        let records = vec![
+            create_record(0, 1, 0),
+            create_record(100, 1, 0),
+            create_record(200, 1, 0),
+        ];
+        let (sink, arrivals) = recording_sink();
+
+        let replayer = TlogReplayer::new(records);
+        let control = replayer.control();
+        let replay_task = tokio::spawn(replayer.replay(sink));
+
+        tokio::time::sleep(Duration::from_millis(150)).await;
+        control.pause();
+        assert!(control.is_paused());
+        tokio::time::sleep(Duration::from_millis(1000)).await;
+        control.resume();
+
+        assert_eq!(replay_task.await.unwrap().unwrap(), 3);
+        assert_eq!(arrival_times(&arrivals), vec![0, 100, 1200]);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_seek() {
+        let records = vec![
+            create_record(0, 1, 0),
+            create_record(100, 1, 0),
+            create_record(200, 1, 0),
+            create_record(300, 1, 0),
+            create_record(400, 1, 0),
+        ];
+        let (sink, arrivals) = recording_sink();
+
+        let replayer = TlogReplayer::new(records);
+        let control = replayer.control();
+        let replay_task = tokio::spawn(replayer.replay(sink));
+
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        control.seek(Duration::from_millis(300));
+
+        assert_eq!(replay_task.await.unwrap().unwrap(), 3);
+        assert_eq!(arrival_times(&arrivals), vec![0, 50, 150]);
+    }
+
+    /// Fails after its records, noting the threads it is read from
+    struct FailingSource {
+        records: Vec<(SystemTime, Packet)>,
+        threads: Arc<Mutex<Vec<std::thread::ThreadId>>>,
+    }
+
+    impl ReplaySource for FailingSource {
+        fn next_record(&mut self) -> io::Result<Option<(SystemTime, Packet)>> {
+            self.threads
+                .lock()
+                .unwrap()
+                .push(std::thread::current().id());
+            if self.records.is_empty() {
+                return Err(io::Error::new(io::ErrorKind::InvalidData, "corrupted"));
+            }
+
+            Ok(Some(self.records.remove(0)))
+        }
+
+        fn rewind(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_blocking_source() {
+        let threads = Arc::new(Mutex::new(Vec::new()));
+        let source = FailingSource {
+            records: vec![create_record(0, 1, 0), create_record(100, 1, 0)],
+            threads: threads.clone(),
+        };
+        let (sink, arrivals) = recording_sink();
+
+        let error = TlogReplayer::from_source(source)
+            .replay(sink)
+            .await
+            .unwrap_err();
+
+        // The records before the error are replayed
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+        assert_eq!(arrival_times(&arrivals), vec![0, 100]);
+        assert!(!threads.lock().unwrap().is_empty());
+        assert!(threads
+            .lock()
+            .unwrap()
+            .iter()
+            .all(|thread| *thread != std::thread::current().id()));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_seek_backwards_in_reader() {
+        let mut writer = crate::tlog::TlogWriter::new(Vec::new());
+        for offset_ms in [0, 100, 200, 300] {
+            let (timestamp, packet) = create_record(offset_ms, 1, 0);
+            writer.write(timestamp, &packet).unwrap();
+        }
+        let reader = TlogReader::new(io::Cursor::new(writer.into_inner()));
+        let (sink, arrivals) = recording_sink();
+
+        let replayer = TlogReplayer::from_reader(reader);
+        let control = replayer.control();
+        let replay_task = tokio::spawn(replayer.replay(sink));
+
+        tokio::time::sleep(Duration::from_millis(250)).await;
+        control.seek(Duration::from_millis(100));
+
+        assert_eq!(replay_task.await.unwrap().unwrap(), 6);
+        assert_eq!(arrival_times(&arrivals), vec![0, 100, 200, 250, 350, 450]);
+    }
+}
@@ -0,0 +1,657 @@
+//! Routing of packets between endpoints, in the way of mavlink-router.
+//!
+//! The [`Router`] learns which systems and components are behind each endpoint from the packets
+//! they send. Targeted messages are only forwarded to the endpoints behind which their target
+//! was seen, and everything else is broadcast. Packets are never echoed back to the endpoint
+//! they came from. Routes expire when their system or component stops sending packets.
+
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    io,
+    pin::Pin,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use futures::{
+    channel::mpsc,
+    future::{self, join_all},
+    stream::{BoxStream, SelectAll},
+    Sink, SinkExt, Stream, StreamExt,
+};
+use log::{trace, warn};
+use tokio::time::Instant;
+
+use crate::{
+    dedup::Deduplicator,
+    dialect::{ArduPilotMega, Dialect},
+    error::DecoderError,
+    target::{target_matches, BROADCAST},
+    Packet,
+};
+
+type PacketPredicate = Arc<dyn Fn(&Packet) -> bool + Send + Sync>;
+
+type BoxSink = Pin<Box<dyn Sink<Packet, Error = io::Error> + Send>>;
+
+/// What a [`MavlinkCodec`](crate::codec::MavlinkCodec) decodes
+type DecodedItem = io::Result<Result<Packet, DecoderError>>;
+
+/// A link of the [`Router`], like a serial port, a UDP socket or a TCP connection
+pub struct Endpoint {
+    name: String,
+    stream: BoxStream<'static, Result<Packet, DecoderError>>,
+    sink: BoxSink,
+    inbound_filter: Option<PacketPredicate>,
+    outbound_filter: Option<PacketPredicate>,
+}
+
+impl std::fmt::Debug for Endpoint {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Endpoint")
+            .field("name", &self.name)
+            .finish_non_exhaustive()
+    }
+}
+
+impl Endpoint {
+    /// An endpoint over a transport framed with a [`MavlinkCodec`](crate::codec::MavlinkCodec),
+    /// like a [`Framed`](tokio_util::codec::Framed).
+    ///
+    /// Decoding errors are counted in the [`EndpointStatistics`], and the endpoint stops
+    /// receiving on the first I/O error.
+    pub fn new<T>(name: impl Into<String>, transport: T) -> Self
+    where
+        T: Stream<Item = DecodedItem> + Sink<Packet> + Send + 'static,
+        T::Error: Into<Box<dyn std::error::Error + Send + Sync>>,
+    {
+        let (sink, stream) = transport.split();
+
+        Self::from_split(name, stream, sink)
+    }
+
+    /// An endpoint with separate receiving and sending halves
+    pub fn from_split<St, Si>(name: impl Into<String>, stream: St, sink: Si) -> Self
+    where
+        St: Stream<Item = DecodedItem> + Send + 'static,
+        Si: Sink<Packet> + Send + 'static,
+        Si::Error: Into<Box<dyn std::error::Error + Send + Sync>>,
+    {
+        let name = name.into();
+
+        let endpoint_name = name.clone();
+        let stream = stream.scan((), move |_, item| {
+            if let Err(error) = &item {
+                warn!("Endpoint {endpoint_name:?} failed, no longer receiving from it: {error}");
+            }
+            future::ready(item.ok())
+        });
+
+        Self {
+            name,
+            stream: stream.boxed(),
+            sink: Box::pin(sink.sink_map_err(io::Error::other)),
+            inbound_filter: None,
+            outbound_filter: None,
+        }
+    }
+
+    /// Only the packets matching `filter` are accepted from this endpoint
+    pub fn with_inbound_filter(
+        mut self,
+        filter: impl Fn(&Packet) -> bool + Send + Sync + 'static,
+    ) -> Self {
+        self.inbound_filter = Some(Arc::new(filter));
+        self
+    }
+
+    /// Only the packets matching `filter` are sent to this endpoint
+    pub fn with_outbound_filter(
+        mut self,
+        filter: impl Fn(&Packet) -> bool + Send + Sync + 'static,
+    ) -> Self {
+        self.outbound_filter = Some(Arc::new(filter));
+        self
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+}
+
+/// Counters of a single endpoint
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct EndpointStatistics {
+    pub name: String,
+    /// Packets received from the endpoint
+    pub received: u64,
+    /// Packets queued to be sent to the endpoint
+    pub forwarded: u64,
+    /// Received frames that failed to decode
+    pub decode_errors: u64,
+    /// Received packets rejected by the inbound filter
+    pub filtered_inbound: u64,
+    /// Packets to be forwarded that were rejected by the outbound filter
+    pub filtered_outbound: u64,
+    /// Received packets already seen within the deduplication window
+    pub duplicates: u64,
+    /// Received packets targeting a system not seen behind any other endpoint
+    pub unroutable: u64,
+    /// Packets to be forwarded that were dropped as the endpoint queue was full or closed
+    pub dropped: u64,
+}
+
+#[derive(Debug)]
+struct RoutingState {
+    endpoints: Vec<EndpointStatistics>,
+    /// The endpoints behind which each (System ID, Component ID) was seen, and when
+    routes: BTreeMap<(u8, u8), BTreeMap<usize, Instant>>,
+    route_timeout: Duration,
+}
+
+impl Default for RoutingState {
+    fn default() -> Self {
+        Self {
+            endpoints: Vec::new(),
+            routes: BTreeMap::new(),
+            route_timeout: Router::DEFAULT_ROUTE_TIMEOUT,
+        }
+    }
+}
+
+impl RoutingState {
+    /// Forgets the routes not seen within the route timeout
+    fn expire_routes(&mut self, now: Instant) {
+        let route_timeout = self.route_timeout;
+
+        self.routes.retain(|source, endpoints| {
+            endpoints.retain(|endpoint, seen| {
+                let alive = now.duration_since(*seen) < route_timeout;
+                if !alive {
+                    trace!("Route to {source:?} through endpoint {endpoint} expired");
+                }
+                alive
+            });
+            !endpoints.is_empty()
+        });
+    }
+}
+
+/// A handle to the statistics of a [`Router`], usable while it runs
+#[derive(Debug, Clone, Default)]
+pub struct RouterStats {
+    state: Arc<Mutex<RoutingState>>,
+}
+
+impl RouterStats {
+    pub fn endpoints(&self) -> Vec<EndpointStatistics> {
+        self.state.lock().unwrap().endpoints.clone()
+    }
+
+    pub fn endpoint(&self, id: usize) -> Option<EndpointStatistics> {
+        self.state.lock().unwrap().endpoints.get(id).cloned()
+    }
+
+    /// The endpoints behind which each (System ID, Component ID) was seen, within the route
+    /// timeout
+    pub fn routes(&self) -> BTreeMap<(u8, u8), Vec<usize>> {
+        let mut state = self.state.lock().unwrap();
+        state.expire_routes(Instant::now());
+
+        state
+            .routes
+            .iter()
+            .map(|(source, endpoints)| (*source, endpoints.keys().copied().collect()))
+            .collect()
+    }
+}
+
+/// Routes packets between [`Endpoint`]s, see the [module documentation](self).
+///
+/// Each endpoint has its own queue, so a slow endpoint only drops its own packets instead of
+/// stalling the others.
+#[derive(Debug)]
+pub struct Router<D: Dialect = ArduPilotMega> {
+    dialect: D,
+    endpoints: Vec<Endpoint>,
+    dedup_window: Option<Duration>,
+    queue_size: usize,
+    stats: RouterStats,
+}
+
+impl Default for Router {
+    fn default() -> Self {
+        Self::new(ArduPilotMega)
+    }
+}
+
+impl Router {
+    /// The same as the heartbeat timeout of a
+    /// [`HeartbeatMonitor`](crate::heartbeat::HeartbeatMonitor)
+    pub const DEFAULT_ROUTE_TIMEOUT: Duration = Duration::from_secs(5);
+}
+
+impl<D: Dialect> Router<D> {
+    pub const DEFAULT_QUEUE_SIZE: usize = 128;
+
+    pub fn new(dialect: D) -> Self {
+        Self {
+            dialect,
+            endpoints: Vec::new(),
+            dedup_window: None,
+            queue_size: Self::DEFAULT_QUEUE_SIZE,
+            stats: RouterStats::default(),
+        }
+    }
+
+    /// Drops the packets already received, from any endpoint, within `window`, see
+    /// [`Deduplicator`]
+    pub fn with_dedup_window(mut self, window: Duration) -> Self {
+        self.dedup_window = Some(window);
+        self
+    }
+
+    /// Forgets the route to a system or component that sent nothing within `timeout`
+    pub fn with_route_timeout(self, timeout: Duration) -> Self {
+        self.stats.state.lock().unwrap().route_timeout = timeout;
+        self
+    }
+
+    /// Number of packets waiting to be sent to each endpoint before they are dropped
+    pub fn with_queue_size(mut self, queue_size: usize) -> Self {
+        self.queue_size = queue_size;
+        self
+    }
+
+    /// Adds an endpoint, returning its ID in the statistics
+    pub fn add_endpoint(&mut self, endpoint: Endpoint) -> usize {
+        self.stats
+            .state
+            .lock()
+            .unwrap()
+            .endpoints
+            .push(EndpointStatistics {
+                name: endpoint.name.clone(),
+                ..Default::default()
+            });
+        self.endpoints.push(endpoint);
+
+        self.endpoints.len() - 1
+    }
+
+    pub fn stats(&self) -> RouterStats {
+        self.stats.clone()
+    }
+
+    /// Routes packets until every endpoint stream ends
+    pub async fn run(self) {
+        let Self {
+            dialect,
+            endpoints,
+            dedup_window,
+            queue_size,
+            stats,
+        } = self;
+
+        let mut streams = SelectAll::new();
+        let mut queues = Vec::with_capacity(endpoints.len());
+        let mut outbound_filters = Vec::with_capacity(endpoints.len());
+        let mut inbound_filters = Vec::with_capacity(endpoints.len());
+        let mut writers = Vec::with_capacity(endpoints.len());
+
+        for (id, endpoint) in endpoints.into_iter().enumerate() {
+            let Endpoint {
+                name,
+                stream,
+                sink,
+                inbound_filter,
+                outbound_filter,
+            } = endpoint;
+
+            let (queue, receiver) = mpsc::channel(queue_size);
+            writers.push(async move {
+                if let Err(error) = receiver.map(Ok).forward(sink).await {
+                    warn!("Endpoint {name:?} failed, no longer sending to it: {error}");
+                }
+            });
+
+            streams.push(stream.map(move |packet| (id, packet)));
+            queues.push(queue);
+            inbound_filters.push(inbound_filter);
+            outbound_filters.push(outbound_filter);
+        }
+
+        let mut deduplicator = dedup_window.map(Deduplicator::new);
+
+        let routing = async move {
+            while let Some((source, decoded)) = streams.next().await {
+                let mut state = stats.state.lock().unwrap();
+                let state = &mut *state;
+
+                let packet = match decoded {
+                    Ok(packet) => packet,
+                    Err(error) => {
+                        trace!("Decoding error from endpoint {source}: {error}");
+                        state.endpoints[source].decode_errors += 1;
+                        continue;
+                    }
+                };
+                state.endpoints[source].received += 1;
+
+                if inbound_filters[source]
+                    .as_ref()
+                    .is_some_and(|filter| !filter(&packet))
+                {
+                    state.endpoints[source].filtered_inbound += 1;
+                    continue;
+                }
+
+                // The sender is reachable through this endpoint, even if the packet is repeated
+                let now = Instant::now();
+                state.expire_routes(now);
+                state
+                    .routes
+                    .entry((*packet.system_id(), *packet.component_id()))
+                    .or_default()
+                    .insert(source, now);
+
+                if let Some(deduplicator) = &mut deduplicator {
+                    if !deduplicator.is_new(&packet) {
+                        state.endpoints[source].duplicates += 1;
+                        continue;
+                    }
+                }
+
+                let destinations = match packet.target(&dialect) {
+                    Some(target) if target.0 != BROADCAST => {
+                        let destinations = targeted_destinations(&state.routes, source, target);
+                        if destinations.is_empty() {
+                            trace!("No route to {target:?}, dropping the packet");
+                            state.endpoints[source].unroutable += 1;
+                        }
+                        destinations
+                    }
+                    _ => (0..queues.len()).filter(|id| *id != source).collect(),
+                };
+
+                for destination in destinations {
+                    if outbound_filters[destination]
+                        .as_ref()
+                        .is_some_and(|filter| !filter(&packet))
+                    {
+                        state.endpoints[destination].filtered_outbound += 1;
+                        continue;
+                    }
+
+                    match queues[destination].try_send(packet.clone()) {
+                        Ok(()) => state.endpoints[destination].forwarded += 1,
+                        Err(_) => state.endpoints[destination].dropped += 1,
+                    }
+                }
+            }
+
+            // Closing the queues lets the writers finish
+            drop(queues);
+        };
+
+        futures::future::join(routing, join_all(writers)).await;
+    }
+}
+
+/// The endpoints behind which the target was seen, other than the source.
+///
+/// Falls back to the endpoints behind which the target system was seen, as not every
+/// component of a system sends packets.
+fn targeted_destinations(
+    routes: &BTreeMap<(u8, u8), BTreeMap<usize, Instant>>,
+    source: usize,
+    target: (u8, u8),
+) -> BTreeSet<usize> {
+    let (target_system, _) = target;
+
+    let collect = |matches: &dyn Fn(u8, u8) -> bool| {
+        routes
+            .iter()
+            .filter(|((system_id, component_id), _)| matches(*system_id, *component_id))
+            .flat_map(|(_, endpoints)| endpoints.keys().copied())
+            .collect::<BTreeSet<_>>()
+    };
+
+    let mut endpoints =
+        collect(&|system_id, component_id| target_matches(target, system_id, component_id));
+    if endpoints.is_empty() {
+        endpoints = collect(&|system_id, _| system_id == target_system);
+    }
+
+    // Never echo back to the source
+    endpoints.remove(&source);
+
+    endpoints
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{
+        codec::MavlinkCodec,
+        test_util::{create_raw_packet, header},
+    };
+    use tokio::io::{AsyncWriteExt, DuplexStream};
+    use tokio_util::codec::Framed;
+
+    type Codec = MavlinkCodec<true, true, false, false, false, false>;
+    type Client = Framed<DuplexStream, Codec>;
+
+    fn heartbeat(system_id: u8, component_id: u8) -> Packet {
+        create_raw_packet(
+            header(system_id, component_id, 0),
+            0,
+            &[0, 0, 0, 0, 6, 8, 0, 0, 3],
+        )
+    }
+
+    fn command_long(system_id: u8, target_system: u8, target_component: u8) -> Packet {
+        let mut payload = [0u8; 33];
+        payload[28..30].copy_from_slice(&400u16.to_le_bytes()); // MAV_CMD_COMPONENT_ARM_DISARM
+        payload[30] = target_system;
+        payload[31] = target_component;
+
+        create_raw_packet(header(system_id, 190, 0), 76, &payload)
+    }
+
+    /// An endpoint over an in-memory duplex stream, and the client side of it
+    fn duplex_endpoint(name: &str) -> (Endpoint, Client) {
+        let (router_side, client_side) = tokio::io::duplex(4096);
+
+        (
+            Endpoint::new(name, Framed::new(router_side, Codec::default())),
+            Framed::new(client_side, Codec::default()),
+        )
+    }
+
+    async fn receive(client: &mut Client) -> Option<Packet> {
+        tokio::time::timeout(Duration::from_millis(100), client.next())
+            .await
+            .ok()??
+            .ok()?
+            .ok()
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_broadcast_without_echo() {
+        let mut router = Router::default();
+        let (endpoint_a, mut client_a) = duplex_endpoint("a");
+        let (endpoint_b, mut client_b) = duplex_endpoint("b");
+        let (endpoint_c, mut client_c) = duplex_endpoint("c");
+        router.add_endpoint(endpoint_a);
+        router.add_endpoint(endpoint_b);
+        router.add_endpoint(endpoint_c);
+        let stats = router.stats();
+        let router_task = tokio::spawn(router.run());
+
+        let packet = heartbeat(1, 1);
+        client_a.send(packet.clone()).await.unwrap();
+
+        assert_eq!(receive(&mut client_b).await, Some(packet.clone()));
+        assert_eq!(receive(&mut client_c).await, Some(packet));
+        assert_eq!(receive(&mut client_a).await, None);
+
+        drop((client_a, client_b, client_c));
+        router_task.await.unwrap();
+
+        let endpoints = stats.endpoints();
+        assert_eq!(endpoints[0].received, 1);
+        assert_eq!(endpoints[0].forwarded, 0);
+        assert_eq!(endpoints[1].forwarded, 1);
+        assert_eq!(endpoints[2].forwarded, 1);
+        assert_eq!(stats.routes()[&(1, 1)], vec![0]);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_targeted_routing() {
+        let mut router = Router::default();
+        let (gcs, mut gcs_client) = duplex_endpoint("gcs");
+        let (vehicle_1, mut vehicle_1_client) = duplex_endpoint("vehicle 1");
+        let (vehicle_2, mut vehicle_2_client) = duplex_endpoint("vehicle 2");
+        router.add_endpoint(gcs);
+        router.add_endpoint(vehicle_1);
+        router.add_endpoint(vehicle_2);
+        let stats = router.stats();
+        let router_task = tokio::spawn(router.run());
+
+        // The vehicles announce themselves
+        vehicle_1_client.send(heartbeat(1, 1)).await.unwrap();
+        vehicle_2_client.send(heartbeat(2, 1)).await.unwrap();
+        assert!(receive(&mut gcs_client).await.is_some());
+        assert!(receive(&mut gcs_client).await.is_some());
+        assert!(receive(&mut vehicle_2_client).await.is_some());
+        assert!(receive(&mut vehicle_1_client).await.is_some());
+
+        // Only the targeted vehicle gets the command
+        let command = command_long(255, 2, 1);
+        gcs_client.send(command.clone()).await.unwrap();
+        assert_eq!(receive(&mut vehicle_2_client).await, Some(command));
+        assert_eq!(receive(&mut vehicle_1_client).await, None);
+
+        // An unknown component of a known system is reached through the system
+        let command = command_long(255, 1, 100);
+        gcs_client.send(command.clone()).await.unwrap();
+        assert_eq!(receive(&mut vehicle_1_client).await, Some(command));
+        assert_eq!(receive(&mut vehicle_2_client).await, None);
+
+        // A broadcast command goes everywhere else
+        let command = command_long(255, BROADCAST, BROADCAST);
+        gcs_client.send(command.clone()).await.unwrap();
+        assert_eq!(receive(&mut vehicle_1_client).await, Some(command.clone()));
+        assert_eq!(receive(&mut vehicle_2_client).await, Some(command));
+
+        // Nobody has seen system 3
+        gcs_client.send(command_long(255, 3, 1)).await.unwrap();
+        assert_eq!(receive(&mut vehicle_1_client).await, None);
+        assert_eq!(receive(&mut vehicle_2_client).await, None);
+
+        drop((gcs_client, vehicle_1_client, vehicle_2_client));
+        router_task.await.unwrap();
+
+        assert_eq!(stats.endpoint(0).unwrap().unroutable, 1);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_filters_and_dedup() {
+        let mut router = Router::default().with_dedup_window(Duration::from_secs(1));
+        let (radio_1, mut radio_1_client) = duplex_endpoint("radio 1");
+        let (radio_2, mut radio_2_client) = duplex_endpoint("radio 2");
+        let (gcs, mut gcs_client) = duplex_endpoint("gcs");
+        router.add_endpoint(radio_1);
+        router.add_endpoint(radio_2.with_inbound_filter(|packet| *packet.system_id() != 42));
+        router.add_endpoint(gcs.with_outbound_filter(|packet| packet.message_id() != 76));
+        let stats = router.stats();
+        let router_task = tokio::spawn(router.run());
+
+        // The same packet through both radios is forwarded once
+        let packet = heartbeat(1, 1);
+        radio_1_client.send(packet.clone()).await.unwrap();
+        assert_eq!(receive(&mut gcs_client).await, Some(packet.clone()));
+        radio_2_client.send(packet.clone()).await.unwrap();
+        assert_eq!(receive(&mut gcs_client).await, None);
+
+        // Outside the window it is new again
+        tokio::time::sleep(Duration::from_secs(2)).await;
+        radio_2_client.send(packet.clone()).await.unwrap();
+        assert_eq!(receive(&mut gcs_client).await, Some(packet));
+
+        radio_2_client.send(heartbeat(42, 1)).await.unwrap();
+        assert_eq!(receive(&mut gcs_client).await, None);
+
+        radio_1_client
+            .send(command_long(1, BROADCAST, BROADCAST))
+            .await
+            .unwrap();
+        assert_eq!(receive(&mut gcs_client).await, None);
+
+        drop((radio_1_client, radio_2_client, gcs_client));
+        router_task.await.unwrap();
+
+        let endpoints = stats.endpoints();
+        assert_eq!(endpoints[1].duplicates, 1);
+        assert_eq!(endpoints[1].filtered_inbound, 1);
+        assert_eq!(endpoints[2].filtered_outbound, 1);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_route_expiry() {
+        let mut router = Router::default().with_route_timeout(Duration::from_secs(5));
+        let (gcs, mut gcs_client) = duplex_endpoint("gcs");
+        let (vehicle, mut vehicle_client) = duplex_endpoint("vehicle");
+        router.add_endpoint(gcs);
+        router.add_endpoint(vehicle);
+        let stats = router.stats();
+        let router_task = tokio::spawn(router.run());
+
+        vehicle_client.send(heartbeat(1, 1)).await.unwrap();
+        assert!(receive(&mut gcs_client).await.is_some());
+        assert_eq!(stats.routes()[&(1, 1)], vec![1]);
+
+        // The vehicle went silent
+        tokio::time::sleep(Duration::from_secs(6)).await;
+        assert!(stats.routes().is_empty());
+
+        gcs_client.send(command_long(255, 1, 1)).await.unwrap();
+        assert_eq!(receive(&mut vehicle_client).await, None);
+
+        drop((gcs_client, vehicle_client));
+        router_task.await.unwrap();
+
+        let gcs = stats.endpoint(0).unwrap();
+        assert_eq!(gcs.unroutable, 1);
+        assert_eq!(gcs.received, 1);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_decode_errors() {
+        let mut router = Router::default();
+        let (radio, mut radio_client) = duplex_endpoint("radio");
+        let (gcs, mut gcs_client) = duplex_endpoint("gcs");
+        router.add_endpoint(radio);
+        router.add_endpoint(gcs);
+        let stats = router.stats();
+        let router_task = tokio::spawn(router.run());
+
+        // A heartbeat with a corrupted CRC, then a valid one
+        let packet = heartbeat(1, 1);
+        let mut corrupted = packet.as_slice().to_vec();
+        *corrupted.last_mut().unwrap() ^= 0xFF;
+        radio_client.get_mut().write_all(&corrupted).await.unwrap();
+        radio_client.send(packet.clone()).await.unwrap();
+
+        assert_eq!(receive(&mut gcs_client).await, Some(packet));
+        assert_eq!(receive(&mut gcs_client).await, None);
+
+        drop((radio_client, gcs_client));
+        router_task.await.unwrap();
+
+        let radio = stats.endpoint(0).unwrap();
+        assert_eq!(radio.decode_errors, 1);
+        assert_eq!(radio.received, 1);
+    }
+}
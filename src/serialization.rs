@@ -0,0 +1,351 @@
+//! Serde support for packets.
+//!
+//! By default, [`Packet`], [`V1Packet`] and [`V2Packet`] are serialized as their raw bytes, the
+//! most compact representation. The [`structured`] module serializes them as [`PacketFields`]
+//! instead, through `#[serde(with = "mavlink_codec::serialization::structured")]`.
+//!
+//! Both representations are validated when deserialized, see [`Packet::try_from`].
+
+use bytes::{BufMut, Bytes, BytesMut};
+use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
+
+use crate::{
+    error::ValidationError,
+    v1::{V1Packet, V1_STX},
+    v2::{V2Packet, MAVLINK_IFLAG_SIGNED, V2_STX},
+    Packet,
+};
+
+/// The structured representation of a packet
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PacketFields {
+    pub version: u8,
+    /// Always zero for MAVLink V1
+    #[serde(default)]
+    pub incompatibility_flags: u8,
+    /// Always zero for MAVLink V1
+    #[serde(default)]
+    pub compatibility_flags: u8,
+    pub sequence: u8,
+    pub system_id: u8,
+    pub component_id: u8,
+    pub message_id: u32,
+    pub payload: Vec<u8>,
+    pub checksum: u16,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub signature: Option<Vec<u8>>,
+}
+
+impl From<&V1Packet> for PacketFields {
+    fn from(packet: &V1Packet) -> Self {
+        Self {
+            version: 1,
+            incompatibility_flags: 0,
+            compatibility_flags: 0,
+            sequence: *packet.sequence(),
+            system_id: *packet.system_id(),
+            component_id: *packet.component_id(),
+            message_id: *packet.message_id() as u32,
+            payload: packet.payload().to_vec(),
+            checksum: packet.checksum(),
+            signature: None,
+        }
+    }
+}
+
+impl From<&V2Packet> for PacketFields {
+    fn from(packet: &V2Packet) -> Self {
+        Self {
+            version: 2,
+            incompatibility_flags: *packet.incompatibility_flags(),
+            compatibility_flags: *packet.compatibility_flags(),
+            sequence: *packet.sequence(),
+            system_id: *packet.system_id(),
+            component_id: *packet.component_id(),
+            message_id: packet.message_id(),
+            payload: packet.payload().to_vec(),
+            checksum: packet.checksum(),
+            signature: packet.signature().map(<[u8]>::to_vec),
+        }
+    }
+}
+
+impl From<&Packet> for PacketFields {
+    fn from(packet: &Packet) -> Self {
+        match packet {
+            Packet::V1(v1_packet) => Self::from(v1_packet),
+            Packet::V2(v2_packet) => Self::from(v2_packet),
+        }
+    }
+}
+
+impl TryFrom<PacketFields> for Packet {
+    type Error = ValidationError;
+
+    fn try_from(fields: PacketFields) -> Result<Self, Self::Error> {
+        if fields.payload.len() > V2Packet::MAX_PAYLOAD_SIZE {
+            return Err(ValidationError::InvalidSize {
+                expected: V2Packet::MAX_PAYLOAD_SIZE,
+                actual: fields.payload.len(),
+            });
+        }
+
+        let mut buf = BytesMut::with_capacity(V2Packet::MAX_PACKET_SIZE);
+
+        match fields.version {
+            1 => {
+                let Ok(msgid) = u8::try_from(fields.message_id) else {
+                    return Err(ValidationError::InvalidMessageID {
+                        version: 1,
+                        msgid: fields.message_id,
+                    });
+                };
+                if fields.incompatibility_flags != 0 || fields.compatibility_flags != 0 {
+                    return Err(ValidationError::InvalidFlags {
+                        incompat_flags: fields.incompatibility_flags,
+                    });
+                }
+                if fields.signature.is_some() {
+                    return Err(ValidationError::SignatureMismatch);
+                }
+
+                buf.put_u8(V1_STX);
+                buf.put_u8(fields.payload.len() as u8);
+                buf.put_u8(fields.sequence);
+                buf.put_u8(fields.system_id);
+                buf.put_u8(fields.component_id);
+                buf.put_u8(msgid);
+                buf.put_slice(&fields.payload);
+                buf.put_u16_le(fields.checksum);
+            }
+            2 => {
+                if fields.message_id > 0x00FF_FFFF {
+                    return Err(ValidationError::InvalidMessageID {
+                        version: 2,
+                        msgid: fields.message_id,
+                    });
+                }
+                let signed = fields.incompatibility_flags & MAVLINK_IFLAG_SIGNED != 0;
+                if signed != fields.signature.is_some() {
+                    return Err(ValidationError::SignatureMismatch);
+                }
+
+                buf.put_u8(V2_STX);
+                buf.put_u8(fields.payload.len() as u8);
+                buf.put_u8(fields.incompatibility_flags);
+                buf.put_u8(fields.compatibility_flags);
+                buf.put_u8(fields.sequence);
+                buf.put_u8(fields.system_id);
+                buf.put_u8(fields.component_id);
+                buf.put_slice(&fields.message_id.to_le_bytes()[..3]);
+                buf.put_slice(&fields.payload);
+                buf.put_u16_le(fields.checksum);
+                if let Some(signature) = &fields.signature {
+                    if signature.len() != V2Packet::SIGNATURE_SIZE {
+                        return Err(ValidationError::InvalidSignatureSize {
+                            size: signature.len(),
+                        });
+                    }
+                    buf.put_slice(signature);
+                }
+            }
+            version => return Err(ValidationError::InvalidVersion { version }),
+        }
+
+        Packet::try_from(buf.freeze())
+    }
+}
+
+impl TryFrom<PacketFields> for V1Packet {
+    type Error = ValidationError;
+
+    fn try_from(fields: PacketFields) -> Result<Self, Self::Error> {
+        match Packet::try_from(fields)? {
+            Packet::V1(v1_packet) => Ok(v1_packet),
+            Packet::V2(_) => Err(ValidationError::InvalidVersion { version: 2 }),
+        }
+    }
+}
+
+impl TryFrom<PacketFields> for V2Packet {
+    type Error = ValidationError;
+
+    fn try_from(fields: PacketFields) -> Result<Self, Self::Error> {
+        match Packet::try_from(fields)? {
+            Packet::V1(_) => Err(ValidationError::InvalidVersion { version: 1 }),
+            Packet::V2(v2_packet) => Ok(v2_packet),
+        }
+    }
+}
+
+/// Serializes packets as [`PacketFields`], for `#[serde(with = "...")]`
+pub mod structured {
+    use super::*;
+
+    pub fn serialize<P, S>(packet: &P, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        for<'a> &'a P: Into<PacketFields>,
+        S: Serializer,
+    {
+        packet.into().serialize(serializer)
+    }
+
+    pub fn deserialize<'de, P, D>(deserializer: D) -> Result<P, D::Error>
+    where
+        P: TryFrom<PacketFields, Error = ValidationError>,
+        D: Deserializer<'de>,
+    {
+        let fields = PacketFields::deserialize(deserializer)?;
+
+        P::try_from(fields).map_err(de::Error::custom)
+    }
+}
+
+/// Accepts both byte strings and sequences of bytes, as self-describing formats like JSON have no
+/// byte string type
+struct BytesVisitor;
+
+impl<'de> de::Visitor<'de> for BytesVisitor {
+    type Value = Bytes;
+
+    fn expecting(&self, formatter: &mut std::fmt::Formatter) -> std::fmt::Result {
+        formatter.write_str("the bytes of a MAVLink packet")
+    }
+
+    fn visit_bytes<E: de::Error>(self, v: &[u8]) -> Result<Self::Value, E> {
+        Ok(Bytes::copy_from_slice(v))
+    }
+
+    fn visit_byte_buf<E: de::Error>(self, v: Vec<u8>) -> Result<Self::Value, E> {
+        Ok(Bytes::from(v))
+    }
+
+    fn visit_seq<A: de::SeqAccess<'de>>(self, mut seq: A) -> Result<Self::Value, A::Error> {
+        let mut buf = BytesMut::with_capacity(seq.size_hint().unwrap_or_default());
+        while let Some(byte) = seq.next_element()? {
+            buf.put_u8(byte);
+        }
+
+        Ok(buf.freeze())
+    }
+}
+
+macro_rules! impl_raw_serde {
+    ($($packet:ty),*) => {$(
+        impl Serialize for $packet {
+            fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+                serializer.serialize_bytes(self.as_slice())
+            }
+        }
+
+        impl<'de> Deserialize<'de> for $packet {
+            fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+                let bytes = deserializer.deserialize_bytes(BytesVisitor)?;
+
+                Self::try_from(bytes).map_err(de::Error::custom)
+            }
+        }
+    )*};
+}
+
+impl_raw_serde!(Packet, V1Packet, V2Packet);
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test_util::{create_v1_packet, create_v2_packet, header};
+
+    fn create_packets() -> (Packet, Packet) {
+        let header = header(1, 1, 42);
+
+        // Attitude messages
+        (create_v1_packet(header, 30), create_v2_packet(header, 30))
+    }
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    struct Structured {
+        #[serde(with = "structured")]
+        packet: Packet,
+    }
+
+    #[test]
+    fn test_raw_roundtrip() {
+        let (v1_packet, v2_packet) = create_packets();
+
+        for packet in [v1_packet, v2_packet] {
+            let json = serde_json::to_string(&packet).unwrap();
+            let deserialized: Packet = serde_json::from_str(&json).unwrap();
+
+            assert_eq!(deserialized, packet);
+        }
+    }
+
+    #[test]
+    fn test_structured_roundtrip() {
+        let (v1_packet, v2_packet) = create_packets();
+
+        for packet in [v1_packet, v2_packet] {
+            let value = serde_json::to_value(Structured {
+                packet: packet.clone(),
+            })
+            .unwrap();
+            assert_eq!(value["packet"]["sequence"], 42);
+            assert_eq!(value["packet"]["message_id"], 30);
+
+            let deserialized: Structured = serde_json::from_value(value).unwrap();
+            assert_eq!(deserialized.packet, packet);
+        }
+    }
+
+    #[test]
+    fn test_deserialize_invalid_crc() {
+        let (_, v2_packet) = create_packets();
+
+        let mut fields = PacketFields::from(&v2_packet);
+        fields.checksum = fields.checksum.wrapping_add(1);
+
+        assert!(matches!(
+            Packet::try_from(fields),
+            Err(ValidationError::InvalidCRC { .. })
+        ));
+
+        let mut bytes = v2_packet.as_slice().to_vec();
+        bytes[10] ^= 0xFF; // Corrupt the payload
+        let json = serde_json::to_string(&bytes).unwrap();
+
+        assert!(serde_json::from_str::<Packet>(&json).is_err());
+    }
+
+    #[test]
+    fn test_deserialize_wrong_version() {
+        let (v1_packet, _) = create_packets();
+
+        let json = serde_json::to_string(&v1_packet).unwrap();
+
+        assert!(serde_json::from_str::<V1Packet>(&json).is_ok());
+        assert!(serde_json::from_str::<V2Packet>(&json).is_err());
+    }
+
+    #[test]
+    fn test_deserialize_truncated() {
+        let (_, v2_packet) = create_packets();
+
+        let bytes = &v2_packet.as_slice()[..v2_packet.packet_size() - 1];
+        let json = serde_json::to_string(bytes).unwrap();
+
+        assert!(serde_json::from_str::<Packet>(&json).is_err());
+    }
+
+    #[test]
+    fn test_signature_mismatch() {
+        let (_, v2_packet) = create_packets();
+
+        let mut fields = PacketFields::from(&v2_packet);
+        fields.incompatibility_flags = MAVLINK_IFLAG_SIGNED;
+
+        assert_eq!(
+            Packet::try_from(fields),
+            Err(ValidationError::SignatureMismatch)
+        );
+    }
+}
@@ -0,0 +1,99 @@
+//! The target of packets, for routing.
+//!
+//! A target System ID or Component ID of [`BROADCAST`] addresses every system or component.
+//! Messages without target fields, like telemetry, are broadcast to everyone.
+
+use crate::{dialect::Dialect, Packet};
+
+/// The System ID and Component ID addressing everyone
+pub const BROADCAST: u8 = 0;
+
+impl Packet {
+    /// The target System ID and Component ID, or `None` if this message isn't targeted.
+    ///
+    /// Messages targeting a whole system have a [`BROADCAST`] target component. Target fields
+    /// truncated from MAVLink V2 payloads are zero, so [`BROADCAST`] as well.
+    pub fn target<D: Dialect>(&self, dialect: &D) -> Option<(u8, u8)> {
+        let offsets = dialect.target_offsets(self.message_id())?;
+
+        let payload = self.payload();
+        let read = |offset: u8| payload.get(offset as usize).copied().unwrap_or(BROADCAST);
+
+        Some((
+            read(offsets.system),
+            offsets.component.map_or(BROADCAST, read),
+        ))
+    }
+
+    /// Whether the packet is addressed to this system and component, either as its target or
+    /// through a broadcast
+    pub fn is_addressed_to<D: Dialect>(
+        &self,
+        system_id: u8,
+        component_id: u8,
+        dialect: &D,
+    ) -> bool {
+        self.target(dialect)
+            .is_none_or(|target| target_matches(target, system_id, component_id))
+    }
+}
+
+/// Whether a target addresses this system and component
+#[inline(always)]
+pub fn target_matches(target: (u8, u8), system_id: u8, component_id: u8) -> bool {
+    let (target_system, target_component) = target;
+
+    (target_system == BROADCAST || target_system == system_id)
+        && (target_component == BROADCAST || target_component == component_id)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{
+        dialect::ArduPilotMega,
+        test_util::{create_raw_packet, header},
+    };
+
+    #[test]
+    fn test_target() {
+        // COMMAND_LONG to the autopilot of system 1
+        let mut payload = [0u8; 33];
+        payload[30] = 1;
+        payload[31] = 1;
+        let command = create_raw_packet(header(255, 190, 0), 76, &payload);
+
+        assert_eq!(command.target(&ArduPilotMega), Some((1, 1)));
+        assert!(command.is_addressed_to(1, 1, &ArduPilotMega));
+        assert!(!command.is_addressed_to(1, 2, &ArduPilotMega));
+        assert!(!command.is_addressed_to(2, 1, &ArduPilotMega));
+
+        // SET_MODE targets every component of system 1
+        let set_mode = create_raw_packet(header(255, 190, 0), 11, &[0, 0, 0, 0, 1, 1]);
+        assert_eq!(set_mode.target(&ArduPilotMega), Some((1, BROADCAST)));
+        assert!(set_mode.is_addressed_to(1, 100, &ArduPilotMega));
+        assert!(!set_mode.is_addressed_to(2, 1, &ArduPilotMega));
+
+        // HEARTBEAT isn't targeted
+        let heartbeat = create_raw_packet(header(255, 190, 0), 0, &[0, 0, 0, 0, 6, 8, 0, 0, 3]);
+        assert_eq!(heartbeat.target(&ArduPilotMega), None);
+        assert!(heartbeat.is_addressed_to(42, 42, &ArduPilotMega));
+    }
+
+    #[test]
+    fn test_truncated_target_is_broadcast() {
+        // PARAM_REQUEST_LIST with both targets truncated to a single zero byte
+        let request = create_raw_packet(header(255, 190, 0), 21, &[0]);
+
+        assert_eq!(request.target(&ArduPilotMega), Some((BROADCAST, BROADCAST)));
+        assert!(request.is_addressed_to(1, 1, &ArduPilotMega));
+    }
+
+    #[test]
+    fn test_target_matches() {
+        assert!(target_matches((BROADCAST, BROADCAST), 1, 1));
+        assert!(target_matches((1, BROADCAST), 1, 42));
+        assert!(target_matches((BROADCAST, 1), 42, 1));
+        assert!(!target_matches((1, 1), 1, 2));
+    }
+}
@@ -0,0 +1,48 @@
+//! Packets for the unit tests
+
+use bytes::{BufMut, BytesMut};
+use mavlink::{
+    ardupilotmega::MavMessage, MAVLinkV1MessageRaw, MAVLinkV2MessageRaw, MavHeader, Message,
+};
+
+use crate::{codec::get_extra_crc, v2::V2_STX, Packet};
+
+pub(crate) const fn header(system_id: u8, component_id: u8, sequence: u8) -> MavHeader {
+    MavHeader {
+        system_id,
+        component_id,
+        sequence,
+    }
+}
+
+/// The default message of an ArduPilotMega Message ID, serialized by rust-mavlink
+pub(crate) fn create_v1_packet(header: MavHeader, message_id: u32) -> Packet {
+    let message_data = MavMessage::default_message_from_id(message_id).unwrap();
+    let mut raw_v1_message = MAVLinkV1MessageRaw::new();
+    raw_v1_message.serialize_message(header, &message_data);
+
+    Packet::from(raw_v1_message)
+}
+
+/// The default message of an ArduPilotMega Message ID, serialized by rust-mavlink
+pub(crate) fn create_v2_packet(header: MavHeader, message_id: u32) -> Packet {
+    let message_data = MavMessage::default_message_from_id(message_id).unwrap();
+    let mut raw_v2_message = MAVLinkV2MessageRaw::new();
+    raw_v2_message.serialize_message(header, &message_data);
+
+    Packet::from(raw_v2_message)
+}
+
+/// A MAVLink 2 packet with the payload as is, for payloads rust-mavlink can't build
+pub(crate) fn create_raw_packet(header: MavHeader, message_id: u32, payload: &[u8]) -> Packet {
+    let mut buf = BytesMut::new();
+    buf.put_u8(V2_STX);
+    buf.put_u8(payload.len() as u8);
+    buf.put_slice(&[0, 0, header.sequence, header.system_id, header.component_id]); // flags
+    buf.put_slice(&message_id.to_le_bytes()[..3]);
+    buf.put_slice(payload);
+    let crc = mavlink::calculate_crc(&buf[1..], get_extra_crc(message_id).unwrap());
+    buf.put_u16_le(crc);
+
+    Packet::try_from(buf.freeze()).unwrap()
+}
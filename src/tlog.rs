@@ -0,0 +1,442 @@
+//! Telemetry log (`.tlog`) support.
+//!
+//! A tlog, as written by QGroundControl and MAVProxy, is a plain concatenation of records,
+//! each one being an 8-byte big-endian UNIX timestamp in microseconds followed by the raw
+//! MAVLink packet.
+
+use std::{
+    io::{self, Read, Seek, Write},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+#[cfg(feature = "async")]
+use std::{
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+use bytes::{Buf, BufMut, BytesMut};
+#[cfg(feature = "async")]
+use futures::Stream;
+use log::trace;
+#[cfg(feature = "async")]
+use tokio::io::AsyncRead;
+use tokio_util::codec::{Decoder, Encoder};
+
+use crate::{
+    codec::MavlinkCodec,
+    error::DecoderError,
+    v1::{self, V1Packet, V1_STX},
+    v2::{self, V2Packet, V2_STX},
+    Packet,
+};
+
+pub const TIMESTAMP_SIZE: usize = std::mem::size_of::<u64>();
+
+const READ_CHUNK_SIZE: usize = 4096;
+
+/// A tlog record codec, decoding and encoding `(SystemTime, Packet)` pairs.
+///
+/// Each candidate record is validated by a fresh clone of the inner codec, a [`MavlinkCodec`] by
+/// default, and whenever a record is not valid, the decoder discards a single byte and tries
+/// again, the same way [`MavlinkCodec`] resyncs on the STX byte.
+#[derive(Debug, Default)]
+pub struct TlogCodec<D = MavlinkCodec<true, true, false, false, false, false>> {
+    codec: D,
+}
+
+impl<D> TlogCodec<D> {
+    /// Validates the records with `codec`, to select the accepted versions or skip the CRC
+    /// validation
+    pub fn with_codec(codec: D) -> Self {
+        Self { codec }
+    }
+}
+
+impl<D> Decoder for TlogCodec<D>
+where
+    D: Decoder<Item = Result<Packet, DecoderError>, Error = io::Error> + Clone,
+{
+    type Item = (SystemTime, Packet);
+    type Error = io::Error;
+
+    fn decode(&mut self, buf: &mut BytesMut) -> Result<Option<Self::Item>, Self::Error> {
+        loop {
+            if buf.len() < TIMESTAMP_SIZE + V1Packet::STX_SIZE {
+                return Ok(None);
+            }
+
+            let record = &buf[TIMESTAMP_SIZE..];
+            let packet_size = match record[0] {
+                V1_STX if record.len() >= V1Packet::STX_SIZE + V1Packet::HEADER_SIZE => {
+                    v1::packet_size(&record)
+                }
+                V2_STX if record.len() >= V2Packet::STX_SIZE + V2Packet::HEADER_SIZE => {
+                    v2::packet_size(&record)
+                }
+                V1_STX | V2_STX => return Ok(None),
+                _ => {
+                    trace!("Invalid tlog record STX byte: {}", record[0]);
+                    buf.advance(1);
+                    continue;
+                }
+            };
+
+            if record.len() < packet_size {
+                return Ok(None);
+            }
+
+            let mut frame = BytesMut::from(&record[..packet_size]);
+            match self.codec.clone().decode(&mut frame)? {
+                // Codecs skipping the CRC validation only consume the STX of the packet
+                Some(Ok(packet)) if packet.packet_size() == packet_size => {
+                    let timestamp = timestamp_from_micros(buf.get_u64());
+                    buf.advance(packet_size);
+
+                    return Ok(Some((timestamp, packet)));
+                }
+                result => {
+                    trace!("Invalid tlog record: {result:?}. Resyncing...");
+                    buf.advance(1);
+                }
+            }
+        }
+    }
+
+    fn decode_eof(&mut self, buf: &mut BytesMut) -> Result<Option<Self::Item>, Self::Error> {
+        // A truncated record might be hiding valid ones, so keep resyncing until the buffer is drained
+        loop {
+            match self.decode(buf)? {
+                Some(record) => return Ok(Some(record)),
+                None if buf.is_empty() => return Ok(None),
+                None => buf.advance(1),
+            }
+        }
+    }
+}
+
+impl<D: Encoder<Packet, Error = io::Error>> Encoder<(SystemTime, Packet)> for TlogCodec<D> {
+    type Error = io::Error;
+
+    fn encode(
+        &mut self,
+        (timestamp, packet): (SystemTime, Packet),
+        buf: &mut BytesMut,
+    ) -> Result<(), Self::Error> {
+        buf.reserve(TIMESTAMP_SIZE + packet.packet_size());
+        buf.put_u64(timestamp_to_micros(timestamp));
+
+        self.codec.encode(packet, buf)
+    }
+}
+
+/// Reads `(SystemTime, Packet)` records from a tlog.
+///
+/// It is an [`Iterator`] when built from a [`std::io::Read`], and, with the `async` feature, a
+/// `Stream` when built from a [`tokio::io::AsyncRead`].
+#[derive(Debug)]
+pub struct TlogReader<R, D = MavlinkCodec<true, true, false, false, false, false>> {
+    reader: R,
+    codec: TlogCodec<D>,
+    buffer: BytesMut,
+    eof: bool,
+}
+
+impl<R> TlogReader<R> {
+    pub fn new(reader: R) -> Self {
+        Self::with_codec(reader, MavlinkCodec::default())
+    }
+}
+
+impl<R, D> TlogReader<R, D> {
+    /// Validates the records with `codec`, see [`TlogCodec::with_codec`]
+    pub fn with_codec(reader: R, codec: D) -> Self {
+        Self {
+            reader,
+            codec: TlogCodec::with_codec(codec),
+            buffer: BytesMut::with_capacity(READ_CHUNK_SIZE),
+            eof: false,
+        }
+    }
+
+    pub fn get_ref(&self) -> &R {
+        &self.reader
+    }
+
+    pub fn into_inner(self) -> R {
+        self.reader
+    }
+}
+
+impl<R: Seek, D> TlogReader<R, D> {
+    /// Goes back to the start of the underlying reader, where the first record is expected
+    pub fn rewind(&mut self) -> io::Result<()> {
+        self.reader.rewind()?;
+        self.buffer.clear();
+        self.eof = false;
+
+        Ok(())
+    }
+}
+
+impl<R, D> Iterator for TlogReader<R, D>
+where
+    R: Read,
+    D: Decoder<Item = Result<Packet, DecoderError>, Error = io::Error> + Clone,
+{
+    type Item = io::Result<(SystemTime, Packet)>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let result = if self.eof {
+                self.codec.decode_eof(&mut self.buffer)
+            } else {
+                self.codec.decode(&mut self.buffer)
+            };
+
+            match result {
+                Ok(Some(record)) => return Some(Ok(record)),
+                Ok(None) if self.eof => return None,
+                Ok(None) => (),
+                Err(error) => return Some(Err(error)),
+            }
+
+            let mut chunk = [0u8; READ_CHUNK_SIZE];
+            match self.reader.read(&mut chunk) {
+                Ok(0) => self.eof = true,
+                Ok(n) => self.buffer.extend_from_slice(&chunk[..n]),
+                Err(error) if error.kind() == io::ErrorKind::Interrupted => (),
+                Err(error) => return Some(Err(error)),
+            }
+        }
+    }
+}
+
+#[cfg(feature = "async")]
+impl<R, D> Stream for TlogReader<R, D>
+where
+    R: AsyncRead + Unpin,
+    D: Decoder<Item = Result<Packet, DecoderError>, Error = io::Error> + Clone + Unpin,
+{
+    type Item = io::Result<(SystemTime, Packet)>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = self.get_mut();
+
+        loop {
+            let result = if this.eof {
+                this.codec.decode_eof(&mut this.buffer)
+            } else {
+                this.codec.decode(&mut this.buffer)
+            };
+
+            match result {
+                Ok(Some(record)) => return Poll::Ready(Some(Ok(record))),
+                Ok(None) if this.eof => return Poll::Ready(None),
+                Ok(None) => (),
+                Err(error) => return Poll::Ready(Some(Err(error))),
+            }
+
+            this.buffer.reserve(READ_CHUNK_SIZE);
+            match tokio_util::io::poll_read_buf(Pin::new(&mut this.reader), cx, &mut this.buffer) {
+                Poll::Ready(Ok(0)) => this.eof = true,
+                Poll::Ready(Ok(_)) => (),
+                Poll::Ready(Err(error)) => return Poll::Ready(Some(Err(error))),
+                Poll::Pending => return Poll::Pending,
+            }
+        }
+    }
+}
+
+/// Writes `(SystemTime, Packet)` records into a tlog.
+///
+/// For asynchronous writers, use a [`tokio_util::codec::FramedWrite`] with a [`TlogCodec`].
+#[derive(Debug)]
+pub struct TlogWriter<W: Write> {
+    writer: W,
+    codec: TlogCodec,
+    buffer: BytesMut,
+}
+
+impl<W: Write> TlogWriter<W> {
+    pub fn new(writer: W) -> Self {
+        Self {
+            writer,
+            codec: TlogCodec::default(),
+            buffer: BytesMut::with_capacity(TIMESTAMP_SIZE + V2Packet::MAX_PACKET_SIZE),
+        }
+    }
+
+    pub fn write(&mut self, timestamp: SystemTime, packet: &Packet) -> io::Result<()> {
+        self.buffer.clear();
+        self.codec
+            .encode((timestamp, packet.clone()), &mut self.buffer)?;
+
+        self.writer.write_all(&self.buffer)
+    }
+
+    /// Writes the packet timestamped with the current system time
+    pub fn write_now(&mut self, packet: &Packet) -> io::Result<()> {
+        self.write(SystemTime::now(), packet)
+    }
+
+    pub fn flush(&mut self) -> io::Result<()> {
+        self.writer.flush()
+    }
+
+    pub fn get_ref(&self) -> &W {
+        &self.writer
+    }
+
+    pub fn into_inner(self) -> W {
+        self.writer
+    }
+}
+
+#[inline(always)]
+fn timestamp_from_micros(micros: u64) -> SystemTime {
+    UNIX_EPOCH + Duration::from_micros(micros)
+}
+
+#[inline(always)]
+fn timestamp_to_micros(timestamp: SystemTime) -> u64 {
+    timestamp
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_micros() as u64
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test_util::{create_v1_packet, create_v2_packet, header};
+
+    fn create_packets() -> Vec<(SystemTime, Packet)> {
+        (0..10u8)
+            .map(|sequence| {
+                // Heartbeat messages
+                let packet = if sequence % 2 == 0 {
+                    create_v1_packet(header(1, 1, sequence), 0)
+                } else {
+                    create_v2_packet(header(1, 1, sequence), 0)
+                };
+
+                let timestamp = timestamp_from_micros(1_700_000_000_000_000 + sequence as u64);
+
+                (timestamp, packet)
+            })
+            .collect()
+    }
+
+    fn write_tlog(records: &[(SystemTime, Packet)]) -> Vec<u8> {
+        let mut writer = TlogWriter::new(Vec::new());
+        for (timestamp, packet) in records {
+            writer.write(*timestamp, packet).unwrap();
+        }
+
+        writer.into_inner()
+    }
+
+    #[test]
+    fn test_write_read() {
+        let records = create_packets();
+        let tlog = write_tlog(&records);
+
+        let decoded = TlogReader::new(&tlog[..])
+            .collect::<io::Result<Vec<_>>>()
+            .unwrap();
+
+        assert_eq!(decoded, records);
+    }
+
+    #[test]
+    fn test_timestamp_encoding() {
+        let records = create_packets();
+        let tlog = write_tlog(&records[..1]);
+
+        assert_eq!(
+            &tlog[..TIMESTAMP_SIZE],
+            &1_700_000_000_000_000u64.to_be_bytes()
+        );
+        assert_eq!(&tlog[TIMESTAMP_SIZE..], records[0].1.as_slice());
+    }
+
+    #[test]
+    fn test_resync_on_corrupted_regions() {
+        let records = create_packets();
+
+        let mut tlog = Vec::new();
+        tlog.extend_from_slice(&[V2_STX, 0, 1, 2, V1_STX, 3]); // Trash in the beginning
+        for (idx, record) in records.iter().enumerate() {
+            let mut bytes = write_tlog(std::slice::from_ref(record));
+
+            if idx == 3 {
+                // Corrupt the payload, invalidating the CRC
+                bytes[TIMESTAMP_SIZE + 12] ^= 0xFF;
+            }
+            if idx == 6 {
+                // Truncate the record
+                bytes.truncate(bytes.len() - 3);
+            }
+
+            tlog.extend_from_slice(&bytes);
+        }
+
+        let decoded = TlogReader::new(&tlog[..])
+            .collect::<io::Result<Vec<_>>>()
+            .unwrap();
+
+        let expected = records
+            .into_iter()
+            .enumerate()
+            .filter(|(idx, _)| ![3, 6].contains(idx))
+            .map(|(_, record)| record)
+            .collect::<Vec<_>>();
+
+        assert_eq!(decoded, expected);
+    }
+
+    #[test]
+    fn test_with_codec() {
+        let records = create_packets();
+        let mut tlog = write_tlog(&records[..1]);
+        tlog[TIMESTAMP_SIZE + 12] ^= 0xFF; // Invalidate the CRC
+
+        assert_eq!(TlogReader::new(&tlog[..]).count(), 0);
+
+        let codec = MavlinkCodec::<true, true, false, false, true, false>::default();
+        let decoded = TlogReader::with_codec(&tlog[..], codec)
+            .collect::<io::Result<Vec<_>>>()
+            .unwrap();
+
+        assert_eq!(decoded.len(), 1);
+        assert_eq!(decoded[0].1.as_slice(), &tlog[TIMESTAMP_SIZE..]);
+    }
+
+    #[test]
+    fn test_truncated_tail() {
+        let records = create_packets();
+        let mut tlog = write_tlog(&records);
+        tlog.truncate(tlog.len() - 1);
+
+        let decoded = TlogReader::new(&tlog[..])
+            .collect::<io::Result<Vec<_>>>()
+            .unwrap();
+
+        assert_eq!(decoded, records[..records.len() - 1]);
+    }
+
+    #[cfg(feature = "async")]
+    #[tokio::test]
+    async fn test_stream() {
+        use futures::StreamExt;
+
+        let records = create_packets();
+        let tlog = write_tlog(&records);
+
+        let decoded = StreamExt::map(TlogReader::new(&tlog[..]), Result::unwrap)
+            .collect::<Vec<_>>()
+            .await;
+
+        assert_eq!(decoded, records);
+    }
+}
@@ -1,5 +1,9 @@
+use std::borrow::Cow;
+
 use bytes::Bytes;
 
+use crate::dialect::Dialect;
+
 pub const V2_STX: u8 = 0xFD;
 pub const MAVLINK_IFLAG_SIGNED: u8 = 0x01;
 pub const MAVLINK_SUPPORTED_IFLAGS: u8 = MAVLINK_IFLAG_SIGNED;
@@ -54,6 +58,23 @@ impl V2Packet {
         payload(&self.buffer)
     }
 
+    /// The payload zero-extended to the full length of its message, as V2 senders trim the
+    /// trailing zeroes.
+    ///
+    /// Only allocates when the payload was truncated. Unknown messages are returned as they are.
+    pub fn payload_extended<D: Dialect>(&self, dialect: &D) -> Cow<'_, [u8]> {
+        let payload = self.payload();
+
+        match dialect.payload_length(self.message_id()) {
+            Some(limits) if payload.len() < limits.max as usize => {
+                let mut extended = payload.to_vec();
+                extended.resize(limits.max as usize, 0);
+                Cow::Owned(extended)
+            }
+            _ => Cow::Borrowed(payload),
+        }
+    }
+
     #[inline(always)]
     pub fn checksum(&self) -> u16 {
         checksum(&self.buffer)
@@ -120,6 +141,17 @@ impl V2Packet {
     }
 }
 
+/// Removes the trailing zeroes of a payload to be sent over MAVLink V2, keeping at least one byte
+#[inline(always)]
+pub fn trim_payload(payload: &[u8]) -> &[u8] {
+    let len = payload
+        .iter()
+        .rposition(|byte| *byte != 0)
+        .map_or(1, |last_non_zero| last_non_zero + 1);
+
+    &payload[..len.min(payload.len())]
+}
+
 #[inline(always)]
 pub(crate) fn header<T: AsRef<[u8]>>(buf: &T) -> &[u8] {
     let header_start = V2Packet::STX_SIZE;
@@ -175,6 +207,7 @@ pub(crate) fn packet_size<T: AsRef<[u8]>>(buf: &T) -> usize {
     let header = V2Packet::HEADER_SIZE;
     let payload = *len(buf) as usize;
     let checksum = V2Packet::CHECKSUM_SIZE;
+    #[allow(clippy::obfuscated_if_else)]
     let signature = has_signature(buf)
         .then_some(V2Packet::SIGNATURE_SIZE)
         .unwrap_or_default();
@@ -337,6 +370,30 @@ mod test {
         assert_eq!(packet_size(&COMMAND_LONG), (1 + 9) + 30 + 2);
     }
 
+    #[test]
+    fn test_trim_payload() {
+        assert_eq!(trim_payload(&[1, 0, 2, 0, 0]), &[1, 0, 2]);
+        assert_eq!(trim_payload(&[1, 2]), &[1, 2]);
+        assert_eq!(trim_payload(&[0, 0, 0]), &[0]);
+        assert_eq!(trim_payload(&[]), &[] as &[u8]);
+    }
+
+    #[test]
+    fn test_payload_extended() {
+        use crate::dialect::ArduPilotMega;
+
+        // The payload of COMMAND_LONG is 33 bytes long, but was sent truncated to 30
+        let packet = V2Packet::new(Bytes::from_static(COMMAND_LONG));
+
+        let extended = packet.payload_extended(&ArduPilotMega);
+        assert!(matches!(extended, Cow::Owned(_)));
+        assert_eq!(extended.len(), 33);
+        assert_eq!(&extended[..30], packet.payload());
+        assert_eq!(&extended[30..], &[0, 0, 0]);
+
+        assert_eq!(trim_payload(&extended), packet.payload());
+    }
+
     #[test]
     fn test_v2packet_from_raw_v2_message() {
         use mavlink::{ardupilotmega::MavMessage, MAVLinkV2MessageRaw, MavHeader, Message};